@@ -0,0 +1,84 @@
+//! Labels drawn onto extracted tiles to help identify them while reverse engineering.
+//! Each label holds an entry's index and its grid coordinate, drawn with a tiny built-in bitmap font.
+
+use image::{Rgba, RgbaImage};
+use crate::GridEntry;
+
+/// The width of a glyph, in font pixels.
+const GLYPH_WIDTH: u32 = 3;
+/// The height of a glyph, in font pixels.
+const GLYPH_HEIGHT: u32 = 5;
+
+/// Returns the rows of a glyph from the top, with the leftmost pixel in the highest of the 3 bits.
+/// Characters without a glyph are drawn blank.
+fn glyph(character: char) -> [u8; GLYPH_HEIGHT as usize] {
+    match character {
+        '0' => [0b111, 0b101, 0b101, 0b101, 0b111],
+        '1' => [0b010, 0b110, 0b010, 0b010, 0b111],
+        '2' => [0b111, 0b001, 0b111, 0b100, 0b111],
+        '3' => [0b111, 0b001, 0b111, 0b001, 0b111],
+        '4' => [0b101, 0b101, 0b111, 0b001, 0b001],
+        '5' => [0b111, 0b100, 0b111, 0b001, 0b111],
+        '6' => [0b111, 0b100, 0b111, 0b101, 0b111],
+        '7' => [0b111, 0b001, 0b001, 0b001, 0b001],
+        '8' => [0b111, 0b101, 0b111, 0b101, 0b111],
+        '9' => [0b111, 0b101, 0b111, 0b001, 0b111],
+        '-' => [0b000, 0b000, 0b111, 0b000, 0b000],
+        ',' => [0b000, 0b000, 0b000, 0b010, 0b100],
+        _ => [0; GLYPH_HEIGHT as usize],
+    }
+}
+
+/// Draws a line of text in white on a black background, clipped to the image.
+///
+/// ### Parameters
+/// - `image`: The image to draw on.
+/// - `text`: The text to draw.
+/// - `x`: The left edge of the background, in pixels.
+/// - `y`: The top edge of the background, in pixels.
+/// - `scale`: The size of each font pixel, in pixels.
+fn draw_text(image: &mut RgbaImage, text: &str, x: u32, y: u32, scale: u32) {
+    let (width, height) = image.dimensions();
+    let mut fill = |left: u32, top: u32, color: Rgba<u8>| {
+        for py in top..(top + scale).min(height) {
+            for px in left..(left + scale).min(width) {
+                image.put_pixel(px, py, color);
+            }
+        }
+    };
+
+    // one font pixel of background around the text, and between characters
+    let columns = text.chars().count() as u32 * (GLYPH_WIDTH + 1) + 1;
+    for row in 0..GLYPH_HEIGHT + 2 {
+        for column in 0..columns {
+            fill(x + column * scale, y + row * scale, Rgba([0, 0, 0, 0xFF]));
+        }
+    }
+
+    for (i, character) in text.chars().enumerate() {
+        let left = x + (1 + i as u32 * (GLYPH_WIDTH + 1)) * scale;
+        for (row, bits) in glyph(character).iter().enumerate() {
+            for column in 0..GLYPH_WIDTH {
+                if bits & (1 << (GLYPH_WIDTH - 1 - column)) != 0 {
+                    fill(left + column * scale, y + (1 + row as u32) * scale, Rgba([0xFF; 4]));
+                }
+            }
+        }
+    }
+}
+
+/// Draws an entry's index and grid coordinate onto the top left of its tile,
+/// the index on the first line and `x,y` on the second.
+/// The font is scaled with the tile, so labels stay readable on large tiles.
+///
+/// ### Parameters
+/// - `tile`: The tile to draw on.
+/// - `entry_index`: The index of the entry the tile belongs to.
+/// - `entry`: The entry the tile belongs to.
+pub fn annotate_tile(tile: &mut RgbaImage, entry_index: usize, entry: &GridEntry) {
+    let scale = (tile.width() / 64).max(1);
+    let line_height = (GLYPH_HEIGHT + 2) * scale;
+
+    draw_text(tile, &entry_index.to_string(), 0, 0, scale);
+    draw_text(tile, &format!("{},{}", entry.grid_x, entry.grid_y), 0, line_height, scale);
+}
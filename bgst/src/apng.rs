@@ -0,0 +1,89 @@
+//! Export of every entry's main image and mask together as one animated PNG.
+//! The main image is the first frame and the mask the second, so viewers that
+//! understand APNG can flip between them and others show the main image.
+
+use anyhow::{Result, bail};
+use std::fs;
+use image::RgbaImage;
+use crate::{
+    BgstError, DanglingReference, ExtractOptions, decode_block, output_image, select_entries, write_png
+};
+
+/// How long each frame is shown, in seconds.
+const FRAME_DELAY_SECONDS: u16 = 1;
+
+/// Encodes a main image and its mask as a looping two-frame APNG.
+///
+/// ### Parameters
+/// - `main`: The main image, shown first.
+/// - `mask`: The mask, which must be the same size.
+///
+/// ### Returns
+/// - the encoded APNG
+pub fn encode_pair_apng(main: &RgbaImage, mask: &RgbaImage) -> Result<Vec<u8>> {
+    if main.dimensions() != mask.dimensions() {
+        bail!(BgstError::Decode("the image sizes are not equal!".to_string()));
+    }
+
+    let mut buffer = Vec::new();
+    let mut encoder = png::Encoder::new(&mut buffer, main.width(), main.height());
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.set_animated(2, 0)?;
+    encoder.set_frame_delay(FRAME_DELAY_SECONDS, 1)?;
+
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(main.as_raw())?;
+    writer.write_image_data(mask.as_raw())?;
+    writer.finish()?;
+
+    Ok(buffer)
+}
+
+/// Decodes every selected entry and writes its main image and mask as an APNG
+/// named after the entry, such as `entry12.png`. Entries without a mask are written
+/// as a plain PNG of their main image, and entries without a main image are skipped.
+///
+/// ### Parameters
+/// - `bgst_contents`: The raw data of a bgst3 file.
+/// - `options`: Options that control which images are decoded and what is written.
+/// - `folder_name`: The existing folder to write the images into.
+///
+/// ### Returns
+/// - the image indices that were skipped because they were out of range
+pub fn write_entry_apngs(
+    bgst_contents: &[u8],
+    options: &ExtractOptions,
+    folder_name: &str
+) -> Result<Vec<DanglingReference>> {
+    let (header, selected, dangling_references) = select_entries(bgst_contents, &options.decode)?;
+
+    let decode = |entry_index: usize, image_index: i16, is_mask: bool| -> Result<Option<RgbaImage>> {
+        if !header.has_image(image_index) {
+            return Ok(None);
+        }
+
+        let format = options.decode.overrides.format_for(image_index as usize, is_mask);
+        let decoded = decode_block(&header, bgst_contents, entry_index, image_index, format, is_mask)?;
+        Ok(RgbaImage::from_raw(header.image_width, header.image_height, decoded))
+    };
+
+    for (entry_index, entry) in &selected {
+        let name = format!("entry{}", entry_index);
+        let Some(main) = decode(*entry_index, entry.main_image_index, false)? else {
+            continue;
+        };
+
+        match decode(*entry_index, entry.mask_image_index, true)? {
+            Some(mask) => {
+                let apng = encode_pair_apng(&output_image(&main, false, options), &output_image(&mask, true, options))?;
+                let filename = format!("{}/{}.png", folder_name, name);
+                fs::write(&filename, apng)
+                    .map_err(|err| BgstError::Write(format!("failed to write {}: {}", filename, err)))?;
+            }
+            None => write_png(folder_name, &name, &output_image(&main, false, options), options, None)?,
+        }
+    }
+
+    Ok(dangling_references)
+}
@@ -0,0 +1,189 @@
+//! An atlas of every distinct placed tile, shared by the exporters that reference tiles
+//! by their position in one image, such as Tiled maps and the JSON hash format
+//! that Phaser and PixiJS load.
+
+use anyhow::{Result, bail};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use image::*;
+use serde_json::{Map, Value, json};
+use crate::{BgstError, ImageList, SceneIndex, masked_tiles};
+
+/// A tile placed on a cell of a scene.
+///
+/// ### Fields
+/// - `scene`: The scene the tile is placed on.
+/// - `grid_x`: The column of the cell.
+/// - `grid_y`: The row of the cell.
+/// - `tile`: The index of the tile in the atlas.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AtlasFrame {
+    pub scene: SceneIndex,
+    pub grid_x: u32,
+    pub grid_y: u32,
+    pub tile: u32,
+}
+
+impl AtlasFrame {
+    /// Returns the name of the frame, made of its scene and cell, such as `Game_3_2`.
+    pub fn name(&self) -> String {
+        format!("{}_{}_{}", self.scene.name(), self.grid_x, self.grid_y)
+    }
+}
+
+/// Every distinct placed tile drawn into one image, and where each one is placed.
+///
+/// ### Fields
+/// - `image`: The atlas, with every distinct tile in rows from the top left.
+/// - `columns`: The number of tiles in each row of the atlas.
+/// - `tile_count`: The number of tiles in the atlas.
+/// - `tile_width`: The width of every tile, in pixels.
+/// - `tile_height`: The height of every tile, in pixels.
+/// - `frames`: Every placed tile, in entry order. An entry on a cell that another
+///   entry is already on is included too, even though it replaces the earlier one.
+pub struct Atlas {
+    pub image: RgbaImage,
+    pub columns: u32,
+    pub tile_count: u32,
+    pub tile_width: u32,
+    pub tile_height: u32,
+    pub frames: Vec<AtlasFrame>,
+}
+
+impl Atlas {
+    /// Returns the pixel position of the top left of a tile in the atlas.
+    ///
+    /// ### Parameters
+    /// - `tile`: The index of the tile.
+    pub fn tile_position(&self, tile: u32) -> (u32, u32) {
+        ((tile % self.columns) * self.tile_width, (tile / self.columns) * self.tile_height)
+    }
+}
+
+/// Draws every distinct tile placed on a known scene into an atlas, with masks applied.
+/// Entries that use the same main and mask image share one tile.
+///
+/// ### Parameters
+/// - `images`: The decoded images.
+///
+/// ### Returns
+/// - the atlas
+pub fn build_atlas(images: &ImageList) -> Result<Atlas> {
+    let mut tiles: Vec<RgbaImage> = Vec::new();
+    let mut tile_ids: HashMap<(i16, i16), u32> = HashMap::new();
+    let mut frames = Vec::new();
+
+    for tile in masked_tiles(images) {
+        let (tile, entry) = tile?;
+        let Some(scene) = SceneIndex::from_index(entry.scene_index) else {
+            continue;
+        };
+
+        if entry.grid_x < 0 || entry.grid_y < 0 {
+            continue;
+        }
+
+        let key = (entry.main_image_index, entry.mask_image_index);
+        let id = *tile_ids.entry(key).or_insert_with(|| {
+            tiles.push(tile);
+            tiles.len() as u32 - 1
+        });
+
+        frames.push(AtlasFrame {
+            scene,
+            grid_x: entry.grid_x as u32,
+            grid_y: entry.grid_y as u32,
+            tile: id,
+        });
+    }
+
+    if frames.is_empty() {
+        bail!("no scenes have tiles placed on them");
+    }
+
+    let tile_count = tiles.len() as u32;
+    let columns = (tiles.len() as f64).sqrt().ceil() as u32;
+    let rows = tile_count.div_ceil(columns);
+
+    let (width, height) = images.canvas_size(columns, rows)?;
+    let mut atlas = Atlas {
+        image: RgbaImage::new(width, height),
+        columns,
+        tile_count,
+        tile_width: images.image_width,
+        tile_height: images.image_height,
+        frames,
+    };
+
+    for (id, tile) in tiles.iter().enumerate() {
+        let (x, y) = atlas.tile_position(id as u32);
+        imageops::overlay(&mut atlas.image, tile, x as i64, y as i64);
+    }
+
+    Ok(atlas)
+}
+
+/// Describes an atlas in the JSON hash format that Phaser and PixiJS load,
+/// with a frame for every placed tile named by `AtlasFrame::name`.
+/// Where several entries are on one cell, the last one's frame is kept, as it's the one drawn on top.
+///
+/// ### Parameters
+/// - `atlas`: The atlas.
+/// - `image_name`: The path of the atlas image as written into the JSON, relative to it.
+///
+/// ### Returns
+/// - the JSON text
+pub fn atlas_json_hash(atlas: &Atlas, image_name: &str) -> Result<String> {
+    let (w, h) = (atlas.tile_width, atlas.tile_height);
+
+    let mut frames = Map::new();
+    for frame in &atlas.frames {
+        let (x, y) = atlas.tile_position(frame.tile);
+        frames.insert(frame.name(), json!({
+            "frame": { "x": x, "y": y, "w": w, "h": h },
+            "rotated": false,
+            "trimmed": false,
+            "spriteSourceSize": { "x": 0, "y": 0, "w": w, "h": h },
+            "sourceSize": { "w": w, "h": h },
+        }));
+    }
+
+    let document = json!({
+        "frames": Value::Object(frames),
+        "meta": {
+            "app": "bgst",
+            "image": image_name,
+            "format": "RGBA8888",
+            "size": { "w": atlas.image.width(), "h": atlas.image.height() },
+            "scale": "1",
+        },
+    });
+
+    Ok(serde_json::to_string_pretty(&document)?)
+}
+
+/// Writes an atlas of a file in the JSON hash format, with the atlas image written
+/// next to it under the same name with a `.png` extension.
+///
+/// ### Parameters
+/// - `images`: The decoded images.
+/// - `output_filename`: The path of the `.json` file to create.
+pub fn export_atlas_json(images: &ImageList, output_filename: &str) -> Result<()> {
+    let output_path = Path::new(output_filename);
+    let image_path = output_path.with_extension("png");
+    let image_name = image_path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+
+    let atlas = build_atlas(images)?;
+    let json = atlas_json_hash(&atlas, &image_name)?;
+
+    atlas.image.save_with_format(&image_path, ImageFormat::Png)
+        .map_err(|err| BgstError::Write(format!("failed to write {}: {}", image_path.display(), err)))?;
+    fs::write(output_path, json)
+        .map_err(|err| BgstError::Write(format!("failed to write {}: {}", output_filename, err)))?;
+
+    Ok(())
+}
@@ -0,0 +1,101 @@
+//! A pure-Rust CMPR decoder, enabled with the `builtin-cmpr` feature.
+//! When enabled, main images are decoded with it instead of `gctex`.
+//! It only replaces `gctex` for CMPR; see `texture` for what is decoded
+//! when the `gctex` feature is disabled as well.
+//! It follows the console's blending, so its output matches `gctex` exactly.
+
+/// Expands a 5-bit channel to 8 bits.
+fn expand_5(value: u16) -> u8 {
+    let value = value as u8;
+    (value << 3) | (value >> 2)
+}
+
+/// Expands a 6-bit channel to 8 bits.
+fn expand_6(value: u16) -> u8 {
+    let value = value as u8;
+    (value << 2) | (value >> 4)
+}
+
+/// Blends two channels 3:5, the way the console interpolates between endpoint colors.
+fn blend(a: u8, b: u8) -> u8 {
+    ((a as u32 * 3 + b as u32 * 5) >> 3) as u8
+}
+
+/// Decodes one 4x4 block into its 16 RGBA pixels, in rows.
+fn decode_block(block: &[u8]) -> [[u8; 4]; 16] {
+    let raw0 = u16::from_be_bytes([block[0], block[1]]);
+    let raw1 = u16::from_be_bytes([block[2], block[3]]);
+
+    let rgb = |raw: u16| [expand_5(raw >> 11), expand_6((raw >> 5) & 0x3F), expand_5(raw & 0x1F)];
+    let (c0, c1) = (rgb(raw0), rgb(raw1));
+
+    let mut colors = [[0u8; 4]; 4];
+    colors[0] = [c0[0], c0[1], c0[2], 0xFF];
+    colors[1] = [c1[0], c1[1], c1[2], 0xFF];
+
+    if raw0 > raw1 {
+        colors[2] = [blend(c1[0], c0[0]), blend(c1[1], c0[1]), blend(c1[2], c0[2]), 0xFF];
+        colors[3] = [blend(c0[0], c1[0]), blend(c0[1], c1[1]), blend(c0[2], c1[2]), 0xFF];
+    } else {
+        let average = |i: usize| ((c0[i] as u32 + c1[i] as u32) / 2) as u8;
+        colors[2] = [average(0), average(1), average(2), 0xFF];
+        colors[3] = [average(0), average(1), average(2), 0];
+    }
+
+    let mut pixels = [[0u8; 4]; 16];
+    for (row, line) in block[4..8].iter().enumerate() {
+        for column in 0..4 {
+            pixels[row * 4 + column] = colors[((line >> (6 - column * 2)) & 3) as usize];
+        }
+    }
+
+    pixels
+}
+
+/// Decodes CMPR data to RGBA. The data is made of 8x8 tiles in rows,
+/// each holding four 4x4 blocks in rows.
+///
+/// ### Parameters
+/// - `block`: The CMPR data, which must cover the image rounded up to whole tiles.
+/// - `width`: The width of the image, in pixels.
+/// - `height`: The height of the image, in pixels.
+///
+/// ### Returns
+/// - the RGBA of the image
+pub fn decode_cmpr_builtin(block: &[u8], width: u32, height: u32) -> Vec<u8> {
+    let mut rgba = vec![0u8; width as usize * height as usize * 4];
+    decode_cmpr_builtin_into(&mut rgba, block, width, height);
+    rgba
+}
+
+/// Decodes CMPR data to RGBA in a buffer the caller owns, without allocating.
+/// Pixels the data doesn't cover are zeroed.
+///
+/// ### Parameters
+/// - `rgba`: Where to write the RGBA, exactly `width * height * 4` bytes long.
+/// - `block`: The CMPR data, which must cover the image rounded up to whole tiles.
+/// - `width`: The width of the image, in pixels.
+/// - `height`: The height of the image, in pixels.
+pub fn decode_cmpr_builtin_into(rgba: &mut [u8], block: &[u8], width: u32, height: u32) {
+    let (width, height) = (width as usize, height as usize);
+    let tiles_wide = width.div_ceil(8);
+    rgba.fill(0);
+
+    for (i, encoded) in block.chunks_exact(8).enumerate() {
+        let tile = i / 4;
+        let block_x = (tile % tiles_wide) * 8 + (i % 2) * 4;
+        let block_y = (tile / tiles_wide) * 8 + (i % 4) / 2 * 4;
+
+        if block_y >= height {
+            continue;
+        }
+
+        for (p, pixel) in decode_block(encoded).iter().enumerate() {
+            let (x, y) = (block_x + p % 4, block_y + p / 4);
+            if x < width && y < height {
+                let start = (y * width + x) * 4;
+                rgba[start..start + 4].copy_from_slice(pixel);
+            }
+        }
+    }
+}
@@ -0,0 +1,39 @@
+//! Export of grid entries as CSV, for looking through many files in a spreadsheet.
+//! Only the entry table is read, so no images are decoded.
+
+use crate::{Bgst, SceneIndex};
+
+/// The header row of the CSV, naming a column for every entry field.
+pub const CSV_COLUMNS: &str = "entry_index,enabled,scene_index,scene_name,grid_x,grid_y,main_image_index,mask_image_index,unk_c,unk_e";
+
+/// Formats the grid entries of a file as CSV, one row per entry after a header row.
+/// Every field is written as it is stored, including the unknown ones,
+/// along with the name of the entry's scene; see `SceneIndex::name_of`.
+///
+/// ### Parameters
+/// - `bgst`: The parsed BGST file.
+///
+/// ### Returns
+/// - the CSV text
+pub fn entries_csv(bgst: &Bgst) -> String {
+    let mut csv = String::from(CSV_COLUMNS);
+    csv.push('\n');
+
+    for (entry_index, entry) in bgst.grid_entries.iter().enumerate() {
+        csv += &format!(
+            "{},{},{},{},{},{},{},{},{},{}\n",
+            entry_index,
+            entry.enabled,
+            entry.scene_index,
+            SceneIndex::name_of(entry.scene_index),
+            entry.grid_x,
+            entry.grid_y,
+            entry.main_image_index,
+            entry.mask_image_index,
+            entry._unk_c,
+            entry._unk_e
+        );
+    }
+
+    csv
+}
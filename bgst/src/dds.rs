@@ -0,0 +1,200 @@
+//! Export of images to DirectDraw Surface (`.dds`) files.
+//! CMPR is DXT1 with its blocks rearranged and its fields stored big-endian,
+//! so main images are transcoded without decoding and lose nothing.
+//! Images in any other format are decoded and stored as uncompressed BGRA.
+
+use anyhow::Result;
+use std::fs;
+use byteorder::{ByteOrder, LittleEndian};
+use image::*;
+use crate::{BgstError, DanglingReference, ExtractOptions, annotate, decode_block, encoded_image, output_image, select_entries};
+use crate::swizzle::TileLayout;
+use crate::texture::TextureFormat;
+
+const DDS_HEADER_SIZE: usize = 4 + 124;
+
+const DDSD_CAPS: u32 = 0x1;
+const DDSD_HEIGHT: u32 = 0x2;
+const DDSD_WIDTH: u32 = 0x4;
+const DDSD_PITCH: u32 = 0x8;
+const DDSD_PIXELFORMAT: u32 = 0x1000;
+const DDSD_LINEARSIZE: u32 = 0x80000;
+
+const DDPF_ALPHAPIXELS: u32 = 0x1;
+const DDPF_FOURCC: u32 = 0x4;
+const DDPF_RGB: u32 = 0x40;
+
+const DDSCAPS_TEXTURE: u32 = 0x1000;
+
+/// Writes a DDS header for a single texture without mipmaps.
+fn dds_header(width: u32, height: u32, compressed: bool, data_size: usize) -> Vec<u8> {
+    let mut header = vec![0u8; DDS_HEADER_SIZE];
+    header[..4].copy_from_slice(b"DDS ");
+
+    let mut flags = DDSD_CAPS | DDSD_HEIGHT | DDSD_WIDTH | DDSD_PIXELFORMAT;
+    flags |= if compressed { DDSD_LINEARSIZE } else { DDSD_PITCH };
+    let pitch_or_linear_size = if compressed { data_size as u32 } else { width * 4 };
+
+    LittleEndian::write_u32(&mut header[4..8], 124);
+    LittleEndian::write_u32(&mut header[8..0xC], flags);
+    LittleEndian::write_u32(&mut header[0xC..0x10], height);
+    LittleEndian::write_u32(&mut header[0x10..0x14], width);
+    LittleEndian::write_u32(&mut header[0x14..0x18], pitch_or_linear_size);
+
+    // pixel format
+    LittleEndian::write_u32(&mut header[0x4C..0x50], 32);
+    if compressed {
+        LittleEndian::write_u32(&mut header[0x50..0x54], DDPF_FOURCC);
+        header[0x54..0x58].copy_from_slice(b"DXT1");
+    } else {
+        LittleEndian::write_u32(&mut header[0x50..0x54], DDPF_RGB | DDPF_ALPHAPIXELS);
+        LittleEndian::write_u32(&mut header[0x58..0x5C], 32);
+        LittleEndian::write_u32(&mut header[0x5C..0x60], 0x00FF_0000);
+        LittleEndian::write_u32(&mut header[0x60..0x64], 0x0000_FF00);
+        LittleEndian::write_u32(&mut header[0x64..0x68], 0x0000_00FF);
+        LittleEndian::write_u32(&mut header[0x68..0x6C], 0xFF00_0000);
+    }
+
+    LittleEndian::write_u32(&mut header[0x6C..0x70], DDSCAPS_TEXTURE);
+
+    header
+}
+
+/// Reverses the order of the four 2-bit pixel indices in a byte.
+fn reverse_indices(byte: u8) -> u8 {
+    (byte & 0x03) << 6 | (byte & 0x0C) << 2 | (byte & 0x30) >> 2 | (byte & 0xC0) >> 6
+}
+
+/// Rearranges CMPR data into DXT1 data. CMPR stores 8x8 tiles of four 4x4 DXT1 blocks
+/// with big-endian colors and pixel indices packed from the most significant bit,
+/// while DXT1 stores its blocks in rows with little-endian colors and pixel indices
+/// packed from the least significant bit.
+///
+/// ### Parameters
+/// - `cmpr`: The CMPR data.
+/// - `width`: The width of the image, in pixels.
+/// - `height`: The height of the image, in pixels.
+///
+/// ### Returns
+/// - the DXT1 data
+pub fn cmpr_to_dxt1(cmpr: &[u8], width: u32, height: u32) -> Vec<u8> {
+    let blocks_wide = width.div_ceil(4) as usize;
+    let blocks_high = height.div_ceil(4) as usize;
+    let tiles_wide = width.div_ceil(8) as usize;
+    let mut dxt1 = vec![0u8; blocks_wide * blocks_high * 8];
+
+    for (i, block) in cmpr.chunks_exact(8).enumerate() {
+        let tile = i / 4;
+        let block_x = (tile % tiles_wide) * 2 + (i % 2);
+        let block_y = (tile / tiles_wide) * 2 + (i % 4) / 2;
+
+        if block_x >= blocks_wide || block_y >= blocks_high {
+            continue;
+        }
+
+        let start = (block_y * blocks_wide + block_x) * 8;
+        let out = &mut dxt1[start..start + 8];
+        out[0] = block[1];
+        out[1] = block[0];
+        out[2] = block[3];
+        out[3] = block[2];
+        for row in 0..4 {
+            out[4 + row] = reverse_indices(block[4 + row]);
+        }
+    }
+
+    dxt1
+}
+
+/// Wraps CMPR data in a DXT1 DDS file.
+///
+/// ### Parameters
+/// - `cmpr`: The CMPR data.
+/// - `width`: The width of the image, in pixels.
+/// - `height`: The height of the image, in pixels.
+///
+/// ### Returns
+/// - the DDS file
+pub fn encode_cmpr_dds(cmpr: &[u8], width: u32, height: u32) -> Vec<u8> {
+    let dxt1 = cmpr_to_dxt1(cmpr, width, height);
+    let mut dds = dds_header(width, height, true, dxt1.len());
+    dds.extend_from_slice(&dxt1);
+    dds
+}
+
+/// Stores an image in an uncompressed BGRA DDS file.
+///
+/// ### Parameters
+/// - `image`: The image.
+///
+/// ### Returns
+/// - the DDS file
+pub fn encode_rgba_dds(image: &RgbaImage) -> Vec<u8> {
+    let mut dds = dds_header(image.width(), image.height(), false, image.as_raw().len());
+    for pixel in image.pixels() {
+        dds.extend_from_slice(&[pixel[2], pixel[1], pixel[0], pixel[3]]);
+    }
+    dds
+}
+
+/// Writes a DDS file, reporting failures as write errors.
+pub(crate) fn write_dds(filename: &str, dds: &[u8]) -> Result<()> {
+    fs::write(filename, dds)
+        .map_err(|err| BgstError::Write(format!("failed to write {}: {}", filename, err)))?;
+    Ok(())
+}
+
+/// Writes every selected image as a DDS file, named `0.dds`, `1.dds`, ... in decode order.
+/// CMPR images are transcoded to DXT1, and every other image, or any image that's
+/// annotated, is decoded and stored uncompressed. Masks are not applied.
+///
+/// ### Parameters
+/// - `bgst_contents`: The raw data of a bgst3 file.
+/// - `options`: Options that control which images are decoded and what is written.
+/// - `folder_name`: The existing folder to write the images into.
+///
+/// ### Returns
+/// - the image indices that were skipped because they were out of range
+pub fn write_dds_images(
+    bgst_contents: &[u8],
+    options: &ExtractOptions,
+    folder_name: &str
+) -> Result<Vec<DanglingReference>> {
+    let (header, selected, dangling_references) = select_entries(bgst_contents, &options.decode)?;
+    let mut written = 0;
+
+    for (entry_index, entry) in &selected {
+        for (image_index, is_mask) in [(entry.main_image_index, false), (entry.mask_image_index, true)] {
+            if !header.has_image(image_index) || (options.decode.masks_only && !is_mask) {
+                continue;
+            }
+
+            let format = options.decode.overrides.format_for(image_index as usize, is_mask);
+            // linear blocks are rearranged, and stripped alpha, annotations and flipped rows are changed
+            // after decoding, so only untouched tiled CMPR is transcoded directly
+            let transcode = format == TextureFormat::CMPR
+                && header.layout == TileLayout::Tiled
+                && (is_mask || !options.strip_alpha)
+                && !options.annotate
+                && !options.decode.flip_v;
+            let dds = if transcode {
+                let encoded = encoded_image(&header, bgst_contents, *entry_index, image_index, format)?;
+                encode_cmpr_dds(encoded, header.image_width, header.image_height)
+            } else {
+                let decoded = decode_block(&header, bgst_contents, *entry_index, image_index, format, is_mask)?;
+                let Some(mut image) = RgbaImage::from_raw(header.image_width, header.image_height, decoded) else {
+                    continue;
+                };
+                if options.annotate {
+                    annotate::annotate_tile(&mut image, *entry_index, entry);
+                }
+                encode_rgba_dds(&output_image(&image, is_mask, options))
+            };
+
+            write_dds(&format!("{}/{}.dds", folder_name, written), &dds)?;
+            written += 1;
+        }
+    }
+
+    Ok(dangling_references)
+}
@@ -0,0 +1,98 @@
+//! Comparison of two versions of a file by their decoded tiles, such as an original
+//! background and a modded one. Tiles are compared by pixels rather than bytes,
+//! so re-encoded blocks that decode to the same image aren't reported.
+
+use anyhow::Result;
+use std::collections::HashMap;
+use image::*;
+use crate::{DecodeOptions, ExtractOptions, decode_masked_entry, select_entries, write_tile};
+
+/// The finished tile of every selected entry, keyed by entry index.
+struct Tiles {
+    width: u32,
+    height: u32,
+    tiles: HashMap<usize, Option<Vec<u8>>>,
+}
+
+/// Decodes the finished tile of every selected entry, with its mask applied.
+fn decode_tiles(bgst_contents: &[u8], options: &DecodeOptions) -> Result<Tiles> {
+    let (header, selected, _) = select_entries(bgst_contents, options)?;
+
+    let mut tiles = HashMap::new();
+    for (entry_index, entry) in &selected {
+        let tile = decode_masked_entry(&header, bgst_contents, *entry_index, entry, &options.overrides)?;
+        tiles.insert(*entry_index, tile);
+    }
+
+    Ok(Tiles {
+        width: header.image_width,
+        height: header.image_height,
+        tiles,
+    })
+}
+
+/// Finds every entry of the modified file whose finished tile differs from the same entry
+/// of the original, masks applied. Entries the original doesn't have count as changed,
+/// and entries without a main image in the modified file are left out, since there's no tile to show.
+///
+/// ### Parameters
+/// - `original`: The raw data of the original file.
+/// - `modified`: The raw data of the modified file.
+/// - `options`: Options that control which entries are decoded, used for both files.
+///
+/// ### Returns
+/// - the index of every changed entry, in order
+pub fn changed_entries(original: &[u8], modified: &[u8], options: &DecodeOptions) -> Result<Vec<usize>> {
+    let before = decode_tiles(original, options)?;
+    let after = decode_tiles(modified, options)?;
+    Ok(compare_tiles(&before, &after))
+}
+
+/// Returns the index of every entry whose tile in `after` differs from its tile in `before`.
+fn compare_tiles(before: &Tiles, after: &Tiles) -> Vec<usize> {
+    let same_size = before.width == after.width && before.height == after.height;
+
+    let mut changed: Vec<usize> = after.tiles
+        .iter()
+        .filter(|(_, tile)| tile.is_some())
+        .filter(|(entry_index, tile)| !same_size || before.tiles.get(entry_index) != Some(tile))
+        .map(|(entry_index, _)| *entry_index)
+        .collect();
+    changed.sort_unstable();
+
+    changed
+}
+
+/// Writes the finished tile of every entry that `changed_entries` reports, taken from
+/// the modified file and named after the index of its entry, such as `12.png`.
+///
+/// ### Parameters
+/// - `original`: The raw data of the original file.
+/// - `modified`: The raw data of the modified file.
+/// - `options`: Options that control which entries are decoded and what is written.
+/// - `folder_name`: The existing folder to write the tiles into.
+///
+/// ### Returns
+/// - the index of every changed entry, in order
+pub fn write_changed_tiles(
+    original: &[u8],
+    modified: &[u8],
+    options: &ExtractOptions,
+    folder_name: &str
+) -> Result<Vec<usize>> {
+    let before = decode_tiles(original, &options.decode)?;
+    let after = decode_tiles(modified, &options.decode)?;
+    let changed = compare_tiles(&before, &after);
+
+    for entry_index in &changed {
+        let Some(Some(decoded)) = after.tiles.get(entry_index) else {
+            continue;
+        };
+
+        if let Some(tile) = RgbaImage::from_raw(after.width, after.height, decoded.clone()) {
+            write_tile(folder_name, &entry_index.to_string(), &tile, false, options)?;
+        }
+    }
+
+    Ok(changed)
+}
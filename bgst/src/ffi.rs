@@ -0,0 +1,166 @@
+//! A C-compatible interface over the decoder, enabled with the `ffi` feature.
+//!
+//! Build a shared library with
+//! `cargo rustc -p bgst --lib --features ffi --crate-type cdylib`.
+//! Every function catches panics, so none of them unwind into the caller.
+//! Buffers returned by the library must be released with `bgst_free`.
+
+use std::io::Cursor;
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+use std::slice;
+use image::*;
+use crate::{Header, get_raw_images, read_source};
+
+/// The call succeeded.
+pub const BGST_OK: i32 = 0;
+/// A pointer argument was null.
+pub const BGST_ERROR_NULL: i32 = 1;
+/// The input is not a valid BGST file.
+pub const BGST_ERROR_INVALID: i32 = 2;
+/// The requested image does not exist.
+pub const BGST_ERROR_INDEX: i32 = 3;
+/// The image could not be decoded or encoded.
+pub const BGST_ERROR_DECODE: i32 = 4;
+/// The library panicked.
+pub const BGST_ERROR_PANIC: i32 = 5;
+
+/// Borrows the input buffer, treating a null pointer with a zero length as empty.
+///
+/// ### Safety
+/// `ptr` must be null or point to `len` readable bytes.
+unsafe fn input<'a>(ptr: *const u8, len: usize) -> Option<&'a [u8]> {
+    if ptr.is_null() {
+        return if len == 0 { Some(&[]) } else { None };
+    }
+
+    Some(slice::from_raw_parts(ptr, len))
+}
+
+/// Reads the input buffer the same way as any other source, decompressing it if needed.
+/// Returns `None` if it can't be read.
+fn uncompressed(input: &[u8]) -> Option<Vec<u8>> {
+    read_source(input).ok()
+}
+
+/// Returns if a buffer holds a valid BGST header.
+///
+/// ### Safety
+/// `ptr` must be null or point to `len` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn bgst_validate(ptr: *const u8, len: usize) -> bool {
+    panic::catch_unwind(|| {
+        input(ptr, len).and_then(uncompressed).is_some_and(|contents| Header::is_valid(&contents))
+    }).unwrap_or(false)
+}
+
+/// Returns the number of images `bgst_decode_png` can decode from a buffer,
+/// or `-1` if the buffer is not a valid BGST file.
+///
+/// ### Safety
+/// `ptr` must be null or point to `len` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn bgst_image_count(ptr: *const u8, len: usize) -> i64 {
+    panic::catch_unwind(|| {
+        input(ptr, len)
+            .and_then(uncompressed)
+            .and_then(|contents| crate::Bgst::parse(&contents).ok())
+            .map(|bgst| crate::decodable_image_count(&bgst) as i64)
+            .unwrap_or(-1)
+    }).unwrap_or(-1)
+}
+
+/// Decodes one image of a BGST file and encodes it as a PNG.
+/// Images are numbered in decode order, the same order `bgsttool` writes them in.
+/// On success, `*out_ptr` and `*out_len` describe a buffer owned by the library.
+///
+/// ### Returns
+/// - `BGST_OK`, or one of the `BGST_ERROR_*` codes
+///
+/// ### Safety
+/// `ptr` must be null or point to `len` readable bytes.
+/// `out_ptr` and `out_len` must be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn bgst_decode_png(
+    ptr: *const u8,
+    len: usize,
+    index: usize,
+    out_ptr: *mut *mut u8,
+    out_len: *mut usize
+) -> i32 {
+    if out_ptr.is_null() || out_len.is_null() {
+        return BGST_ERROR_NULL;
+    }
+
+    *out_ptr = ptr::null_mut();
+    *out_len = 0;
+
+    let Some(contents) = input(ptr, len) else {
+        return BGST_ERROR_NULL;
+    };
+
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        let Some(images) = uncompressed(contents).and_then(|contents| get_raw_images(&contents).ok()) else {
+            return Err(BGST_ERROR_INVALID);
+        };
+
+        let Some(decoded) = images.images.get(index) else {
+            return Err(BGST_ERROR_INDEX);
+        };
+
+        let image = RgbaImage::from_raw(images.image_width, images.image_height, decoded.to_owned())
+            .ok_or(BGST_ERROR_DECODE)?;
+
+        let mut buffer = Cursor::new(Vec::new());
+        image.write_to(&mut buffer, ImageFormat::Png)
+            .map_err(|_| BGST_ERROR_DECODE)?;
+
+        Ok(buffer.into_inner().into_boxed_slice())
+    }));
+
+    match result {
+        Ok(Ok(png)) => {
+            *out_len = png.len();
+            *out_ptr = Box::into_raw(png) as *mut u8;
+            BGST_OK
+        }
+
+        Ok(Err(code)) => code,
+        Err(_) => BGST_ERROR_PANIC,
+    }
+}
+
+/// Releases a buffer returned by the library. Passing a null pointer does nothing.
+///
+/// ### Safety
+/// `ptr` and `len` must be exactly as returned by the library,
+/// and the buffer must not be used or released again afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn bgst_free(ptr: *mut u8, len: usize) {
+    if ptr.is_null() {
+        return;
+    }
+
+    let _ = panic::catch_unwind(AssertUnwindSafe(|| {
+        drop(Box::from_raw(ptr::slice_from_raw_parts_mut(ptr, len)));
+    }));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_bad_input() {
+        unsafe {
+            assert!(!bgst_validate(ptr::null(), 0));
+            assert!(!bgst_validate(b"BGST".as_ptr(), 4));
+            assert_eq!(bgst_image_count(ptr::null(), 4), -1);
+
+            let mut out_ptr = ptr::null_mut();
+            let mut out_len = 0;
+            assert_eq!(bgst_decode_png(b"BGST".as_ptr(), 4, 0, &mut out_ptr, &mut out_len), BGST_ERROR_INVALID);
+            assert!(out_ptr.is_null());
+        }
+    }
+}
@@ -0,0 +1,90 @@
+//! 8-bit indexed PNG output, for tiles that use few enough colors.
+//! Background tiles are often flat-colored, so this can shrink them considerably.
+
+use anyhow::Result;
+use std::collections::HashMap;
+use image::*;
+
+/// The most colors an indexed PNG can hold.
+const MAX_PALETTE_SIZE: usize = 256;
+
+/// Splits an image into a palette of its distinct colors and one palette index per pixel.
+/// Colors are numbered in the order they first appear.
+///
+/// ### Parameters
+/// - `image`: The image.
+///
+/// ### Returns
+/// - the palette and the indices, or `None` if the image has more than 256 colors
+pub fn palettize(image: &RgbaImage) -> Option<(Vec<Rgba<u8>>, Vec<u8>)> {
+    let mut palette = Vec::new();
+    let mut lookup = HashMap::new();
+    let mut indices = Vec::with_capacity(image.width() as usize * image.height() as usize);
+
+    for pixel in image.pixels() {
+        let index = match lookup.get(pixel) {
+            Some(index) => *index,
+            None => {
+                if palette.len() == MAX_PALETTE_SIZE {
+                    return None;
+                }
+
+                let index = palette.len() as u8;
+                palette.push(*pixel);
+                lookup.insert(*pixel, index);
+                index
+            }
+        };
+
+        indices.push(index);
+    }
+
+    Some((palette, indices))
+}
+
+/// Encodes an image as an 8-bit indexed PNG, with a transparency chunk
+/// if any of its colors aren't fully opaque.
+///
+/// ### Parameters
+/// - `image`: The image.
+///
+/// ### Returns
+/// - the encoded PNG, or `None` if the image has more than 256 colors
+pub fn encode_indexed_png(image: &RgbaImage) -> Result<Option<Vec<u8>>> {
+    encode_indexed_png_with_text(image, &[])
+}
+
+/// Encodes an image like `encode_indexed_png`, with text chunks before its image data.
+///
+/// ### Parameters
+/// - `image`: The image.
+/// - `chunks`: The keyword and text of every chunk.
+///
+/// ### Returns
+/// - the encoded PNG, or `None` if the image has more than 256 colors
+pub fn encode_indexed_png_with_text(image: &RgbaImage, chunks: &[(String, String)]) -> Result<Option<Vec<u8>>> {
+    let Some((palette, indices)) = palettize(image) else {
+        return Ok(None);
+    };
+
+    let colors: Vec<u8> = palette.iter().flat_map(|color| [color[0], color[1], color[2]]).collect();
+    let alpha: Vec<u8> = palette.iter().map(|color| color[3]).collect();
+
+    let mut buffer = Vec::new();
+    let mut encoder = png::Encoder::new(&mut buffer, image.width(), image.height());
+    encoder.set_color(png::ColorType::Indexed);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.set_palette(colors);
+    if alpha.iter().any(|alpha| *alpha != 0xFF) {
+        encoder.set_trns(alpha);
+    }
+    for (keyword, text) in chunks {
+        encoder.add_text_chunk(keyword.clone(), text.clone())?;
+    }
+
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(&indices)?;
+    writer.finish()?;
+
+    Ok(Some(buffer))
+}
@@ -0,0 +1,49 @@
+//! Export of grid entries as JSON Lines, one object per entry, for piping into `jq`
+//! and other stream processors. Only the entry table is read, so no images are decoded.
+
+use anyhow::Result;
+use std::io::Write;
+use serde::Serialize;
+use crate::{Bgst, SceneIndex};
+
+/// One line of the output, with its fields in the order they are stored.
+#[derive(Serialize)]
+struct JsonEntry {
+    entry_index: usize,
+    enabled: i16,
+    scene_index: i16,
+    scene_name: String,
+    grid_x: i16,
+    grid_y: i16,
+    main_image_index: i16,
+    mask_image_index: i16,
+    unk_c: i16,
+    unk_e: i16,
+}
+
+/// Writes the grid entries of a file as JSON Lines, one object per entry in order.
+/// Every object has the same keys as the columns of `csv::entries_csv`,
+/// and every field is written as it is stored, including the unknown ones.
+///
+/// ### Parameters
+/// - `bgst`: The parsed BGST file.
+/// - `writer`: Where to write the lines.
+pub fn write_entries_jsonl(bgst: &Bgst, mut writer: impl Write) -> Result<()> {
+    for (entry_index, entry) in bgst.grid_entries.iter().enumerate() {
+        let line = JsonEntry {
+            entry_index,
+            enabled: entry.enabled,
+            scene_index: entry.scene_index,
+            scene_name: SceneIndex::name_of(entry.scene_index),
+            grid_x: entry.grid_x,
+            grid_y: entry.grid_y,
+            main_image_index: entry.main_image_index,
+            mask_image_index: entry.mask_image_index,
+            unk_c: entry._unk_c,
+            unk_e: entry._unk_e,
+        };
+        writeln!(writer, "{}", serde_json::to_string(&line)?)?;
+    }
+
+    Ok(())
+}
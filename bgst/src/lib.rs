@@ -1,15 +1,197 @@
-use anyhow::{Result, bail};
+use anyhow::{Result, bail, Context};
+use std::borrow::Cow;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt;
+use std::hash::{DefaultHasher, Hash, Hasher};
 use std::fs;
-use std::io::Cursor;
+use std::io::{Cursor, Read};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
 use image::*;
-use gctex;
 use byteorder::{ByteOrder, BigEndian};
+use rayon::prelude::*;
+use serde::Serialize;
+use overrides::FormatOverrides;
+use provenance::TileProvenance;
+use swizzle::TileLayout;
+use texture::TextureFormat;
+use timing::{BlockTiming, DecodeEvent, DecodeObserver};
 
 pub const HEADER_SIZE: usize = 0x40;
 pub const GRID_ENTRY_SIZE: usize = 0x10;
+/// The size of one image block. CMPR and I4 are both 4 bits per pixel,
+/// so main and mask images share this stride; it holds a 512x512 image in either format.
 pub const COMPRESSED_IMAGE_SIZE: usize = 0x20000;
+/// The extensions BGST files are found with. Both use the same layout, which is
+/// the only one this crate reads; files are recognized by their magic, not their extension.
+pub const BGST_EXTENSIONS: [&str; 2] = ["bgst3", "bgst"];
+/// The default largest image width or height a file may declare.
+pub const DEFAULT_MAX_DIMENSION: u32 = 16384;
+/// The default largest number of pixels in an image assembled from many tiles, 1 GiB as RGBA.
+pub const DEFAULT_MAX_CANVAS_PIXELS: u64 = 1 << 28;
+
+pub mod annotate;
+pub mod apng;
+pub mod atlas;
+pub mod csv;
+pub mod dds;
+pub mod diff;
+pub mod indexed;
+pub mod jsonl;
+pub mod manifest;
+pub mod ora;
+pub mod overrides;
+pub mod pack;
+pub mod preview;
+pub mod provenance;
+pub mod report;
+pub mod tiff;
+pub mod tmx;
+pub mod swizzle;
+pub mod tasks;
+pub mod texture;
+pub mod timing;
+pub mod unique;
+pub mod warnings;
+
+#[cfg(feature = "builtin-cmpr")]
+pub mod cmpr;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+#[cfg(feature = "ndarray")]
+pub mod ndarray;
+#[cfg(feature = "yaz0")]
+pub mod yaz0;
+
+/// The categories of failure the library reports.
+/// Errors returned by the library can be downcast to this type.
+#[derive(Debug)]
+pub enum BgstError {
+    /// The input file does not exist.
+    FileNotFound(String),
+    /// The input is not a valid BGST file.
+    InvalidFile(String),
+    /// An image could not be decoded.
+    Decode(String),
+    /// The output could not be written.
+    Write(String),
+}
+
+impl fmt::Display for BgstError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BgstError::FileNotFound(filename) => write!(f, "file {} does not exist", filename),
+            BgstError::InvalidFile(message) => write!(f, "{}", message),
+            BgstError::Decode(message) => write!(f, "{}", message),
+            BgstError::Write(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for BgstError {}
+
+/// Somewhere the contents of a BGST file can be read from.
+/// Paths (`Path`, `PathBuf`, `str` and `String`) are read from disk,
+/// and byte buffers (`[u8]` and `Vec<u8>`) are used as they are.
+/// Implement it to read files from somewhere else, such as an archive.
+/// Sources are read with `read_source`, which decompresses them if needed.
+pub trait BgstSource {
+    /// Reads the whole file.
+    fn read_all(&self) -> Result<Vec<u8>>;
+
+    /// Returns the name of the file, if it has one.
+    /// Named sources can be extracted into a folder named after them.
+    fn name(&self) -> Option<String> {
+        None
+    }
+}
+
+impl BgstSource for Path {
+    fn read_all(&self) -> Result<Vec<u8>> {
+        if !self.exists() {
+            bail!(BgstError::FileNotFound(self.display().to_string()));
+        }
+
+        fs::read(self).with_context(|| format!("failed to read {}", self.display()))
+    }
+
+    fn name(&self) -> Option<String> {
+        Some(self.to_string_lossy().into_owned())
+    }
+}
+
+impl BgstSource for PathBuf {
+    fn read_all(&self) -> Result<Vec<u8>> {
+        self.as_path().read_all()
+    }
+
+    fn name(&self) -> Option<String> {
+        self.as_path().name()
+    }
+}
+
+impl BgstSource for str {
+    fn read_all(&self) -> Result<Vec<u8>> {
+        Path::new(self).read_all()
+    }
+
+    fn name(&self) -> Option<String> {
+        Path::new(self).name()
+    }
+}
+
+impl BgstSource for String {
+    fn read_all(&self) -> Result<Vec<u8>> {
+        self.as_str().read_all()
+    }
+
+    fn name(&self) -> Option<String> {
+        self.as_str().name()
+    }
+}
+
+impl BgstSource for [u8] {
+    fn read_all(&self) -> Result<Vec<u8>> {
+        Ok(self.to_vec())
+    }
+}
+
+impl BgstSource for Vec<u8> {
+    fn read_all(&self) -> Result<Vec<u8>> {
+        Ok(self.clone())
+    }
+}
+
+impl<T: BgstSource + ?Sized> BgstSource for &T {
+    fn read_all(&self) -> Result<Vec<u8>> {
+        (**self).read_all()
+    }
+
+    fn name(&self) -> Option<String> {
+        (**self).name()
+    }
+}
+
+/// Reads the contents of a file from any source.
+/// With the `yaz0` feature, contents that are Yaz0 compressed are decompressed,
+/// so compressed files are read the same way from disk, from buffers and from archives.
+/// 
+/// ### Parameters
+/// - `source`: The bgst3 file.
+/// 
+/// ### Returns
+/// - the uncompressed contents of the file
+pub fn read_source(source: &(impl BgstSource + ?Sized)) -> Result<Vec<u8>> {
+    let contents = source.read_all()?;
+
+    #[cfg(feature = "yaz0")]
+    let contents = yaz0::decompress_if_compressed(contents)?;
+
+    Ok(contents)
+}
 
 /// A list of layers, described as "scenes" ingame.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub enum SceneIndex {
     Far05 = 0,
     Far04 = 1,
@@ -25,6 +207,109 @@ pub enum SceneIndex {
     Near05 = 11,
 }
 
+impl SceneIndex {
+    /// Every scene, from the farthest to the nearest.
+    pub const ALL: [SceneIndex; 12] = [
+        SceneIndex::Far05,
+        SceneIndex::Far04,
+        SceneIndex::Far03,
+        SceneIndex::Far02,
+        SceneIndex::Far01,
+        SceneIndex::Map,
+        SceneIndex::Game,
+        SceneIndex::Near01,
+        SceneIndex::Near02,
+        SceneIndex::Near03,
+        SceneIndex::Near04,
+        SceneIndex::Near05,
+    ];
+
+    /// Converts a scene index as stored in a grid entry.
+    /// 
+    /// ### Parameters
+    /// - `index`: The raw scene index.
+    /// 
+    /// ### Returns
+    /// - the scene, or `None` if the index is out of range
+    pub fn from_index(index: i16) -> Option<SceneIndex> {
+        usize::try_from(index)
+            .ok()
+            .and_then(|index| SceneIndex::ALL.get(index))
+            .copied()
+    }
+
+    /// Returns the name of the scene a raw scene index refers to, or `Unknown(N)`
+    /// for indices that aren't one of the known scenes, such as for metadata.
+    /// 
+    /// ### Parameters
+    /// - `index`: The raw scene index.
+    /// 
+    /// ### Returns
+    /// - the name
+    pub fn name_of(index: i16) -> String {
+        match SceneIndex::from_index(index) {
+            Some(scene) => scene.name().to_string(),
+            None => format!("Unknown({})", index),
+        }
+    }
+
+    /// Finds a scene by its name, ignoring case.
+    /// 
+    /// ### Parameters
+    /// - `name`: The name of the scene, such as `Game` or `Far01`.
+    /// 
+    /// ### Returns
+    /// - the scene, or `None` if no scene has that name
+    pub fn from_name(name: &str) -> Option<SceneIndex> {
+        SceneIndex::ALL
+            .into_iter()
+            .find(|scene| scene.name().eq_ignore_ascii_case(name))
+    }
+
+    /// Returns the name of the scene, as used ingame.
+    pub fn name(&self) -> &'static str {
+        match self {
+            SceneIndex::Far05 => "Far05",
+            SceneIndex::Far04 => "Far04",
+            SceneIndex::Far03 => "Far03",
+            SceneIndex::Far02 => "Far02",
+            SceneIndex::Far01 => "Far01",
+            SceneIndex::Map => "Map",
+            SceneIndex::Game => "Game",
+            SceneIndex::Near01 => "Near01",
+            SceneIndex::Near02 => "Near02",
+            SceneIndex::Near03 => "Near03",
+            SceneIndex::Near04 => "Near04",
+            SceneIndex::Near05 => "Near05",
+        }
+    }
+}
+
+/// The largest image dimensions a file may declare before it is rejected,
+/// so a corrupt or malicious header can't cause huge allocations.
+/// 
+/// ### Fields
+/// - `max_image_width`: The largest allowed image width, in pixels.
+/// - `max_image_height`: The largest allowed image height, in pixels.
+/// - `max_canvas_pixels`: The largest number of pixels an image assembled from
+///   many tiles, such as a composited scene, may have.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DimensionLimits {
+    pub max_image_width: u32,
+    pub max_image_height: u32,
+    pub max_canvas_pixels: u64,
+}
+
+impl Default for DimensionLimits {
+    fn default() -> Self {
+        DimensionLimits {
+            max_image_width: DEFAULT_MAX_DIMENSION,
+            max_image_height: DEFAULT_MAX_DIMENSION,
+            max_canvas_pixels: DEFAULT_MAX_CANVAS_PIXELS,
+        }
+    }
+}
+
 /// A stripped-down version of the header found
 /// in BGST files. Unknown fields are named based
 /// on the file offset.
@@ -33,22 +318,35 @@ pub enum SceneIndex {
 /// - `_unk_4`: Currently an unknown value.
 /// - `image_width`: The width of every image in the grid, in pixels.
 /// - `image_height`: The height of every image in the grid, in pixels.
-/// - `grid_width`: The number of rows the grid has.
-/// - `grid_height`: The number of columns the grid has.
+/// - `grid_width`: The number of columns the grid has.
+/// - `grid_height`: The number of rows the grid has.
 /// - `image_count`: The number of images in the file.
 /// - `layer_enabled`: Indicates which of the 12 rendering layers from the game are available to entries.
 /// - `info_offset`: Offset to entry info.
 /// - `image_data_offset`: Offset to the array of compressed image data.
+/// - `tail`: The bytes from `0x30` to the end of the header, which aren't understood yet.
+/// - `block_size`: The distance between image blocks, in bytes. This isn't stored in the file,
+///   and is `COMPRESSED_IMAGE_SIZE` unless overridden.
+/// - `layout`: How the data of image blocks is laid out. This isn't stored in the file either,
+///   and is `TileLayout::Tiled` unless overridden.
+/// - `entry_stride`: The distance between grid entries, in bytes. This isn't stored in the file,
+///   and is `GRID_ENTRY_SIZE` unless overridden. Bytes past the known fields of an entry are skipped.
+/// - `observer`: Told about every image block decoded with this header, if set.
 pub struct Header {
     _unk_4: u32,
     image_width: u32,
     image_height: u32,
-    _grid_width: u32,
-    _grid_height: u32,
+    grid_width: u32,
+    grid_height: u32,
     image_count: u32,
     _layer_enabled: [bool; 12],
     info_offset: usize,
-    image_data_offset: usize
+    image_data_offset: usize,
+    tail: [u8; 16],
+    block_size: usize,
+    layout: TileLayout,
+    entry_stride: usize,
+    observer: Option<DecodeObserver>
 }
 
 impl Header {
@@ -61,7 +359,7 @@ impl Header {
     /// ### Returns
     /// - a `Header` struct
     pub fn from_validated_header_bytes(
-        header_contents: &Vec<u8>
+        header_contents: &[u8]
     ) -> Header {
         let _unk_4 = BigEndian::read_u32(&header_contents[4..8]);
         let image_width = BigEndian::read_u32(&header_contents[8..0xC]);
@@ -71,8 +369,8 @@ impl Header {
         let image_count = BigEndian::read_u32(&header_contents[0x18..0x1C]);
         let mut layer_enabled = [false; 12];
     
-        for i in 0..12 {
-            layer_enabled[i] = header_contents
+        for (i, enabled) in layer_enabled.iter_mut().enumerate() {
+            *enabled = header_contents
                 .get(0x1C + i)
                 .copied()
                 .unwrap_or(0) != 0;
@@ -80,17 +378,26 @@ impl Header {
 
         let info_offset = BigEndian::read_u32(&header_contents[0x28..0x2C]) as usize;
         let image_data_offset = BigEndian::read_u32(&header_contents[0x2C..0x30]) as usize;
+        let mut tail = [0; 16];
+        for (i, byte) in tail.iter_mut().enumerate() {
+            *byte = header_contents.get(0x30 + i).copied().unwrap_or(0);
+        }
 
         Header {
             _unk_4,
             image_width,
             image_height,
-            _grid_width: grid_width,
-            _grid_height: grid_height,
+            grid_width,
+            grid_height,
             image_count,
             _layer_enabled: layer_enabled,
             info_offset,
-            image_data_offset
+            image_data_offset,
+            tail,
+            block_size: COMPRESSED_IMAGE_SIZE,
+            layout: TileLayout::Tiled,
+            entry_stride: GRID_ENTRY_SIZE,
+            observer: None
         }
     }
 
@@ -102,26 +409,289 @@ impl Header {
     /// ### Returns
     /// - `true` if the given header was valid
     pub fn is_valid(
-        file_contents: &Vec<u8>
+        file_contents: &[u8]
     ) -> bool {
-        if file_contents.len() < HEADER_SIZE {
-            return false;
-        } else if &file_contents[..4] != b"BGST" {
-            return false;
+        file_contents.len() >= HEADER_SIZE && &file_contents[..4] == b"BGST"
+    }
+
+    /// Returns the width of every image in the grid, in pixels.
+    pub fn image_width(&self) -> u32 {
+        self.image_width
+    }
+
+    /// Returns the height of every image in the grid, in pixels.
+    pub fn image_height(&self) -> u32 {
+        self.image_height
+    }
+
+    /// Returns the number of columns the grid has.
+    pub fn grid_width(&self) -> u32 {
+        self.grid_width
+    }
+
+    /// Returns the number of rows the grid has.
+    pub fn grid_height(&self) -> u32 {
+        self.grid_height
+    }
+
+    /// Returns if the header marks a scene's layer as enabled.
+    /// 
+    /// ### Parameters
+    /// - `scene`: The scene to check.
+    pub fn layer_enabled(&self, scene: SceneIndex) -> bool {
+        self._layer_enabled[scene as usize]
+    }
+
+    /// Returns the bytes from `0x30` to the end of the header.
+    /// Their meaning is unknown, so they are kept as they are.
+    pub fn header_tail(&self) -> [u8; 16] {
+        self.tail
+    }
+
+    /// Returns the distance between image blocks, in bytes.
+    pub fn block_size(&self) -> usize {
+        self.block_size
+    }
+
+    /// Returns the distance between grid entries, in bytes.
+    pub fn entry_stride(&self) -> usize {
+        self.entry_stride
+    }
+
+    /// Returns the size of the image data region, in bytes.
+    /// Anything past the end of this region is not image data.
+    pub fn image_data_size(&self) -> usize {
+        self.image_count as usize * self.block_size
+    }
+
+    /// Checks that the image dimensions the header declares are within the given limits.
+    /// 
+    /// ### Parameters
+    /// - `limits`: The largest allowed dimensions.
+    /// 
+    /// ### Returns
+    /// - an error naming the offending dimension if either is too large
+    pub fn check_dimensions(&self, limits: &DimensionLimits) -> Result<()> {
+        if self.image_width > limits.max_image_width {
+            bail!(BgstError::InvalidFile(format!(
+                "image width {} exceeds the limit of {}",
+                self.image_width,
+                limits.max_image_width
+            )));
         }
 
-        true
+        if self.image_height > limits.max_image_height {
+            bail!(BgstError::InvalidFile(format!(
+                "image height {} exceeds the limit of {}",
+                self.image_height,
+                limits.max_image_height
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// Checks that a file is large enough to hold every image block the header declares.
+    /// 
+    /// ### Parameters
+    /// - `file_len`: The size of the file, in bytes.
+    /// 
+    /// ### Returns
+    /// - an error naming the shortfall if the file is truncated
+    pub fn check_file_size(&self, file_len: usize) -> Result<()> {
+        let required = self.image_data_offset + self.image_data_size();
+
+        if file_len < required {
+            bail!(BgstError::InvalidFile(format!(
+                "file truncated by {} bytes ({} images need 0x{:X} bytes, file is 0x{:X})",
+                required - file_len,
+                self.image_count,
+                required,
+                file_len
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// Works out the distance between grid entries from the size of the entry table,
+    /// for files whose entry count is known from elsewhere. The header doesn't store one.
+    /// 
+    /// ### Parameters
+    /// - `entry_count`: The number of entries the file is known to have.
+    /// 
+    /// ### Returns
+    /// - the entry stride, or `None` if the table can't be split evenly into
+    ///   that many entries of at least `GRID_ENTRY_SIZE` bytes
+    pub fn infer_entry_stride(&self, entry_count: usize) -> Option<usize> {
+        let table_len = self.image_data_offset.checked_sub(self.info_offset)?;
+        if entry_count == 0 || !table_len.is_multiple_of(entry_count) {
+            return None;
+        }
+
+        Some(table_len / entry_count).filter(|stride| *stride >= GRID_ENTRY_SIZE)
+    }
+
+    /// Returns the image count a file really has room for, if it is one less than declared.
+    /// Declaring one image too many is a common authoring mistake, and only that case
+    /// is recognized; files missing more than one block are left alone.
+    /// 
+    /// ### Parameters
+    /// - `file_len`: The size of the file, in bytes.
+    /// 
+    /// ### Returns
+    /// - the image count to use instead, or `None` if the declared count doesn't need repairing
+    pub fn repaired_image_count(&self, file_len: usize) -> Option<u32> {
+        if self.image_count == 0 || self.check_file_size(file_len).is_ok() {
+            return None;
+        }
+
+        let count = self.image_count - 1;
+        let required = self.image_data_offset + count as usize * self.block_size;
+        (file_len >= required).then_some(count)
+    }
+
+    /// Returns the encoded data of one image. Only the declared
+    /// image blocks are reachable; anything after them is ignored.
+    /// 
+    /// ### Parameters
+    /// - `bgst_contents`: The raw data of the bgst3 file this header belongs to.
+    /// - `index`: The image index.
+    /// 
+    /// ### Returns
+    /// - the `block_size` bytes of the image block
+    pub fn image_block<'a>(&self, bgst_contents: &'a [u8], index: usize) -> Result<&'a [u8]> {
+        if index >= self.image_count as usize {
+            bail!(BgstError::InvalidFile(format!(
+                "image {} is out of range, the file has {} images",
+                index,
+                self.image_count
+            )));
+        }
+
+        let start = self.image_data_offset + index * self.block_size;
+        let Some(block) = bgst_contents.get(start..start + self.block_size) else {
+            bail!(BgstError::InvalidFile(format!("image {} is past the end of the file", index)));
+        };
+
+        Ok(block)
+    }
+
+    /// Returns if an image index refers to an image in the file.
+    /// 
+    /// ### Parameters
+    /// - `index`: The image index, as stored in a grid entry.
+    /// 
+    /// ### Returns
+    /// `true` if the index is in range.
+    pub fn has_image(&self, index: i16) -> bool {
+        index > -1 && (index as u32) < self.image_count
+    }
+}
+
+/// Returns the number of grid entries stored between the info and image data offsets.
+/// A partial entry at the end of the table is not counted.
+/// 
+/// ### Parameters
+/// - `header`: The header of the file.
+/// 
+/// ### Returns
+/// - the number of entries
+pub fn entry_count(header: &Header) -> usize {
+    header.image_data_offset.saturating_sub(header.info_offset) / header.entry_stride
+}
+
+/// Returns the number of bytes between the last whole grid entry and the image data,
+/// such as alignment padding. The header has no entry count field, so these bytes are
+/// ignored by `entry_count`, but a value other than 0 may mean the offsets are wrong.
+/// 
+/// ### Parameters
+/// - `header`: The header of the file.
+/// 
+/// ### Returns
+/// - the number of bytes left over after the entry table
+pub fn entry_table_padding(header: &Header) -> usize {
+    header.image_data_offset.saturating_sub(header.info_offset) % header.entry_stride
+}
+
+/// Returns the file offset of a grid entry.
+/// 
+/// ### Parameters
+/// - `header`: The header of the file.
+/// - `index`: The index of the entry.
+/// 
+/// ### Returns
+/// - the offset of the entry's first byte
+pub fn entry_offset(header: &Header, index: usize) -> usize {
+    header.info_offset + index * header.entry_stride
+}
+
+/// The values needed to build a BGST header.
+/// 
+/// ### Fields
+/// - `unk_4`: The value of the unknown field at offset `0x4`.
+/// - `image_width`: The width of every image in the grid, in pixels.
+/// - `image_height`: The height of every image in the grid, in pixels.
+/// - `grid_width`: The number of columns the grid has.
+/// - `grid_height`: The number of rows the grid has.
+/// - `image_count`: The number of images in the file.
+/// - `layer_enabled`: Indicates which of the 12 rendering layers from the game are available to entries.
+/// - `info_offset`: Offset to entry info.
+/// - `image_data_offset`: Offset to the array of compressed image data.
+/// - `tail`: The bytes from `0x30` to the end of the header, which aren't understood yet.
+#[derive(Clone, Debug, Default)]
+pub struct HeaderParams {
+    pub unk_4: u32,
+    pub image_width: u32,
+    pub image_height: u32,
+    pub grid_width: u32,
+    pub grid_height: u32,
+    pub image_count: u32,
+    pub layer_enabled: [bool; 12],
+    pub info_offset: u32,
+    pub image_data_offset: u32,
+    pub tail: [u8; 16],
+}
+
+/// Serializes a BGST header. This is the inverse of
+/// `Header::from_validated_header_bytes`.
+/// 
+/// ### Parameters
+/// - `params`: The values to write into the header.
+/// 
+/// ### Returns
+/// - the raw header
+pub fn build_header(params: HeaderParams) -> [u8; HEADER_SIZE] {
+    let mut header = [0u8; HEADER_SIZE];
+
+    header[..4].copy_from_slice(b"BGST");
+    BigEndian::write_u32(&mut header[4..8], params.unk_4);
+    BigEndian::write_u32(&mut header[8..0xC], params.image_width);
+    BigEndian::write_u32(&mut header[0xC..0x10], params.image_height);
+    BigEndian::write_u32(&mut header[0x10..0x14], params.grid_width);
+    BigEndian::write_u32(&mut header[0x14..0x18], params.grid_height);
+    BigEndian::write_u32(&mut header[0x18..0x1C], params.image_count);
+
+    for (i, enabled) in params.layer_enabled.iter().enumerate() {
+        header[0x1C + i] = *enabled as u8;
     }
+
+    BigEndian::write_u32(&mut header[0x28..0x2C], params.info_offset);
+    BigEndian::write_u32(&mut header[0x2C..0x30], params.image_data_offset);
+    header[0x30..].copy_from_slice(&params.tail);
+
+    header
 }
 
 /// A structure containing information on entries in the grid.
 /// Unknown fields are named based on the file offset.
 /// 
 /// ### Fields
-/// - `enabled`: Indicates whether or not this cell should be shown.
+/// - `enabled`: Indicates whether or not this cell should be shown. Only `0` and `1`
+///   have been seen in files so far; any other value is treated as enabled.
 /// - `scene_index`: Indicates which scene index this cell is to be shown on.
-/// - `grid_x`: The row in which the cell is rendered.
-/// - `grid_y`:The column in which the cell is rendered.
+/// - `grid_x`: The column in which the cell is rendered.
+/// - `grid_y`: The row in which the cell is rendered.
 /// - `main_image_index`: The CMPR image this cell will render, if any
 /// - `mask_image_index`: The I4 mask this cell will apply to the image, if any
 /// - `_unk_c`: Currently an unknown value.
@@ -138,290 +708,4709 @@ pub struct GridEntry {
 }
 
 impl GridEntry {
-    /// Returns if the entry is enabled.
+    /// Serializes the entry as it is stored in a file: every field as a big-endian
+    /// `i16`, in the order the parser reads them, so parsing and serializing
+    /// an entry gives back the same bytes.
+    /// 
+    /// ### Returns
+    /// - the raw entry
+    pub fn to_be_bytes(&self) -> [u8; GRID_ENTRY_SIZE] {
+        let mut bytes = [0u8; GRID_ENTRY_SIZE];
+        let fields = [
+            self.enabled,
+            self.scene_index,
+            self.grid_x,
+            self.grid_y,
+            self.main_image_index,
+            self.mask_image_index,
+            self._unk_c,
+            self._unk_e
+        ];
+
+        for (i, field) in fields.iter().enumerate() {
+            BigEndian::write_i16(&mut bytes[i * 2..i * 2 + 2], *field);
+        }
+
+        bytes
+    }
+
+    /// Returns if the entry is enabled. Every nonzero value counts as enabled,
+    /// since the game is only known to test it against zero.
     /// 
     /// ### Returns
     /// `true` if the entry is enabled.
     pub fn is_enabled(&self) -> bool {
         self.enabled != 0
     }
+
+    /// Returns the raw `enabled` value, for callers that want to tell apart
+    /// values other than `0` and `1`, if they turn up.
+    pub fn enabled(&self) -> i16 {
+        self.enabled
+    }
+
+    /// Returns the scene index this entry is shown on.
+    pub fn scene_index(&self) -> i16 {
+        self.scene_index
+    }
+
+    /// Returns the horizontal grid position of this entry.
+    pub fn grid_x(&self) -> i16 {
+        self.grid_x
+    }
+
+    /// Returns the vertical grid position of this entry.
+    pub fn grid_y(&self) -> i16 {
+        self.grid_y
+    }
 }
 
+/// A grid entry read in place from the raw data of a file.
+/// Fields are decoded when they are accessed, so scanning entries
+/// through views doesn't allocate.
+#[derive(Clone, Copy, Debug)]
+pub struct GridEntryView<'a> {
+    bytes: &'a [u8],
+}
 
+impl<'a> GridEntryView<'a> {
+    /// Reads the field at an index, in the order fields are stored.
+    fn field(&self, index: usize) -> i16 {
+        BigEndian::read_i16(&self.bytes[index * 2..index * 2 + 2])
+    }
 
+    /// Returns the raw bytes of the entry.
+    pub fn bytes(&self) -> &'a [u8] {
+        self.bytes
+    }
 
-/// Attempts to return the RGBA of every image.
-/// 
-/// ### Parameters
-/// - `bgst_contents`: The raw data of a bgst3 file.
-/// 
-/// ### Returns
-/// - an `ImageList` struct
-pub fn get_raw_images(
-        bgst_contents: &Vec<u8>
-    ) -> Result<ImageList> {
-    
-    if !Header::is_valid(&bgst_contents) {
-        bail!("file is not a valid BGST file");
+    /// Returns if the entry is enabled; see `GridEntry::is_enabled`.
+    pub fn is_enabled(&self) -> bool {
+        self.enabled() != 0
     }
-    
-    let header = Header::from_validated_header_bytes(&bgst_contents);
-    let mut grid_entries = Vec::new();
-    
-    let mut current_offset = header.info_offset;
-    while current_offset < header.image_data_offset {
-        let enabled = BigEndian::read_i16(&bgst_contents[current_offset..current_offset + 2]);
-        let scene_index = BigEndian::read_i16(&bgst_contents[current_offset + 2..current_offset + 4]);
-        let grid_x = BigEndian::read_i16(&bgst_contents[current_offset + 4..current_offset + 6]);
-        let grid_y = BigEndian::read_i16(&bgst_contents[current_offset + 6..current_offset + 8]);
-        let main_image_index = BigEndian::read_i16(&bgst_contents[current_offset + 8..current_offset + 0xA]);
-        let mask_image_index = BigEndian::read_i16(&bgst_contents[current_offset + 0xA..current_offset + 0xC]);
-        let _unk_c = BigEndian::read_i16(&bgst_contents[current_offset + 0xC..current_offset + 0xE]);
-        let _unk_e = BigEndian::read_i16(&bgst_contents[current_offset + 0xE..current_offset + 0x10]);
-        let entry = GridEntry {
-            enabled,
-            scene_index,
-            grid_x,
-            grid_y,
-            main_image_index,
-            mask_image_index,
-            _unk_c,
-            _unk_e
-        };
-        grid_entries.push(entry);
-        current_offset += GRID_ENTRY_SIZE;
+
+    /// Returns the raw `enabled` value.
+    pub fn enabled(&self) -> i16 {
+        self.field(0)
     }
-          
-    let mut images = Vec::new();
-    let image_data = Vec::from(&bgst_contents[header.image_data_offset..]);
-
-    for i in 0..grid_entries.len() {
-        let entry = &grid_entries[i];
-        if entry.main_image_index > -1 && entry.main_image_index < header.image_count as i16 {
-            let encoded = Vec::from(&image_data[entry.main_image_index as usize * COMPRESSED_IMAGE_SIZE..entry.main_image_index as usize * COMPRESSED_IMAGE_SIZE + COMPRESSED_IMAGE_SIZE]);
-            let decoded = gctex::decode(
-                &encoded,
-                header.image_width,
-                header.image_height,
-                gctex::TextureFormat::CMPR,
-                &Vec::new(),
-                0
-            );
-            images.push(decoded);
-        }
-        if entry.mask_image_index > -1 && entry.mask_image_index < header.image_count as i16 {
-            let encoded = Vec::from(&image_data[entry.mask_image_index as usize * COMPRESSED_IMAGE_SIZE..entry.mask_image_index as usize * COMPRESSED_IMAGE_SIZE + COMPRESSED_IMAGE_SIZE]);
-            let decoded = gctex::decode(
-                &encoded,
-                header.image_width,
-                header.image_height,
-                gctex::TextureFormat::I4,
-                &Vec::new(),
-                0
-            );
-            images.push(decoded);
-        }
+
+    /// Returns the scene index this entry is shown on.
+    pub fn scene_index(&self) -> i16 {
+        self.field(1)
     }
 
-    let result = ImageList {
-        image_width: header.image_width,
-        image_height: header.image_height,
-        grid_entries,
-        images
-    };
-    Ok(result)
-} 
+    /// Returns the horizontal grid position of this entry.
+    pub fn grid_x(&self) -> i16 {
+        self.field(2)
+    }
 
+    /// Returns the vertical grid position of this entry.
+    pub fn grid_y(&self) -> i16 {
+        self.field(3)
+    }
 
-/// A list of compressed or uncompressed images.
-/// 
-/// ### Fields
-/// - `image_width`: The width of every image, in pixels.
-/// - `image_height`: The height of every image, in pixels.
-/// - `images`: The images.
-pub struct ImageList {
-    image_width: u32,
-    image_height: u32,
+    /// Returns the index of the main image, or a negative value if there is none.
+    pub fn main_image_index(&self) -> i16 {
+        self.field(4)
+    }
+
+    /// Returns the index of the mask image, or a negative value if there is none.
+    pub fn mask_image_index(&self) -> i16 {
+        self.field(5)
+    }
+
+    /// Returns the unknown value at `0xC`.
+    pub fn unk_c(&self) -> i16 {
+        self.field(6)
+    }
+
+    /// Returns the unknown value at `0xE`.
+    pub fn unk_e(&self) -> i16 {
+        self.field(7)
+    }
+
+    /// Decodes every field into an owned entry.
+    pub fn to_entry(&self) -> GridEntry {
+        GridEntry {
+            enabled: self.enabled(),
+            scene_index: self.scene_index(),
+            grid_x: self.grid_x(),
+            grid_y: self.grid_y(),
+            main_image_index: self.main_image_index(),
+            mask_image_index: self.mask_image_index(),
+            _unk_c: self.unk_c(),
+            _unk_e: self.unk_e(),
+        }
+    }
+}
+
+/// Iterates over the grid entries of a file without copying them.
+/// Only the header is parsed up front.
+/// 
+/// ### Parameters
+/// - `bgst_contents`: The raw data of a bgst3 file. Only the header and entry table are needed.
+/// 
+/// ### Returns
+/// - a view of every entry, in order
+pub fn entry_views(bgst_contents: &[u8]) -> Result<impl Iterator<Item = GridEntryView<'_>>> {
+    if !Header::is_valid(bgst_contents) {
+        bail!(BgstError::InvalidFile("file is not a valid BGST file".to_string()));
+    }
+
+    let header = Header::from_validated_header_bytes(bgst_contents);
+    let end = entry_offset(&header, entry_count(&header));
+    if bgst_contents.len() < end {
+        bail!(BgstError::InvalidFile("file ends inside its entry table".to_string()));
+    }
+
+    Ok(bgst_contents[header.info_offset.min(end)..end]
+        .chunks_exact(GRID_ENTRY_SIZE)
+        .map(|bytes| GridEntryView { bytes }))
+}
+
+/// Counts the entries on each scene, reading only the header and entry table.
+/// At most `image_data_offset` bytes are read, so the image data is never touched.
+/// Entries with a scene index out of range are not counted.
+/// 
+/// ### Parameters
+/// - `reader`: Where the file is read from, positioned at its start.
+/// 
+/// ### Returns
+/// - the number of entries on every scene with at least one entry
+pub fn peek_scenes(mut reader: impl Read) -> Result<BTreeMap<SceneIndex, usize>> {
+    let mut contents = vec![0u8; HEADER_SIZE];
+    reader.read_exact(&mut contents)
+        .map_err(|_| BgstError::InvalidFile("file is not a valid BGST file".to_string()))?;
+
+    if !Header::is_valid(&contents) {
+        bail!(BgstError::InvalidFile("file is not a valid BGST file".to_string()));
+    }
+
+    let header = Header::from_validated_header_bytes(&contents);
+    let remaining = header.image_data_offset.saturating_sub(HEADER_SIZE);
+    reader.take(remaining as u64).read_to_end(&mut contents)?;
+
+    let mut scenes = BTreeMap::new();
+    for entry in Bgst::parse_unchecked(&contents)?.grid_entries {
+        if let Some(scene) = SceneIndex::from_index(entry.scene_index) {
+            *scenes.entry(scene).or_insert(0) += 1;
+        }
+    }
+
+    Ok(scenes)
+}
+
+/// Lays out the entries placed on a scene as rows of cells, covering the grid the header declares.
+/// Like in `composite_scene`, a later entry on the same cell replaces an earlier one.
+/// Entries outside the grid, including those with a negative position, are left out.
+/// 
+/// ### Parameters
+/// - `bgst`: The parsed BGST file.
+/// - `scene`: The scene to lay out.
+/// 
+/// ### Returns
+/// - `grid_height` rows of `grid_width` cells, indexed as `grid[y][x]`, holding the entry placed there
+pub fn grid(bgst: &Bgst, scene: SceneIndex) -> Vec<Vec<Option<&GridEntry>>> {
+    let columns = bgst.header.grid_width as usize;
+    let rows = bgst.header.grid_height as usize;
+    let mut grid = vec![vec![None; columns]; rows];
+
+    for entry in bgst.grid_entries.iter().filter(|entry| entry.scene_index == scene as i16) {
+        let (Ok(x), Ok(y)) = (usize::try_from(entry.grid_x), usize::try_from(entry.grid_y)) else {
+            continue;
+        };
+
+        if let Some(cell) = grid.get_mut(y).and_then(|row| row.get_mut(x)) {
+            *cell = Some(entry);
+        }
+    }
+
+    grid
+}
+
+/// Computes the pixel rectangle covered by the tiles placed on a scene.
+/// Entries with a negative grid position are not placed, the same as in `composite_scene`.
+/// 
+/// ### Parameters
+/// - `bgst`: The parsed BGST file.
+/// - `scene`: The scene to measure.
+/// 
+/// ### Returns
+/// - `(x, y, w, h)` in pixels, or `None` if no tiles are placed on the scene
+pub fn scene_pixel_bounds(bgst: &Bgst, scene: SceneIndex) -> Option<(u32, u32, u32, u32)> {
+    let mut placed = bgst.grid_entries
+        .iter()
+        .filter(|entry| entry.scene_index == scene as i16)
+        .filter(|entry| entry.grid_x >= 0 && entry.grid_y >= 0)
+        .map(|entry| (entry.grid_x as u32, entry.grid_y as u32));
+
+    let (first_x, first_y) = placed.next()?;
+    let (min_x, min_y, max_x, max_y) = placed.fold(
+        (first_x, first_y, first_x, first_y),
+        |(min_x, min_y, max_x, max_y), (x, y)| (min_x.min(x), min_y.min(y), max_x.max(x), max_y.max(y))
+    );
+
+    let width = bgst.header.image_width;
+    let height = bgst.header.image_height;
+
+    Some((
+        min_x * width,
+        min_y * height,
+        (max_x - min_x + 1) * width,
+        (max_y - min_y + 1) * height
+    ))
+}
+
+/// A rectangular range of grid cells.
+/// 
+/// ### Fields
+/// - `x`: The first horizontal grid position in the range.
+/// - `y`: The first vertical grid position in the range.
+/// - `width`: The number of cells the range spans horizontally.
+/// - `height`: The number of cells the range spans vertically.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct GridRect {
+    pub x: i16,
+    pub y: i16,
+    pub width: i16,
+    pub height: i16,
+}
+
+impl GridRect {
+    /// Returns if a grid position lies within the range.
+    /// 
+    /// ### Parameters
+    /// - `grid_x`: The horizontal grid position.
+    /// - `grid_y`: The vertical grid position.
+    /// 
+    /// ### Returns
+    /// `true` if the position is inside the range.
+    pub fn contains(&self, grid_x: i16, grid_y: i16) -> bool {
+        let (x, y) = (self.x as i32, self.y as i32);
+        let (grid_x, grid_y) = (grid_x as i32, grid_y as i32);
+
+        grid_x >= x && grid_x < x + self.width as i32
+            && grid_y >= y && grid_y < y + self.height as i32
+    }
+}
+
+/// Which scenes entries are decoded from.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum SceneFilter {
+    /// Only scenes whose layer the header marks as enabled, which is what the game renders.
+    #[default]
+    EnabledLayers,
+    /// Every scene, whether or not its layer is enabled.
+    All,
+    /// Only the given scenes, whether or not their layers are enabled.
+    Only(Vec<SceneIndex>),
+}
+
+impl SceneFilter {
+    /// Returns if entries on a scene should be decoded.
+    /// 
+    /// ### Parameters
+    /// - `header`: The header of the file the entry is from.
+    /// - `scene_index`: The raw scene index of the entry.
+    /// 
+    /// ### Returns
+    /// `true` if the scene is selected by this filter.
+    pub fn allows(&self, header: &Header, scene_index: i16) -> bool {
+        match self {
+            SceneFilter::All => true,
+            SceneFilter::EnabledLayers => SceneIndex::from_index(scene_index)
+                .is_some_and(|scene| header.layer_enabled(scene)),
+            SceneFilter::Only(scenes) => SceneIndex::from_index(scene_index)
+                .is_some_and(|scene| scenes.contains(&scene)),
+        }
+    }
+}
+
+/// Options that control which images are decoded.
+/// 
+/// ### Fields
+/// - `region`: If set, only entries within this range of grid cells are decoded.
+/// - `skip_disabled`: Whether or not entries that aren't enabled are skipped.
+///   Off by default, since disabled entries still reference valid image data.
+///   Entries are skipped only when `enabled` is zero; see `GridEntry::is_enabled`.
+/// - `scenes`: The scenes entries are decoded from. By default, only scenes
+///   whose layer the header marks as enabled.
+/// - `masks_only`: Whether or not only mask images are decoded, skipping main images.
+///   When extracting, masks are written as they are, since there is nothing to apply them to.
+/// - `overrides`: Dimensions and formats to use instead of the usual ones.
+/// - `limits`: The largest image dimensions accepted, checked after overrides are applied.
+/// - `max_images`: If set, at most this many images are decoded, counted after the
+///   other options have selected entries. Entries are taken in order and never split,
+///   so decoding stops at the first entry whose images don't all fit.
+/// - `repair`: Whether or not to decode files that declare one image more than they hold
+///   as if they declared the right count; see `Header::repaired_image_count`. Entries that
+///   use the missing image are skipped as dangling, so the result differs from what the header declares.
+/// - `flip_v`: Whether or not tiles are flipped upside down when they are written or composited,
+///   for engines whose textures start at the bottom left. By default, the first row of a tile
+///   and grid row 0 are at the top, the way the GameCube stores them. Composites also place grid
+///   row 0 at the bottom, so the whole image is flipped. The decoded images themselves aren't flipped.
+/// - `blank_index`: An image index that some files use as an empty placeholder. Entries that use it
+///   as their main image are skipped, so they stay transparent in composites, and entries that use it
+///   as their mask are decoded without one. When only masks are decoded, the main image doesn't matter.
+///   A placeholder is usually referenced by many entries, and often reported as an empty block
+///   by `warnings::collect_warnings`; `bgsttool csv` lists every reference.
+/// - `observer`: If set, is told which entries were selected and about every image block
+///   as it's decoded, such as to show progress; see `timing::DecodeObserver`.
+#[derive(Clone, Debug, Default)]
+pub struct DecodeOptions {
+    pub region: Option<GridRect>,
+    pub skip_disabled: bool,
+    pub scenes: SceneFilter,
+    pub masks_only: bool,
+    pub overrides: FormatOverrides,
+    pub limits: DimensionLimits,
+    pub max_images: Option<usize>,
+    pub repair: bool,
+    pub flip_v: bool,
+    pub blank_index: Option<i16>,
+    pub observer: Option<DecodeObserver>,
+}
+
+impl DecodeOptions {
+    /// Returns if an entry should be decoded.
+    /// 
+    /// ### Parameters
+    /// - `header`: The header of the file the entry is from.
+    /// - `entry`: The entry to check.
+    /// 
+    /// ### Returns
+    /// `true` if the entry is selected by these options.
+    pub fn selects(&self, header: &Header, entry: &GridEntry) -> bool {
+        if self.skip_disabled && !entry.is_enabled() {
+            return false;
+        }
+
+        if !self.scenes.allows(header, entry.scene_index) {
+            return false;
+        }
+
+        // the main image isn't decoded when only masks are, so a blank one doesn't matter
+        if !self.masks_only && self.blank_index.is_some_and(|blank| entry.main_image_index == blank) {
+            return false;
+        }
+
+        match &self.region {
+            Some(region) => region.contains(entry.grid_x, entry.grid_y),
+            None => true,
+        }
+    }
+}
+
+
+
+
+/// A parsed BGST file, without any decoded image data.
+/// 
+/// ### Fields
+/// - `header`: The header of the file.
+/// - `grid_entries`: Every entry in the grid.
+pub struct Bgst {
+    header: Header,
     grid_entries: Vec<GridEntry>,
-    images: Vec<Vec<u8>>
-} 
+}
+
+impl Bgst {
+    /// Parses the header and grid entries of a BGST file,
+    /// rejecting images larger than the default limits.
+    /// 
+    /// ### Parameters
+    /// - `bgst_contents`: The raw data of a bgst3 file.
+    /// 
+    /// ### Returns
+    /// - a `Bgst` struct
+    pub fn parse(bgst_contents: &[u8]) -> Result<Bgst> {
+        Bgst::parse_with_limits(bgst_contents, &DimensionLimits::default())
+    }
+
+    /// Parses the header and grid entries of a BGST file,
+    /// rejecting images larger than the given limits.
+    /// 
+    /// ### Parameters
+    /// - `bgst_contents`: The raw data of a bgst3 file.
+    /// - `limits`: The largest allowed image dimensions.
+    /// 
+    /// ### Returns
+    /// - a `Bgst` struct
+    pub fn parse_with_limits(bgst_contents: &[u8], limits: &DimensionLimits) -> Result<Bgst> {
+        let bgst = Bgst::parse_unchecked(bgst_contents)?;
+        bgst.header.check_dimensions(limits)?;
+        Ok(bgst)
+    }
+
+    /// Parses the header and grid entries of a file whose entries are a different size
+    /// than usual, such as a revision of the format with more fields per entry.
+    /// Only the known fields at the start of each entry are read.
+    /// 
+    /// ### Parameters
+    /// - `bgst_contents`: The raw data of a bgst3 file.
+    /// - `entry_stride`: The distance between entries, in bytes. It must be at least
+    ///   `GRID_ENTRY_SIZE`; `Header::infer_entry_stride` can find it if the entry count is known.
+    /// 
+    /// ### Returns
+    /// - a `Bgst` struct
+    pub fn parse_with_stride(bgst_contents: &[u8], entry_stride: usize) -> Result<Bgst> {
+        let bgst = Bgst::parse_unchecked_with_stride(bgst_contents, entry_stride)?;
+        bgst.header.check_dimensions(&DimensionLimits::default())?;
+        Ok(bgst)
+    }
+
+    /// Parses a file without checking its dimensions.
+    fn parse_unchecked(bgst_contents: &[u8]) -> Result<Bgst> {
+        Bgst::parse_unchecked_with_stride(bgst_contents, GRID_ENTRY_SIZE)
+    }
+
+    /// Parses a file with the given entry stride, without checking its dimensions.
+    fn parse_unchecked_with_stride(bgst_contents: &[u8], entry_stride: usize) -> Result<Bgst> {
+        if entry_stride < GRID_ENTRY_SIZE {
+            bail!(BgstError::InvalidFile(format!(
+                "entries can't be {} bytes apart, since each one is at least {} bytes long",
+                entry_stride,
+                GRID_ENTRY_SIZE
+            )));
+        }
+
+        if !Header::is_valid(bgst_contents) {
+            bail!(BgstError::InvalidFile("file is not a valid BGST file".to_string()));
+        }
+
+        let mut header = Header::from_validated_header_bytes(bgst_contents);
+        header.entry_stride = entry_stride;
+        if bgst_contents.len() < entry_offset(&header, entry_count(&header)) {
+            bail!(BgstError::InvalidFile("file ends inside its entry table".to_string()));
+        }
+
+        let mut grid_entries = Vec::new();
+
+        for index in 0..entry_count(&header) {
+            let current_offset = entry_offset(&header, index);
+            let enabled = BigEndian::read_i16(&bgst_contents[current_offset..current_offset + 2]);
+            let scene_index = BigEndian::read_i16(&bgst_contents[current_offset + 2..current_offset + 4]);
+            let grid_x = BigEndian::read_i16(&bgst_contents[current_offset + 4..current_offset + 6]);
+            let grid_y = BigEndian::read_i16(&bgst_contents[current_offset + 6..current_offset + 8]);
+            let main_image_index = BigEndian::read_i16(&bgst_contents[current_offset + 8..current_offset + 0xA]);
+            let mask_image_index = BigEndian::read_i16(&bgst_contents[current_offset + 0xA..current_offset + 0xC]);
+            let _unk_c = BigEndian::read_i16(&bgst_contents[current_offset + 0xC..current_offset + 0xE]);
+            let _unk_e = BigEndian::read_i16(&bgst_contents[current_offset + 0xE..current_offset + 0x10]);
+            let entry = GridEntry {
+                enabled,
+                scene_index,
+                grid_x,
+                grid_y,
+                main_image_index,
+                mask_image_index,
+                _unk_c,
+                _unk_e
+            };
+            grid_entries.push(entry);
+        }
+
+        Ok(Bgst {
+            header,
+            grid_entries
+        })
+    }
+
+    /// Returns the header of the file.
+    pub fn header(&self) -> &Header {
+        &self.header
+    }
+
+    /// Returns every entry in the grid.
+    pub fn grid_entries(&self) -> &[GridEntry] {
+        &self.grid_entries
+    }
+}
+
+/// An image index in a grid entry that does not refer to an image in the file.
+/// 
+/// ### Fields
+/// - `entry_index`: The index of the entry in the grid.
+/// - `image_index`: The out-of-range image index.
+/// - `is_mask`: Whether the index is the entry's mask image index.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub struct DanglingReference {
+    pub entry_index: usize,
+    pub image_index: i16,
+    pub is_mask: bool,
+}
+
+/// Collects the out-of-range image indices of a single entry.
+fn entry_dangling_references(
+    header: &Header,
+    entry_index: usize,
+    entry: &GridEntry
+) -> Vec<DanglingReference> {
+    [(entry.main_image_index, false), (entry.mask_image_index, true)]
+        .into_iter()
+        .filter(|(image_index, _)| *image_index > -1 && !header.has_image(*image_index))
+        .map(|(image_index, is_mask)| DanglingReference {
+            entry_index,
+            image_index,
+            is_mask
+        })
+        .collect()
+}
+
+/// Finds every entry that references an image beyond the file's image count.
+/// These entries are skipped when decoding.
+/// 
+/// ### Parameters
+/// - `bgst`: The parsed BGST file.
+/// 
+/// ### Returns
+/// - every dangling image index, in entry order
+pub fn dangling_references(bgst: &Bgst) -> Vec<DanglingReference> {
+    bgst.grid_entries
+        .iter()
+        .enumerate()
+        .flat_map(|(entry_index, entry)| entry_dangling_references(&bgst.header, entry_index, entry))
+        .collect()
+}
+
+/// Finds every entry whose scene index isn't one of the known scenes.
+/// These entries are either corrupt or use a scene this crate doesn't know,
+/// and are never composited.
+///
+/// ### Parameters
+/// - `bgst`: The parsed BGST file.
+///
+/// ### Returns
+/// - the index of every entry with an unknown scene, in entry order
+pub fn validate_scenes(bgst: &Bgst) -> Vec<usize> {
+    bgst.grid_entries
+        .iter()
+        .enumerate()
+        .filter(|(_, entry)| SceneIndex::from_index(entry.scene_index).is_none())
+        .map(|(entry_index, _)| entry_index)
+        .collect()
+}
+
+/// Returns if any entry has a mask, such as to decide whether applying masks is worth offering.
+/// Mask indices that are out of range still count, since the entry means to use a mask.
+///
+/// ### Parameters
+/// - `bgst`: The parsed BGST file.
+///
+/// ### Returns
+/// `true` if any entry's mask image index isn't negative.
+pub fn uses_masks(bgst: &Bgst) -> bool {
+    bgst.grid_entries.iter().any(|entry| entry.mask_image_index >= 0)
+}
+
+/// The distinct values of each unknown field across a batch of files,
+/// with how often each value was seen.
+/// 
+/// ### Fields
+/// - `file_count`: The number of files that were parsed.
+/// - `skipped_files`: The number of files that were not valid BGST files.
+/// - `unk_4`: The values of the header field at offset `0x4`.
+/// - `unk_c`: The values of the entry field at offset `0xC`.
+/// - `unk_e`: The values of the entry field at offset `0xE`.
+#[derive(Clone, Debug, Default)]
+pub struct UnknownStats {
+    pub file_count: usize,
+    pub skipped_files: usize,
+    pub unk_4: BTreeMap<u32, usize>,
+    pub unk_c: BTreeMap<i16, usize>,
+    pub unk_e: BTreeMap<i16, usize>,
+}
+
+impl fmt::Display for UnknownStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{} files parsed, {} skipped", self.file_count, self.skipped_files)?;
+
+        writeln!(f, "header 0x4:")?;
+        for (value, count) in &self.unk_4 {
+            writeln!(f, "  0x{:08X}: {}", value, count)?;
+        }
+
+        writeln!(f, "entry 0xC:")?;
+        for (value, count) in &self.unk_c {
+            writeln!(f, "  0x{:04X}: {}", value, count)?;
+        }
+
+        writeln!(f, "entry 0xE:")?;
+        for (value, count) in &self.unk_e {
+            writeln!(f, "  0x{:04X}: {}", value, count)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Tallies the values of every unknown field across a batch of files.
+/// Only the header and grid entries are parsed; nothing is decoded.
+/// 
+/// ### Parameters
+/// - `files`: The raw data of each bgst3 file.
+/// 
+/// ### Returns
+/// - an `UnknownStats` struct
+pub fn analyze_unknowns(files: &[Vec<u8>]) -> UnknownStats {
+    let mut stats = UnknownStats::default();
+
+    for file in files {
+        let Ok(bgst) = Bgst::parse(file) else {
+            stats.skipped_files += 1;
+            continue;
+        };
+
+        stats.file_count += 1;
+        *stats.unk_4.entry(bgst.header._unk_4).or_default() += 1;
+
+        for entry in &bgst.grid_entries {
+            *stats.unk_c.entry(entry._unk_c).or_default() += 1;
+            *stats.unk_e.entry(entry._unk_e).or_default() += 1;
+        }
+    }
+
+    stats
+}
+
+/// Returns the encoded data of one image, for use with other decoders.
+/// 
+/// ### Parameters
+/// - `bgst`: The parsed BGST file.
+/// - `data`: The raw data of the same bgst3 file.
+/// - `index`: The image index.
+/// 
+/// ### Returns
+/// - the bytes of the image block
+pub fn image_block<'a>(bgst: &Bgst, data: &'a [u8], index: usize) -> Result<&'a [u8]> {
+    bgst.header.image_block(data, index)
+}
+
+/// Counts the images that `get_raw_images` will decode.
+/// Every entry contributes one image for a valid main image index
+/// and one for a valid mask image index.
+/// 
+/// ### Parameters
+/// - `bgst`: The parsed BGST file.
+/// 
+/// ### Returns
+/// - the number of images that will be decoded
+pub fn decodable_image_count(bgst: &Bgst) -> usize {
+    let options = DecodeOptions::default();
+
+    bgst.grid_entries
+        .iter()
+        .filter(|entry| options.selects(&bgst.header, entry))
+        .map(|entry| {
+            bgst.header.has_image(entry.main_image_index) as usize
+                + bgst.header.has_image(entry.mask_image_index) as usize
+        })
+        .sum()
+}
+
+/// Estimates how much memory the images `get_raw_images` decodes will take up, as RGBA,
+/// such as to warn before decoding a large file. The overhead of the lists holding them isn't counted.
+/// Files that are too large can be decoded an image at a time with `for_each_raw_image`.
+/// 
+/// ### Parameters
+/// - `bgst`: The parsed BGST file.
+/// 
+/// ### Returns
+/// - the number of bytes the decoded images take up
+pub fn estimated_decoded_bytes(bgst: &Bgst) -> u64 {
+    decodable_image_count(bgst) as u64
+        * bgst.header.image_width as u64
+        * bgst.header.image_height as u64
+        * 4
+}
+
+/// Finds the distinct image indices entries reference, as main images and as masks.
+/// Only indices within the file's image count are included; blocks in neither set
+/// are unused, and out of range indices are reported by `dangling_references`.
+/// 
+/// ### Parameters
+/// - `bgst`: The parsed BGST file.
+/// 
+/// ### Returns
+/// - the indices referenced as main images, and the indices referenced as masks
+pub fn referenced_indices(bgst: &Bgst) -> (BTreeSet<i16>, BTreeSet<i16>) {
+    let mut mains = BTreeSet::new();
+    let mut masks = BTreeSet::new();
+
+    for entry in &bgst.grid_entries {
+        if bgst.header.has_image(entry.main_image_index) {
+            mains.insert(entry.main_image_index);
+        }
+        if bgst.header.has_image(entry.mask_image_index) {
+            masks.insert(entry.mask_image_index);
+        }
+    }
+
+    (mains, masks)
+}
+
+/// Removes every image block that no entry references and renumbers the entries'
+/// image indices to match, updating the image count. Blocks keep their relative order,
+/// and anything after the image data is dropped. Out of range indices are left as they are.
+/// 
+/// ### Parameters
+/// - `bgst`: The parsed file, which is updated to match the compacted data.
+/// - `data`: The raw data of the file, which is compacted in place.
+pub fn compact_images(bgst: &mut Bgst, data: &mut Vec<u8>) -> Result<()> {
+    bgst.header.check_file_size(data.len())?;
+
+    let (mains, masks) = referenced_indices(bgst);
+    let referenced: BTreeSet<i16> = mains.union(&masks).copied().collect();
+    let header = &bgst.header;
+
+    let renumbered: HashMap<i16, i16> = referenced
+        .iter()
+        .enumerate()
+        .map(|(new_index, old_index)| (*old_index, new_index as i16))
+        .collect();
+
+    let image_data_offset = header.image_data_offset;
+    let block_size = header.block_size;
+    for (new_index, old_index) in referenced.iter().enumerate() {
+        let from = image_data_offset + *old_index as usize * block_size;
+        let to = image_data_offset + new_index * block_size;
+        data.copy_within(from..from + block_size, to);
+    }
+    data.truncate(image_data_offset + referenced.len() * block_size);
+
+    for (i, entry) in bgst.grid_entries.iter_mut().enumerate() {
+        for index in [&mut entry.main_image_index, &mut entry.mask_image_index] {
+            if let Some(new_index) = renumbered.get(index) {
+                *index = *new_index;
+            }
+        }
+
+        let offset = entry_offset(&bgst.header, i);
+        data[offset..offset + GRID_ENTRY_SIZE].copy_from_slice(&entry.to_be_bytes());
+    }
+
+    bgst.header.image_count = referenced.len() as u32;
+    BigEndian::write_u32(&mut data[0x18..0x1C], bgst.header.image_count);
+
+    Ok(())
+}
+
+/// Attempts to return the RGBA of every image.
+/// 
+/// ### Parameters
+/// - `bgst_contents`: The raw data of a bgst3 file.
+/// 
+/// ### Returns
+/// - an `ImageList` struct
+pub fn get_raw_images(
+        bgst_contents: &[u8]
+    ) -> Result<ImageList> {
+    get_raw_images_with(bgst_contents, &DecodeOptions::default())
+}
+
+/// Attempts to return the RGBA of every image, decoding main and mask images
+/// in the given formats instead of CMPR and I4.
+///
+/// ### Parameters
+/// - `bgst_contents`: The raw data of a bgst3 file.
+/// - `main_format`: The format of main images.
+/// - `mask_format`: The format of mask images.
+///
+/// ### Returns
+/// - an `ImageList` struct
+pub fn get_raw_images_as(
+    bgst_contents: &[u8],
+    main_format: TextureFormat,
+    mask_format: TextureFormat
+) -> Result<ImageList> {
+    let options = DecodeOptions {
+        overrides: FormatOverrides {
+            main_format: Some(main_format),
+            mask_format: Some(mask_format),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    get_raw_images_with(bgst_contents, &options)
+}
+
+/// Attempts to return the RGBA of every image selected by the given options.
+/// Entries that are not selected are skipped before decoding. Images are stored
+/// per entry, its main image followed by its mask image, so an image used by several
+/// entries is stored once for each; see `ImageList::main_count` and `ImageList::mask_count`.
+/// 
+/// ### Parameters
+/// - `bgst_contents`: The raw data of a bgst3 file.
+/// - `options`: Options that control which images are decoded.
+/// 
+/// ### Returns
+/// - an `ImageList` struct
+pub fn get_raw_images_with(
+        bgst_contents: &[u8],
+        options: &DecodeOptions
+    ) -> Result<ImageList> {
+    let mut images = Vec::new();
+    let (header, selected, dangling_references) = decode_selected(
+        bgst_contents,
+        options,
+        |_, decoded, _| {
+            images.push(decoded);
+            Ok(())
+        }
+    )?;
+    let (entry_indices, grid_entries): (Vec<usize>, Vec<GridEntry>) = selected.into_iter().unzip();
+
+    let main_count = if options.masks_only {
+        0
+    } else {
+        grid_entries.iter().filter(|entry| header.has_image(entry.main_image_index)).count()
+    };
+    let mask_count = grid_entries.iter().filter(|entry| header.has_image(entry.mask_image_index)).count();
+    let skipped_entries = entry_count(&header) - grid_entries.len();
+
+    let result = ImageList {
+        image_width: header.image_width,
+        image_height: header.image_height,
+        grid_width: header.grid_width,
+        grid_height: header.grid_height,
+        image_count: header.image_count,
+        unk_4: header._unk_4,
+        layer_enabled: header._layer_enabled,
+        grid_entries,
+        entry_indices,
+        images,
+        dangling_references,
+        masks_only: options.masks_only,
+        flip_v: options.flip_v,
+        max_canvas_pixels: options.limits.max_canvas_pixels,
+        main_count,
+        mask_count,
+        skipped_entries
+    };
+    Ok(result)
+} 
+
+/// Attempts to return the RGBA of every image selected by the given options,
+/// with every row padded to the given pitch, such as for uploading straight to GPU textures.
+/// Images are in the same order `get_raw_images_with` stores them. Padding bytes are zero.
+///
+/// ### Parameters
+/// - `bgst_contents`: The raw data of a bgst3 file.
+/// - `options`: Options that control which images are decoded.
+/// - `row_pitch`: The length of each row in bytes, at least 4 bytes per pixel of the image width.
+///
+/// ### Returns
+/// - the RGBA of every image, with padded rows
+pub fn get_raw_images_with_pitch(
+    bgst_contents: &[u8],
+    options: &DecodeOptions,
+    row_pitch: usize
+) -> Result<Vec<Vec<u8>>> {
+    let mut images = Vec::new();
+    for_each_raw_image(bgst_contents, options, |header, decoded| {
+        images.push(pad_rows(&decoded, header.image_width, row_pitch)?);
+        Ok(())
+    })?;
+    Ok(images)
+}
+
+/// Copies an RGBA image, padding each row to the given pitch with zero bytes.
+///
+/// ### Parameters
+/// - `image`: The tightly packed RGBA of the image.
+/// - `width`: The width of the image, in pixels.
+/// - `row_pitch`: The length of each row in bytes, at least 4 bytes per pixel of `width`.
+///
+/// ### Returns
+/// - the RGBA of the image, with padded rows
+pub fn pad_rows(image: &[u8], width: u32, row_pitch: usize) -> Result<Vec<u8>> {
+    let row_len = width as usize * 4;
+    if row_pitch < row_len {
+        bail!("a row pitch of {} bytes is shorter than the {} bytes of each row", row_pitch, row_len);
+    }
+
+    if row_len == 0 {
+        return Ok(Vec::new());
+    }
+
+    let mut padded = vec![0; image.len() / row_len * row_pitch];
+    for (row, padded_row) in image.chunks_exact(row_len).zip(padded.chunks_exact_mut(row_pitch)) {
+        padded_row[..row_len].copy_from_slice(row);
+    }
+    Ok(padded)
+}
+
+/// Decodes the RGBA of every image selected by the given options, one at a time.
+/// Each image is handed to `on_image` as soon as it is decoded and is not kept afterwards.
+/// Images are visited in the same order `get_raw_images` stores them.
+/// 
+/// ### Parameters
+/// - `bgst_contents`: The raw data of a bgst3 file.
+/// - `options`: Options that control which images are decoded.
+/// - `on_image`: Called with the file header and the RGBA of each decoded image.
+/// 
+/// ### Returns
+/// - the image indices that were skipped because they were out of range
+pub fn for_each_raw_image(
+    bgst_contents: &[u8],
+    options: &DecodeOptions,
+    mut on_image: impl FnMut(&Header, Vec<u8>) -> Result<()>
+) -> Result<Vec<DanglingReference>> {
+    let (_, _, dangling_references) = decode_selected(bgst_contents, options, |header, decoded, _| on_image(header, decoded))?;
+    Ok(dangling_references)
+}
+
+/// Returns the encoded data of one image, checking that its block is large enough
+/// to hold an image of the header's dimensions in the given format.
+fn encoded_image<'a>(
+    header: &Header,
+    bgst_contents: &'a [u8],
+    entry_index: usize,
+    image_index: i16,
+    format: TextureFormat
+) -> Result<&'a [u8]> {
+    let encoded = header.image_block(bgst_contents, image_index as usize)?;
+
+    let required = texture::image_size(format, header.image_width, header.image_height);
+    if encoded.len() < required {
+        bail!(BgstError::Decode(format!(
+            "entry {}: image {} needs {} bytes as {:?}, but image blocks are only {} bytes",
+            entry_index,
+            image_index,
+            required,
+            format,
+            encoded.len()
+        )));
+    }
+
+    Ok(&encoded[..required])
+}
+
+/// Decodes one image block to RGBA in the header's layout,
+/// checking that the decoder produced a full image, and tells the header's observer.
+fn decode_block(
+    header: &Header,
+    bgst_contents: &[u8],
+    entry_index: usize,
+    image_index: i16,
+    format: TextureFormat,
+    is_mask: bool
+) -> Result<Vec<u8>> {
+    let start = std::time::Instant::now();
+    let decoded = decode_block_in_layout(header, bgst_contents, entry_index, image_index, format)?;
+
+    if let Some(observer) = &header.observer {
+        let timing = BlockTiming { entry_index, image_index, is_mask, duration: start.elapsed() };
+        observer.notify(&DecodeEvent::Decoded(timing));
+    }
+
+    Ok(decoded)
+}
+
+/// Decodes one image block to RGBA in the header's layout.
+fn decode_block_in_layout(
+    header: &Header,
+    bgst_contents: &[u8],
+    entry_index: usize,
+    image_index: i16,
+    format: TextureFormat
+) -> Result<Vec<u8>> {
+    let encoded = encoded_image(header, bgst_contents, entry_index, image_index, format)?;
+    let decode_linear = || {
+        let tiled = swizzle::tile_linear(encoded, header.image_width, header.image_height, format);
+        decode_tiles(header, &tiled, entry_index, image_index, format)
+    };
+
+    match header.layout {
+        TileLayout::Tiled => decode_tiles(header, encoded, entry_index, image_index, format),
+        TileLayout::Linear => decode_linear(),
+        TileLayout::Detect => {
+            let tiled = decode_tiles(header, encoded, entry_index, image_index, format)?;
+            let linear = decode_linear()?;
+            let roughness = |rgba: &[u8]| swizzle::roughness(rgba, header.image_width, header.image_height);
+
+            Ok(if roughness(&linear) < roughness(&tiled) { linear } else { tiled })
+        }
+    }
+}
+
+/// Decodes tiled image data to RGBA, checking that the decoder produced a full image.
+/// Dimensions that aren't a whole number of tiles, such as 120x96 tiles of CMPR,
+/// are stored padded to whole tiles; both decoders decode the padded image and crop it.
+fn decode_tiles(
+    header: &Header,
+    encoded: &[u8],
+    entry_index: usize,
+    image_index: i16,
+    format: TextureFormat
+) -> Result<Vec<u8>> {
+    let decoded = texture::decode(encoded, header.image_width, header.image_height, format)?;
+
+    let expected = header.image_width as usize * header.image_height as usize * 4;
+    if decoded.len() != expected {
+        bail!(BgstError::Decode(format!(
+            "entry {}: decoding image {} as {:?} produced {} bytes, expected {}",
+            entry_index,
+            image_index,
+            format,
+            decoded.len(),
+            expected
+        )));
+    }
+
+    Ok(decoded)
+}
+
+/// Decodes the main image of an entry and applies its mask, if it has one.
+/// 
+/// ### Returns
+/// - the RGBA of the masked image, or `None` if the entry has no main image
+fn decode_masked_entry(
+    header: &Header,
+    bgst_contents: &[u8],
+    entry_index: usize,
+    entry: &GridEntry,
+    overrides: &FormatOverrides
+) -> Result<Option<Vec<u8>>> {
+    if !header.has_image(entry.main_image_index) {
+        return Ok(None);
+    }
+
+    let main = decode_block(
+        header,
+        bgst_contents,
+        entry_index,
+        entry.main_image_index,
+        overrides.format_for(entry.main_image_index as usize, false),
+        false
+    )?;
+
+    if !header.has_image(entry.mask_image_index) {
+        return Ok(Some(main));
+    }
+
+    let mask = decode_block(
+        header,
+        bgst_contents,
+        entry_index,
+        entry.mask_image_index,
+        overrides.format_for(entry.mask_image_index as usize, true),
+        true
+    )?;
+
+    apply_mask(&main, &mask, header.image_width, header.image_height).map(Some)
+}
+
+/// The header with any overrides applied, the selected entries with their indices
+/// and the dangling image indices.
+type Selection = (Header, Vec<(usize, GridEntry)>, Vec<DanglingReference>);
+
+/// Parses a file and picks out the entries selected by the given options,
+/// checking that the file is long enough for the images it declares.
+fn select_entries(
+    bgst_contents: &[u8],
+    options: &DecodeOptions
+) -> Result<Selection> {
+    let entry_stride = options.overrides.entry_stride.unwrap_or(GRID_ENTRY_SIZE);
+    let Bgst { mut header, grid_entries } = Bgst::parse_unchecked_with_stride(bgst_contents, entry_stride)?;
+
+    if let Some(image_width) = options.overrides.image_width {
+        header.image_width = image_width;
+    }
+    if let Some(image_height) = options.overrides.image_height {
+        header.image_height = image_height;
+    }
+    if let Some(block_size) = options.overrides.block_size {
+        header.block_size = block_size;
+    }
+    if let Some(layout) = options.overrides.layout {
+        header.layout = layout;
+    }
+    if options.repair {
+        if let Some(image_count) = header.repaired_image_count(bgst_contents.len()) {
+            header.image_count = image_count;
+        }
+    }
+
+    header.check_dimensions(&options.limits)?;
+
+    let mut dangling_references = Vec::new();
+    let mut remaining_images = options.max_images.unwrap_or(usize::MAX);
+    let selected: Vec<(usize, GridEntry)> = grid_entries
+        .into_iter()
+        .enumerate()
+        .filter(|(_, entry)| options.selects(&header, entry))
+        .map(|(entry_index, mut entry)| {
+            if options.blank_index.is_some_and(|blank| entry.mask_image_index == blank) {
+                entry.mask_image_index = -1;
+            }
+            (entry_index, entry)
+        })
+        .take_while(|(_, entry)| {
+            let main = !options.masks_only && header.has_image(entry.main_image_index);
+            let images = main as usize + header.has_image(entry.mask_image_index) as usize;
+
+            let fits = images <= remaining_images;
+            if fits {
+                remaining_images -= images;
+            }
+            fits
+        })
+        .inspect(|(entry_index, entry)| {
+            dangling_references.extend(entry_dangling_references(&header, *entry_index, entry));
+        })
+        .collect();
+          
+    header.check_file_size(bgst_contents.len())?;
+
+    header.observer = options.observer.clone();
+    if let Some(observer) = &header.observer {
+        let images = options.max_images.unwrap_or(usize::MAX) - remaining_images;
+        observer.notify(&DecodeEvent::Selected { images });
+    }
+
+    Ok((header, selected, dangling_references))
+}
+
+/// Decodes every image selected by the given options and hands each one to `on_image`,
+/// along with whether or not it's a mask.
+/// 
+/// ### Returns
+/// - the header, the selected entries with their indices in the file, and the dangling image indices
+fn decode_selected(
+    bgst_contents: &[u8],
+    options: &DecodeOptions,
+    mut on_image: impl FnMut(&Header, Vec<u8>, bool) -> Result<()>
+) -> Result<Selection> {
+    let (header, selected, dangling_references) = select_entries(bgst_contents, options)?;
+
+    for (entry_index, entry) in &selected {
+        if !options.masks_only && header.has_image(entry.main_image_index) {
+            let decoded = decode_block(
+                &header,
+                bgst_contents,
+                *entry_index,
+                entry.main_image_index,
+                options.overrides.format_for(entry.main_image_index as usize, false),
+                false
+            )?;
+            on_image(&header, decoded, false)?;
+        }
+        if header.has_image(entry.mask_image_index) {
+            let decoded = decode_block(
+                &header,
+                bgst_contents,
+                *entry_index,
+                entry.mask_image_index,
+                options.overrides.format_for(entry.mask_image_index as usize, true),
+                true
+            )?;
+            on_image(&header, decoded, true)?;
+        }
+    }
+
+    Ok((header, selected, dangling_references))
+}
+
+/// Options that control how a file is extracted.
+/// 
+/// ### Fields
+/// - `decode`: Options that control which images are decoded.
+/// - `do_mask`: Whether or not masks should be applied to their images.
+/// - `write_alpha`: Whether or not to also write the alpha channel of every main image
+///   and finished tile as a grayscale `_alpha.png`, after masks are applied.
+///   A mask's alpha only repeats its intensity, so theirs isn't written.
+/// - `unique_images`: Whether or not to write every distinct image once,
+///   named by its image index, instead of once per entry. Masks are not applied.
+/// - `indexed`: Whether or not to write images with at most 256 colors as
+///   8-bit indexed PNGs. Images with more colors are written as RGBA.
+/// - `flatten`: Whether or not to write one finished tile per entry, with its mask
+///   applied, instead of its main and mask images separately.
+/// - `format`: The file format images are written in.
+/// - `entry_names`: Whether or not to name images after their entry, such as
+///   `entry12_main` and `entry12_mask`, instead of numbering them in decode order.
+///   Masks are not applied.
+/// - `check_masks`: Whether or not to warn about tiles that are fully transparent
+///   once their mask is applied. This decodes every masked entry an extra time.
+/// - `annotate`: Whether or not to draw each entry's index and grid coordinate onto its images.
+///   Images keep the names the other options give them. Unique images and APNGs aren't annotated.
+/// - `strip_alpha`: Whether or not to make every pixel of written main images and finished tiles
+///   fully opaque, showing what masks and transparent pixels hide. Masks are written as they are. This undoes masking, so it's meant to be used without it.
+/// - `verbose`: Whether or not to print how long every image block takes to decode
+///   as it's extracted, and the total at the end.
+/// - `hex_dump`: Whether or not to print a hex dump of the header of files that can't be parsed,
+///   or are truncated, and of the bytes around where they go wrong.
+/// - `embed_meta`: Whether or not to record where every PNG came from in `tEXt` chunks;
+///   see `provenance::TileProvenance`. Images keep the names the other options give them.
+///   Unique images, APNGs and DDS textures don't record it.
+/// - `source_name`: The name recorded as the source of embedded metadata.
+///   `extract_bgst_to` uses the name of the source when this isn't set.
+/// - `apng`: Whether or not to write every entry's main image and mask together
+///   as a two-frame APNG; see `apng::write_entry_apngs`. APNGs are always PNGs,
+///   so `format`, `write_alpha`, `annotate`, `embed_meta` and `flatten` are ignored.
+#[derive(Clone, Debug, Default)]
+pub struct ExtractOptions {
+    pub decode: DecodeOptions,
+    pub do_mask: bool,
+    pub write_alpha: bool,
+    pub unique_images: bool,
+    pub indexed: bool,
+    pub flatten: bool,
+    pub format: OutputFormat,
+    pub entry_names: bool,
+    pub check_masks: bool,
+    pub annotate: bool,
+    pub strip_alpha: bool,
+    pub verbose: bool,
+    pub hex_dump: bool,
+    pub embed_meta: bool,
+    pub source_name: Option<String>,
+    pub apng: bool,
+}
+
+/// The file formats images can be extracted to.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// PNG images.
+    #[default]
+    Png,
+    /// DDS textures. Unmodified CMPR images are transcoded to DXT1 without loss,
+    /// and anything else is stored as uncompressed BGRA.
+    Dds,
+}
+
+/// Copies an image with every pixel made fully opaque, keeping its colors.
+/// 
+/// ### Parameters
+/// - `image`: The image to copy.
+/// 
+/// ### Returns
+/// - the opaque image
+pub fn strip_alpha(image: &RgbaImage) -> RgbaImage {
+    let mut opaque = image.clone();
+    for pixel in opaque.pixels_mut() {
+        pixel[3] = 0xFF;
+    }
+    opaque
+}
+
+/// Splits the alpha channel of an image into its own grayscale image.
+/// 
+/// ### Parameters
+/// - `image`: The image.
+/// 
+/// ### Returns
+/// - the alpha channel
+pub fn alpha_channel(image: &RgbaImage) -> GrayImage {
+    GrayImage::from_fn(image.width(), image.height(), |x, y| {
+        Luma([image.get_pixel(x, y)[3]])
+    })
+}
+
+/// Applies the options that change how written images look, `strip_alpha` and `flip_v`.
+/// A mask's alpha only repeats its intensity, so only main images and finished tiles have their alpha stripped.
+fn output_image<'a>(image: &'a RgbaImage, is_mask: bool, options: &ExtractOptions) -> Cow<'a, RgbaImage> {
+    let mut image = Cow::Borrowed(image);
+    if options.strip_alpha && !is_mask {
+        image = Cow::Owned(strip_alpha(&image));
+    }
+    if options.decode.flip_v {
+        image = Cow::Owned(imageops::flip_vertical(&*image));
+    }
+    image
+}
+
+/// Writes an image to `folder_name/name.png`, or `name.dds` when extracting to DDS,
+/// along with its alpha channel as a PNG if requested and the image isn't a mask.
+fn write_tile(
+    folder_name: &str,
+    name: &str,
+    image: &RgbaImage,
+    is_mask: bool,
+    options: &ExtractOptions
+) -> Result<()> {
+    write_tile_with_provenance(folder_name, name, image, is_mask, options, None)
+}
+
+/// Writes an image like `write_tile`, recording where it came from in the PNG if it's given.
+fn write_tile_with_provenance(
+    folder_name: &str,
+    name: &str,
+    image: &RgbaImage,
+    is_mask: bool,
+    options: &ExtractOptions,
+    provenance: Option<&TileProvenance>
+) -> Result<()> {
+    let image = output_image(image, is_mask, options);
+    let image = image.as_ref();
+
+    if options.format == OutputFormat::Dds {
+        dds::write_dds(&(folder_name.to_owned() + "/" + name + ".dds"), &dds::encode_rgba_dds(image))?;
+    } else {
+        write_png(folder_name, name, image, options, provenance)?;
+    }
+
+    if options.write_alpha && !is_mask {
+        let filename = folder_name.to_owned() + "/" + name + "_alpha.png";
+        alpha_channel(image).save_with_format(&filename, ImageFormat::Png)
+            .map_err(|err| BgstError::Write(format!("failed to write {}: {}", filename, err)))?;
+    }
+
+    Ok(())
+}
+
+/// Encodes an image as a PNG and writes it to `folder_name/name.png`.
+fn write_png(
+    folder_name: &str,
+    name: &str,
+    image: &RgbaImage,
+    options: &ExtractOptions,
+    provenance: Option<&TileProvenance>
+) -> Result<()> {
+    let filename = folder_name.to_owned() + "/" + name + ".png";
+    let write_error = |err: &dyn fmt::Display| BgstError::Write(format!("failed to write {}: {}", filename, err));
+    let chunks = provenance.map(TileProvenance::text_chunks).unwrap_or_default();
+
+    let png = match options.indexed.then(|| indexed::encode_indexed_png_with_text(image, &chunks)).transpose()?.flatten() {
+        Some(png) => Some(png),
+        None if !chunks.is_empty() => Some(provenance::encode_png_with_text(image, &chunks)?),
+        None => None,
+    };
+
+    match png {
+        Some(png) => fs::write(&filename, png).map_err(|err| write_error(&err))?,
+        None => image.save_with_format(&filename, ImageFormat::Png).map_err(|err| write_error(&err))?,
+    }
+
+    Ok(())
+}
+
+/// Decodes, encodes and writes every selected image as a PNG in one pass,
+/// so only one decoded and one encoded image are held in memory at a time.
+/// Files are named `0.png`, `1.png`, ... in decode order. Masks are not applied.
+/// 
+/// ### Parameters
+/// - `bgst_contents`: The raw data of a bgst3 file.
+/// - `options`: Options that control which images are decoded and what is written.
+/// - `folder_name`: The existing folder to write the images into.
+/// 
+/// ### Returns
+/// - the image indices that were skipped because they were out of range
+pub fn write_png_images(
+    bgst_contents: &[u8],
+    options: &ExtractOptions,
+    folder_name: &str
+) -> Result<Vec<DanglingReference>> {
+    let mut written = 0;
+    write_selected_images(bgst_contents, options, folder_name, |_, _| {
+        written += 1;
+        (written - 1).to_string()
+    })
+}
+
+/// Decodes every selected entry that has a main image, applies its mask if it
+/// has one, and writes the finished tile as a transparent PNG. Tiles are written
+/// one at a time and are not assembled into a grid.
+/// Files are named after the index of their entry, such as `12.png`.
+/// 
+/// ### Parameters
+/// - `bgst_contents`: The raw data of a bgst3 file.
+/// - `options`: Options that control which images are decoded and what is written.
+/// - `folder_name`: The existing folder to write the tiles into.
+/// 
+/// ### Returns
+/// - the image indices that were skipped because they were out of range
+pub fn write_flattened_tiles(
+    bgst_contents: &[u8],
+    options: &ExtractOptions,
+    folder_name: &str
+) -> Result<Vec<DanglingReference>> {
+    let (header, selected, dangling_references) = select_entries(bgst_contents, &options.decode)?;
+
+    for (entry_index, entry) in &selected {
+        let Some(decoded) = decode_masked_entry(&header, bgst_contents, *entry_index, entry, &options.decode.overrides)? else {
+            continue;
+        };
+
+        if let Some(mut tile) = RgbaImage::from_raw(header.image_width, header.image_height, decoded) {
+            if options.annotate {
+                annotate::annotate_tile(&mut tile, *entry_index, entry);
+            }
+            let provenance = options.embed_meta
+                .then(|| TileProvenance::new(options.source_name.as_deref(), *entry_index, entry, None));
+            write_tile_with_provenance(folder_name, &entry_index.to_string(), &tile, false, options, provenance.as_ref())?;
+        }
+    }
+
+    Ok(dangling_references)
+}
+
+
+/// Returns the name an entry's image is written under when naming by entry,
+/// without the extension, such as `entry12_main` or `entry12_mask`.
+/// 
+/// ### Parameters
+/// - `entry_index`: The index of the entry.
+/// - `is_mask`: Whether the image is the entry's mask.
+pub fn entry_image_name(entry_index: usize, is_mask: bool) -> String {
+    if is_mask {
+        format!("entry{}_mask", entry_index)
+    } else {
+        format!("entry{}_main", entry_index)
+    }
+}
+
+/// Decodes and writes the main and mask image of every selected entry,
+/// named after the entry with `entry_image_name`, so the same file always
+/// produces the same names. An image used by several entries is written for each.
+/// Masks are not applied.
+/// 
+/// ### Parameters
+/// - `bgst_contents`: The raw data of a bgst3 file.
+/// - `options`: Options that control which images are decoded and what is written.
+/// - `folder_name`: The existing folder to write the images into.
+/// 
+/// ### Returns
+/// - the image indices that were skipped because they were out of range
+pub fn write_entry_images(
+    bgst_contents: &[u8],
+    options: &ExtractOptions,
+    folder_name: &str
+) -> Result<Vec<DanglingReference>> {
+    write_selected_images(bgst_contents, options, folder_name, entry_image_name)
+}
+
+/// Decodes and writes the main and mask image of every selected entry in decode order,
+/// annotating them and recording where they came from if asked, under the names given
+/// by `name` for the entry index and whether the image is the mask.
+fn write_selected_images(
+    bgst_contents: &[u8],
+    options: &ExtractOptions,
+    folder_name: &str,
+    mut name: impl FnMut(usize, bool) -> String
+) -> Result<Vec<DanglingReference>> {
+    let (header, selected, dangling_references) = select_entries(bgst_contents, &options.decode)?;
+
+    for (entry_index, entry) in &selected {
+        for (image_index, is_mask) in [(entry.main_image_index, false), (entry.mask_image_index, true)] {
+            if !header.has_image(image_index) || (options.decode.masks_only && !is_mask) {
+                continue;
+            }
+
+            let decoded = decode_block(
+                &header,
+                bgst_contents,
+                *entry_index,
+                image_index,
+                options.decode.overrides.format_for(image_index as usize, is_mask),
+                is_mask
+            )?;
+
+            if let Some(mut image) = RgbaImage::from_raw(header.image_width, header.image_height, decoded) {
+                if options.annotate {
+                    annotate::annotate_tile(&mut image, *entry_index, entry);
+                }
+                let provenance = options.embed_meta.then(|| {
+                    TileProvenance::new(options.source_name.as_deref(), *entry_index, entry, Some((image_index, is_mask)))
+                });
+                write_tile_with_provenance(
+                    folder_name,
+                    &name(*entry_index, is_mask),
+                    &image,
+                    is_mask,
+                    options,
+                    provenance.as_ref()
+                )?;
+            }
+        }
+    }
+
+    Ok(dangling_references)
+}
+
+/// An entry with its decoded main and mask images, if any.
+type EntryImages<'a> = (&'a GridEntry, Option<&'a [u8]>, Option<&'a [u8]>);
+
+/// A list of compressed or uncompressed images.
+/// 
+/// ### Fields
+/// - `image_width`: The width of every image, in pixels.
+/// - `image_height`: The height of every image, in pixels.
+/// - `grid_width`: The number of columns the grid has.
+/// - `grid_height`: The number of rows the grid has.
+/// - `image_count`: The number of images declared by the file.
+/// - `unk_4`: The unknown header value at 0x4, kept for repacking.
+/// - `layer_enabled`: Which rendering layers are available to entries, kept for repacking.
+/// - `grid_entries`: The entries the images were decoded for.
+/// - `entry_indices`: The index in the file of every entry in `grid_entries`.
+/// - `images`: The images.
+/// - `dangling_references`: Image indices that were skipped because they were out of range.
+/// - `masks_only`: Whether or not only mask images were decoded.
+/// - `flip_v`: Whether or not finished tiles and composites are flipped upside down.
+/// - `max_canvas_pixels`: The largest number of pixels an image assembled from the tiles may have.
+/// - `main_count`: The number of main images in `images`.
+/// - `mask_count`: The number of mask images in `images`.
+/// - `skipped_entries`: The number of entries in the file that were not selected for decoding.
+pub struct ImageList {
+    image_width: u32,
+    image_height: u32,
+    grid_width: u32,
+    grid_height: u32,
+    image_count: u32,
+    unk_4: u32,
+    layer_enabled: [bool; 12],
+    grid_entries: Vec<GridEntry>,
+    entry_indices: Vec<usize>,
+    images: Vec<Vec<u8>>,
+    dangling_references: Vec<DanglingReference>,
+    masks_only: bool,
+    flip_v: bool,
+    max_canvas_pixels: u64,
+    main_count: usize,
+    mask_count: usize,
+    skipped_entries: usize
+} 
+
+impl ImageList {
+    /// Returns the image indices that were skipped because they were out of range.
+    pub fn dangling_references(&self) -> &[DanglingReference] {
+        &self.dangling_references
+    }
+
+    /// Returns the number of main images that were decoded.
+    /// Every selected entry with an in-range main image adds one, even if
+    /// another entry uses the same image, so this can exceed the file's `image_count`.
+    pub fn main_count(&self) -> usize {
+        self.main_count
+    }
+
+    /// Returns the number of mask images that were decoded, counted the same way as `main_count`.
+    /// The decoded images are `main_count() + mask_count()` long.
+    pub fn mask_count(&self) -> usize {
+        self.mask_count
+    }
+
+    /// Returns the number of entries in the file that were not selected for decoding,
+    /// such as those on disabled layers or outside the requested region.
+    pub fn skipped_entries(&self) -> usize {
+        self.skipped_entries
+    }
+
+    /// Returns the size of a canvas that holds the given number of tiles in each direction,
+    /// checking that it doesn't overflow or exceed the canvas pixel limit.
+    /// 
+    /// ### Parameters
+    /// - `columns`: The number of tiles across.
+    /// - `rows`: The number of tiles down.
+    /// 
+    /// ### Returns
+    /// - the width and height of the canvas, in pixels
+    pub fn canvas_size(&self, columns: u32, rows: u32) -> Result<(u32, u32)> {
+        let too_large = || BgstError::InvalidFile(format!(
+            "a canvas of {}x{} tiles of {}x{} pixels exceeds the limit of {} pixels",
+            columns,
+            rows,
+            self.image_width,
+            self.image_height,
+            self.max_canvas_pixels
+        ));
+
+        let width = columns.checked_mul(self.image_width).ok_or_else(too_large)?;
+        let height = rows.checked_mul(self.image_height).ok_or_else(too_large)?;
+        if width as u64 * height as u64 > self.max_canvas_pixels {
+            bail!(too_large());
+        }
+
+        Ok((width, height))
+    }
+
+    /// Returns the decoded main image of an entry.
+    ///
+    /// ### Parameters
+    /// - `entry_index`: The index of the entry in the file.
+    ///
+    /// ### Returns
+    /// - the RGBA of the main image, or `None` if the entry wasn't decoded or has no decoded main image
+    pub fn get_main(&self, entry_index: usize) -> Option<&[u8]> {
+        self.decoded_entry(entry_index).and_then(|(_, main, _)| main)
+    }
+
+    /// Returns the decoded mask image of an entry.
+    ///
+    /// ### Parameters
+    /// - `entry_index`: The index of the entry in the file.
+    ///
+    /// ### Returns
+    /// - the RGBA of the mask image, or `None` if the entry wasn't decoded or has no mask image
+    pub fn get_mask(&self, entry_index: usize) -> Option<&[u8]> {
+        self.decoded_entry(entry_index).and_then(|(_, _, mask)| mask)
+    }
+
+    /// Finds a decoded entry by its index in the file, with its images.
+    fn decoded_entry(&self, entry_index: usize) -> Option<EntryImages<'_>> {
+        let position = self.entry_indices.iter().position(|index| *index == entry_index)?;
+        self.entry_images().nth(position)
+    }
+
+    /// Pairs every entry with its decoded main and mask images, if any.
+    fn entry_images(&self) -> impl Iterator<Item = EntryImages<'_>> {
+        let has_image = |index: i16| index > -1 && (index as u32) < self.image_count;
+        let mut next_image = 0;
+
+        self.grid_entries.iter().map(move |entry| {
+            let mut take = |index: i16| {
+                if !has_image(index) {
+                    return None;
+                }
+
+                let image = self.images.get(next_image).map(Vec::as_slice);
+                next_image += 1;
+                image
+            };
+
+            let main = if self.masks_only { None } else { take(entry.main_image_index) };
+            let mask = take(entry.mask_image_index);
+            (entry, main, mask)
+        })
+    }
+}
+
+/// Builds an `ImageList` from tiles instead of a file, such as to test composites
+/// and exporters with synthetic data. Every tile becomes an entry with its own
+/// main image and, if given, its own mask, numbered in the order they are added.
+/// Every layer is enabled.
+/// 
+/// ### Fields
+/// - `image_width`: The width of every tile, in pixels.
+/// - `image_height`: The height of every tile, in pixels.
+/// - `grid_width`: The number of columns the grid has.
+/// - `grid_height`: The number of rows the grid has.
+/// - `entries`: The entries added so far.
+/// - `images`: The RGBA of the images added so far.
+pub struct ImageListBuilder {
+    image_width: u32,
+    image_height: u32,
+    grid_width: u32,
+    grid_height: u32,
+    entries: Vec<GridEntry>,
+    images: Vec<Vec<u8>>,
+}
+
+impl ImageListBuilder {
+    /// Starts an empty list of tiles of the given size, on a grid of the given size.
+    /// 
+    /// ### Parameters
+    /// - `image_width`: The width of every tile, in pixels.
+    /// - `image_height`: The height of every tile, in pixels.
+    /// - `grid_width`: The number of columns the grid has.
+    /// - `grid_height`: The number of rows the grid has.
+    pub fn new(image_width: u32, image_height: u32, grid_width: u32, grid_height: u32) -> ImageListBuilder {
+        ImageListBuilder {
+            image_width,
+            image_height,
+            grid_width,
+            grid_height,
+            entries: Vec::new(),
+            images: Vec::new(),
+        }
+    }
+
+    /// Adds an enabled tile.
+    /// 
+    /// ### Parameters
+    /// - `scene`: The scene the tile is placed on.
+    /// - `grid_x`: The column the tile is placed in.
+    /// - `grid_y`: The row the tile is placed in.
+    /// - `main`: The main image.
+    /// - `mask`: The mask image, if the tile has one.
+    pub fn tile(
+        self,
+        scene: SceneIndex,
+        grid_x: i16,
+        grid_y: i16,
+        main: RgbaImage,
+        mask: Option<RgbaImage>
+    ) -> ImageListBuilder {
+        self.entry(true, scene, grid_x, grid_y, main, mask)
+    }
+
+    /// Adds a tile, which may be disabled.
+    /// 
+    /// ### Parameters
+    /// - `enabled`: Whether or not the entry is enabled.
+    /// - the remaining parameters are the same as for `tile`.
+    pub fn entry(
+        mut self,
+        enabled: bool,
+        scene: SceneIndex,
+        grid_x: i16,
+        grid_y: i16,
+        main: RgbaImage,
+        mask: Option<RgbaImage>
+    ) -> ImageListBuilder {
+        let main_image_index = self.images.len() as i16;
+        self.images.push(main.into_raw());
+
+        let mask_image_index = match mask {
+            Some(mask) => {
+                self.images.push(mask.into_raw());
+                self.images.len() as i16 - 1
+            }
+            None => -1,
+        };
+
+        self.entries.push(GridEntry {
+            enabled: enabled as i16,
+            scene_index: scene as i16,
+            grid_x,
+            grid_y,
+            main_image_index,
+            mask_image_index,
+            _unk_c: 0,
+            _unk_e: 0,
+        });
+        self
+    }
+
+    /// Finishes the list, checking that every image is the size of a tile.
+    /// 
+    /// ### Returns
+    /// - an `ImageList` struct
+    pub fn build(self) -> Result<ImageList> {
+        let expected = self.image_width as usize * self.image_height as usize * 4;
+        if let Some(index) = self.images.iter().position(|image| image.len() != expected) {
+            bail!(
+                "image {} is {} bytes, but images of {}x{} pixels are {} bytes",
+                index,
+                self.images[index].len(),
+                self.image_width,
+                self.image_height,
+                expected
+            );
+        }
+
+        let mask_count = self.entries.iter().filter(|entry| entry.mask_image_index > -1).count();
+
+        Ok(ImageList {
+            image_width: self.image_width,
+            image_height: self.image_height,
+            grid_width: self.grid_width,
+            grid_height: self.grid_height,
+            image_count: self.images.len() as u32,
+            unk_4: 0,
+            layer_enabled: [true; 12],
+            main_count: self.entries.len(),
+            mask_count,
+            entry_indices: (0..self.entries.len()).collect(),
+            grid_entries: self.entries,
+            images: self.images,
+            dangling_references: Vec::new(),
+            masks_only: false,
+            flip_v: false,
+            max_canvas_pixels: DEFAULT_MAX_CANVAS_PIXELS,
+            skipped_entries: 0,
+        })
+    }
+}
+
+/// Composites every tile placed on a scene into one image.
+/// Tiles are drawn in entry order at their grid position, with masks applied.
+/// The canvas covers the whole grid declared by the header, so cells without
+/// a tile stay transparent. It only grows past the grid if a tile is placed outside it.
+/// If the images were decoded with `flip_v`, grid row 0 is placed at the bottom.
+/// 
+/// ### Parameters
+/// - `images`: The decoded images.
+/// - `scene`: The scene to composite.
+/// 
+/// ### Returns
+/// - the composited scene, or `None` if no tiles are placed on it
+pub fn composite_scene(images: &ImageList, scene: SceneIndex) -> Result<Option<RgbaImage>> {
+    let tiles: Vec<(RgbaImage, &GridEntry)> = masked_tiles(images)
+        .collect::<Result<Vec<_>>>()?
+        .into_iter()
+        .filter(|(_, entry)| entry.scene_index == scene as i16)
+        .filter(|(_, entry)| entry.grid_x >= 0 && entry.grid_y >= 0)
+        .collect();
+
+    if tiles.is_empty() {
+        return Ok(None);
+    }
+
+    let columns = tiles
+        .iter()
+        .map(|(_, entry)| entry.grid_x as u32 + 1)
+        .fold(images.grid_width, u32::max);
+    let rows = tiles
+        .iter()
+        .map(|(_, entry)| entry.grid_y as u32 + 1)
+        .fold(images.grid_height, u32::max);
+
+    let (width, height) = images.canvas_size(columns, rows)?;
+    let mut canvas = RgbaImage::new(width, height);
+
+    for (tile, entry) in &tiles {
+        let row = if images.flip_v {
+            rows - 1 - entry.grid_y as u32
+        } else {
+            entry.grid_y as u32
+        };
+
+        imageops::overlay(
+            &mut canvas,
+            tile,
+            entry.grid_x as i64 * images.image_width as i64,
+            row as i64 * images.image_height as i64
+        );
+    }
+
+    Ok(Some(canvas))
+}
+
+/// Composites every scene into one image, from the farthest to the nearest.
+/// Each scene is composited with `composite_scene`, then its alpha is multiplied
+/// by its opacity before it is blended over the scenes behind it.
+/// 
+/// ### Parameters
+/// - `images`: The decoded images.
+/// - `opacity`: The opacity of each scene, from 0.0 to 1.0. Scenes not in the map are fully opaque.
+/// 
+/// ### Returns
+/// - the composited scenes, or `None` if no tiles are placed on any visible scene
+pub fn composite_all(images: &ImageList, opacity: &HashMap<SceneIndex, f32>) -> Result<Option<RgbaImage>> {
+    let mut layers = Vec::new();
+
+    for scene in SceneIndex::ALL {
+        let scene_opacity = opacity.get(&scene).copied().unwrap_or(1.0).clamp(0.0, 1.0);
+        if scene_opacity == 0.0 {
+            continue;
+        }
+
+        let Some(mut layer) = composite_scene(images, scene)? else {
+            continue;
+        };
+
+        if scene_opacity < 1.0 {
+            for pixel in layer.pixels_mut() {
+                pixel[3] = (pixel[3] as f32 * scene_opacity).round() as u8;
+            }
+        }
+
+        layers.push(layer);
+    }
+
+    let Some(width) = layers.iter().map(RgbaImage::width).max() else {
+        return Ok(None);
+    };
+    let height = layers.iter().map(RgbaImage::height).max().unwrap_or(0);
+
+    let mut canvas = RgbaImage::new(width, height);
+    for layer in &layers {
+        imageops::overlay(&mut canvas, layer, 0, 0);
+    }
+
+    Ok(Some(canvas))
+}
+
+/// Composites every scene that has tiles placed on it and writes each one
+/// as `<scene name>.<extension>`, such as `Game.png` or `Far01.webp`.
+/// Each scene can be written in its own format, which helps when distant scenes
+/// can be stored in a smaller format than the ones the player sees up close.
+/// 
+/// ### Parameters
+/// - `images`: The decoded images.
+/// - `folder_name`: The folder to write the scenes into, which is created if it doesn't exist.
+/// - `formats`: The format of each scene. Scenes not in the map are written as PNG.
+pub fn export_scenes(
+    images: &ImageList,
+    folder_name: &str,
+    formats: &HashMap<SceneIndex, ImageFormat>
+) -> Result<()> {
+    fs::create_dir_all(folder_name)
+        .map_err(|err| BgstError::Write(format!("failed to create folder {}: {}", folder_name, err)))?;
+
+    for scene in SceneIndex::ALL {
+        let Some(layer) = composite_scene(images, scene)? else {
+            continue;
+        };
+
+        let format = formats.get(&scene).copied().unwrap_or(ImageFormat::Png);
+        let extension = format.extensions_str().first().copied().unwrap_or("img");
+        let filename = format!("{}/{}.{}", folder_name, scene.name(), extension);
+
+        layer.save_with_format(&filename, format)
+            .map_err(|err| BgstError::Write(format!("failed to write {}: {}", filename, err)))?;
+    }
+
+    Ok(())
+}
+
+/// The directions tiles can be stacked in a filmstrip.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum FilmstripDirection {
+    /// One column, from the top down.
+    #[default]
+    Vertical,
+    /// One row, from left to right.
+    Horizontal,
+}
+
+/// Stacks every decoded tile, with masks applied, into one strip in entry order.
+/// Each tile keeps its full size, so the same tile of two files lines up
+/// when their filmstrips are compared.
+/// 
+/// ### Parameters
+/// - `images`: The decoded images.
+/// - `direction`: The direction to stack the tiles in.
+/// 
+/// ### Returns
+/// - the filmstrip, or `None` if no entry has a main image
+pub fn filmstrip(images: &ImageList, direction: FilmstripDirection) -> Result<Option<RgbaImage>> {
+    let tiles: Vec<RgbaImage> = masked_tiles(images).map(|tile| tile.map(|(tile, _)| tile)).collect::<Result<_>>()?;
+    if tiles.is_empty() {
+        return Ok(None);
+    }
+
+    let count = u32::try_from(tiles.len()).unwrap_or(u32::MAX);
+    let (width, height) = match direction {
+        FilmstripDirection::Vertical => images.canvas_size(1, count)?,
+        FilmstripDirection::Horizontal => images.canvas_size(count, 1)?,
+    };
+
+    let mut strip = RgbaImage::new(width, height);
+    for (i, tile) in tiles.iter().enumerate() {
+        let (x, y) = match direction {
+            FilmstripDirection::Vertical => (0, i as i64 * images.image_height as i64),
+            FilmstripDirection::Horizontal => (i as i64 * images.image_width as i64, 0),
+        };
+        imageops::overlay(&mut strip, tile, x, y);
+    }
+
+    Ok(Some(strip))
+}
+
+/// Splits a sheet of tiles back into the tiles, the inverse of laying tiles out in rows
+/// from the top left with no gaps, as the atlases of `atlas::build_atlas` and filmstrips are.
+/// This lets an edited sheet be sliced up and packed again.
+/// Every cell is returned, including empty ones at the end of the last row.
+/// 
+/// ### Parameters
+/// - `sheet`: The sheet of tiles.
+/// - `columns`: The number of tiles in each row.
+/// - `tile_width`: The width of every tile, in pixels.
+/// - `tile_height`: The height of every tile, in pixels.
+/// 
+/// ### Returns
+/// - every tile, row by row
+pub fn reslice_contact_sheet(
+    sheet: &RgbaImage,
+    columns: u32,
+    tile_width: u32,
+    tile_height: u32
+) -> Result<Vec<RgbaImage>> {
+    let expected_width = columns.checked_mul(tile_width);
+    if expected_width != Some(sheet.width()) || tile_height == 0 || !sheet.height().is_multiple_of(tile_height) {
+        bail!(
+            "a sheet of {}x{} pixels can't hold {} columns of {}x{} tiles",
+            sheet.width(),
+            sheet.height(),
+            columns,
+            tile_width,
+            tile_height
+        );
+    }
+
+    let rows = sheet.height() / tile_height;
+    let tiles = (0..rows)
+        .flat_map(|row| (0..columns).map(move |column| (column, row)))
+        .map(|(column, row)| {
+            imageops::crop_imm(sheet, column * tile_width, row * tile_height, tile_width, tile_height).to_image()
+        })
+        .collect();
+
+    Ok(tiles)
+}
+
+/// Decodes the tile placed at one grid position of a scene, with its mask applied.
+/// Only that entry's images are decoded. If several entries are placed there,
+/// the last one is decoded, since it is drawn on top when compositing.
+/// 
+/// ### Parameters
+/// - `bgst_contents`: The raw data of a bgst3 file.
+/// - `scene`: The scene to look on.
+/// - `grid_x`: The horizontal grid position.
+/// - `grid_y`: The vertical grid position.
+/// 
+/// ### Returns
+/// - the tile, or `None` if no tile with a main image is placed there
+pub fn decode_at(bgst_contents: &[u8], scene: SceneIndex, grid_x: i16, grid_y: i16) -> Result<Option<RgbaImage>> {
+    let options = DecodeOptions {
+        region: Some(GridRect { x: grid_x, y: grid_y, width: 1, height: 1 }),
+        scenes: SceneFilter::Only(vec![scene]),
+        ..Default::default()
+    };
+
+    let (header, selected, _) = select_entries(bgst_contents, &options)?;
+    let Some((entry_index, entry)) = selected
+        .iter()
+        .rev()
+        .find(|(_, entry)| header.has_image(entry.main_image_index)) else {
+        return Ok(None);
+    };
+
+    let Some(decoded) = decode_masked_entry(&header, bgst_contents, *entry_index, entry, &options.overrides)? else {
+        return Ok(None);
+    };
+
+    Ok(RgbaImage::from_raw(header.image_width, header.image_height, decoded))
+}
+
+thread_local! {
+    /// The buffer masks are decoded into by `decode_entry_into`, reused by later calls on the same thread.
+    static MASK_SCRATCH: std::cell::RefCell<Vec<u8>> = const { std::cell::RefCell::new(Vec::new()) };
+}
+
+/// Decodes one image block in the usual layout straight into a buffer of the right size.
+fn decode_block_into(
+    header: &Header,
+    bgst_contents: &[u8],
+    entry_index: usize,
+    image_index: i16,
+    format: TextureFormat,
+    out: &mut [u8]
+) -> Result<()> {
+    let encoded = encoded_image(header, bgst_contents, entry_index, image_index, format)?;
+    texture::decode_into(out, encoded, header.image_width, header.image_height, format)
+}
+
+/// Decodes the main image of an entry into a buffer the caller owns and applies its mask,
+/// if it has one, so buffers can be reused across tiles. Images are decoded as CMPR
+/// and masks as I4, with the default mask key. The main image is decoded in place;
+/// masks go through a buffer that is allocated once per thread and then reused.
+/// 
+/// ### Parameters
+/// - `bgst_contents`: The raw data of a bgst3 file.
+/// - `entry_index`: The index of the entry in the file.
+/// - `out`: Where to write the RGBA, exactly `image_width * image_height * 4` bytes long.
+pub fn decode_entry_into(bgst_contents: &[u8], entry_index: usize, out: &mut [u8]) -> Result<()> {
+    let Some(entry) = entry_views(bgst_contents)?.nth(entry_index) else {
+        bail!("entry {} is out of range", entry_index);
+    };
+
+    let header = Header::from_validated_header_bytes(bgst_contents);
+    header.check_dimensions(&DimensionLimits::default())?;
+
+    let expected = header.image_width as usize * header.image_height as usize * 4;
+    if out.len() != expected {
+        bail!("the buffer is {} bytes long, but the image needs {} bytes", out.len(), expected);
+    }
+
+    if !header.has_image(entry.main_image_index()) {
+        bail!(BgstError::Decode(format!("entry {} has no main image", entry_index)));
+    }
+    decode_block_into(&header, bgst_contents, entry_index, entry.main_image_index(), TextureFormat::CMPR, out)?;
+
+    if !header.has_image(entry.mask_image_index()) {
+        return Ok(());
+    }
+
+    MASK_SCRATCH.with_borrow_mut(|mask| {
+        mask.resize(expected, 0);
+        decode_block_into(&header, bgst_contents, entry_index, entry.mask_image_index(), TextureFormat::I4, mask)?;
+
+        let key = MaskKey::default();
+        for (pixel, mask_pixel) in out.chunks_exact_mut(4).zip(mask.chunks_exact(4)) {
+            if key.matches(&Rgba([mask_pixel[0], mask_pixel[1], mask_pixel[2], mask_pixel[3]])) {
+                pixel[3] = 0;
+            }
+        }
+
+        Ok(())
+    })
+}
+
+/// The contents of a cached file, and its decoded images.
+type CachedFile = (Vec<u8>, Arc<ImageList>);
+
+/// A cache of decoded files, grouped by a hash of their contents.
+/// Useful when the same file is decoded many times, such as in a viewer.
+/// The contents of every file are kept, so files whose hashes collide are told apart.
+#[derive(Default)]
+pub struct DecodeCache {
+    entries: HashMap<u64, Vec<CachedFile>>,
+}
+
+impl DecodeCache {
+    /// Creates an empty cache.
+    pub fn new() -> DecodeCache {
+        DecodeCache::default()
+    }
+
+    /// Returns the decoded images of a file, decoding them only
+    /// if the same contents haven't been decoded before.
+    /// 
+    /// ### Parameters
+    /// - `data`: The raw data of a bgst3 file.
+    /// 
+    /// ### Returns
+    /// - the shared `ImageList` for the file
+    pub fn get_or_decode(&mut self, data: &[u8]) -> Result<Arc<ImageList>> {
+        let mut hasher = DefaultHasher::new();
+        data.hash(&mut hasher);
+        let key = hasher.finish();
+
+        let bucket = self.entries.entry(key).or_default();
+        if let Some((_, images)) = bucket.iter().find(|(contents, _)| contents.as_slice() == data) {
+            return Ok(Arc::clone(images));
+        }
+
+        let images = Arc::new(get_raw_images(data)?);
+        bucket.push((data.to_vec(), Arc::clone(&images)));
+        Ok(images)
+    }
+
+    /// Removes every decoded file from the cache.
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+}
+
+/// Builds the finished tile of every entry that has a main image,
+/// applying the entry's mask if it has one, and flipping it if the images were decoded with `flip_v`.
+/// 
+/// ### Parameters
+/// - `images`: The decoded images.
+/// 
+/// ### Returns
+/// - an iterator over each finished tile and the entry it belongs to,
+///   or the error of a tile whose mask couldn't be applied
+pub fn masked_tiles(images: &ImageList) -> impl Iterator<Item = Result<(RgbaImage, &GridEntry)>> {
+    images.entry_images().filter_map(|(entry, main, mask)| {
+        let main = main?;
+
+        let tile = (|| {
+            let decoded = match mask {
+                Some(mask) => apply_mask(main, mask, images.image_width, images.image_height)?,
+                None => main.to_vec(),
+            };
+
+            let Some(tile) = RgbaImage::from_raw(images.image_width, images.image_height, decoded) else {
+                bail!(BgstError::Decode(format!("the tile at {}, {} is the wrong size", entry.grid_x, entry.grid_y)));
+            };
+            Ok(tile)
+        })();
+
+        Some(tile.map(|tile| {
+            if images.flip_v {
+                (imageops::flip_vertical(&tile), entry)
+            } else {
+                (tile, entry)
+            }
+        }))
+    })
+}
+
+
+/// The color a mask uses to mark transparent pixels.
+/// 
+/// ### Fields
+/// - `color`: The key color. Only the red, green and blue channels are compared.
+/// - `tolerance`: How far each channel may be from the key color and still match.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MaskKey {
+    pub color: Rgba<u8>,
+    pub tolerance: u8,
+}
+
+impl Default for MaskKey {
+    /// Pure black with no tolerance, which is what the game's masks use.
+    fn default() -> Self {
+        MaskKey {
+            color: Rgba([0, 0, 0, 0xFF]),
+            tolerance: 0,
+        }
+    }
+}
+
+impl MaskKey {
+    /// Returns if a mask pixel matches the key color.
+    /// 
+    /// ### Parameters
+    /// - `pixel`: The mask pixel.
+    /// 
+    /// ### Returns
+    /// `true` if the pixel should be made transparent.
+    pub fn matches(&self, pixel: &Rgba<u8>) -> bool {
+        (0..3).all(|channel| pixel[channel].abs_diff(self.color[channel]) <= self.tolerance)
+    }
+}
+
+/// How a mask is combined with its main image.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MaskMode {
+    /// Pixels become transparent wherever the mask matches the key color, and are left alone elsewhere.
+    Key(MaskKey),
+    /// The luminance of the mask becomes the alpha of the main image, keeping its color.
+    /// This keeps soft edges, reading the mask as per-pixel opacity.
+    MaskAsAlpha,
+}
+
+impl Default for MaskMode {
+    /// The default key, which is what the game's masks use.
+    fn default() -> Self {
+        MaskMode::Key(MaskKey::default())
+    }
+}
+
+impl From<MaskKey> for MaskMode {
+    fn from(key: MaskKey) -> Self {
+        MaskMode::Key(key)
+    }
+}
+
+/// Applies a mask to an image, using black as the transparent key.
+/// 
+/// ### Parameters
+/// - `main_image`: The RGBA of the main image.
+/// - `mask_image`: The RGBA of the mask image.
+/// - `width`: The width of both images, in pixels.
+/// - `height`: The height of both images, in pixels.
+/// 
+/// ### Returns
+/// - the RGBA of the masked image
+pub fn apply_mask(
+    main_image: &[u8],
+    mask_image: &[u8],
+    width: u32,
+    height: u32
+) -> Result<Vec<u8>> {
+    apply_mask_with(main_image, mask_image, width, height, MaskKey::default())
+}
+
+/// Applies a mask to an image. With a key, pixels of the main image become
+/// transparent wherever the mask matches the key color; see `MaskMode` for the other modes.
+/// 
+/// ### Parameters
+/// - `main_image`: The RGBA of the main image.
+/// - `mask_image`: The RGBA of the mask image.
+/// - `width`: The width of both images, in pixels.
+/// - `height`: The height of both images, in pixels.
+/// - `mode`: How the mask is applied, such as a `MaskKey` for the color that marks transparent pixels.
+/// 
+/// ### Returns
+/// - the RGBA of the masked image
+pub fn apply_mask_with(
+    main_image: &[u8],
+    mask_image: &[u8],
+    width: u32,
+    height: u32,
+    mode: impl Into<MaskMode>
+) -> Result<Vec<u8>> {
+    let mode = mode.into();
+    if main_image.len() != mask_image.len() {
+        bail!(BgstError::Decode("the image sizes are not equal!".to_string()));
+    }
+
+    // decode the main and mask images from raw rgba bytes
+
+    let main_img: RgbaImage = ImageBuffer::from_raw(width, height, main_image.to_vec())
+        .ok_or_else(|| BgstError::Decode("failed to decode main image".to_string()))?;
+    let mask_img: RgbaImage = ImageBuffer::from_raw(width, height, mask_image.to_vec())
+        .ok_or_else(|| BgstError::Decode("failed to decode mask image".to_string()))?;
+
+    let mut output_img = RgbaImage::new(width, height);
+
+
+    for (x, y, pixel) in output_img.enumerate_pixels_mut() {
+        let main_pixel = main_img.get_pixel(x, y);
+        let mask_pixel = mask_img.get_pixel(x, y);
+
+        match mode {
+            // if the mask pixel matches the key, set alpha of main image to 0
+            MaskMode::Key(key) if key.matches(mask_pixel) => {
+                *pixel = Rgba([main_pixel[0], main_pixel[1], main_pixel[2], 0]); // make transparent
+            }
+            MaskMode::Key(_) => *pixel = *main_pixel, // keep original pixel
+            MaskMode::MaskAsAlpha => {
+                *pixel = Rgba([main_pixel[0], main_pixel[1], main_pixel[2], mask_pixel.to_luma()[0]]);
+            }
+        }
+    }
+
+    let output_bytes = output_img.into_raw();
+
+    Ok(output_bytes)
+}
+
+/// Encodes decoded images as PNGs. With masks applied, there is one finished tile per entry
+/// that has a main image, in entry order; see `masked_tiles`. Otherwise, every decoded image
+/// is encoded in the order it was decoded, which is per entry rather than by image index.
+/// 
+/// ### Parameters
+/// - `raw_images`: The decoded images.
+/// - `do_mask`: Whether or not to apply each entry's mask to its main image.
+/// 
+/// ### Returns
+/// - the encoded PNGs
+pub fn get_png_images(
+    raw_images: &ImageList,
+    do_mask: bool,
+) -> Result<Vec<Vec<u8>>> {
+    let mut result = Vec::new();
+
+    for img in get_rgba_images(raw_images, do_mask)? {
+        let mut buffer = Cursor::new(Vec::new());
+
+        img.write_to(&mut buffer, ImageFormat::Png)?;
+
+        result.push(buffer.into_inner());
+    }
+
+    Ok(result)
+}
+
+/// The images `get_png_images` encodes, before they are encoded.
+fn get_rgba_images(
+    raw_images: &ImageList,
+    do_mask: bool,
+) -> Result<Vec<RgbaImage>> {
+    if do_mask {
+        return masked_tiles(raw_images).map(|tile| tile.map(|(tile, _)| tile)).collect();
+    }
+
+    Ok(raw_images.images
+        .iter()
+        .filter_map(|raw_image| RgbaImage::from_raw(
+            raw_images.image_width,
+            raw_images.image_height,
+            raw_image.to_owned()
+        ))
+        .collect())
+}
+
+/// Derives the output folder for a file by removing its extension.
+/// Files without an extension get an `_extracted` suffix instead,
+/// so the folder never has the same name as the file.
+/// 
+/// ### Parameters
+/// - `input_filename`: The path to the file.
+/// 
+/// ### Returns
+/// - the folder `extract_bgst_with` extracts the file into
+pub fn output_folder_name(input_filename: &str) -> String {
+    let path = Path::new(input_filename);
+
+    if path.extension().is_some() {
+        path.with_extension("").to_string_lossy().into_owned()
+    } else {
+        input_filename.to_owned() + "_extracted"
+    }
+}
+
+/// Formats a region of a file as a hex dump, 16 bytes to a line, each line starting
+/// with its offset in the file and ending with the bytes as ASCII.
+/// The region is cut short where the data ends.
+/// 
+/// ### Parameters
+/// - `bytes`: The data to dump from.
+/// - `start`: The offset of the first byte to dump.
+/// - `len`: The number of bytes to dump.
+/// 
+/// ### Returns
+/// - the dump, with a newline after every line
+fn hex_dump(bytes: &[u8], start: usize, len: usize) -> String {
+    let start = start.min(bytes.len());
+    let end = start.saturating_add(len).min(bytes.len());
+    let mut dump = String::new();
+
+    for (i, line) in bytes[start..end].chunks(16).enumerate() {
+        let hex: Vec<String> = line.iter().map(|byte| format!("{:02X}", byte)).collect();
+        let ascii: String = line
+            .iter()
+            .map(|byte| if byte.is_ascii_graphic() || *byte == b' ' { *byte as char } else { '.' })
+            .collect();
+        dump += &format!("{:08X}  {:<47}  {}\n", start + i * 16, hex.join(" "), ascii);
+    }
+
+    dump
+}
+
+/// Formats the bytes around where parsing a file went wrong, for `ExtractOptions::hex_dump`:
+/// the header, which holds every offset the parser follows, and the line holding the offset
+/// that failed along with the lines on either side. An offset past the end of the file is
+/// shown by the end of the file, where it was cut short.
+/// 
+/// ### Parameters
+/// - `file_contents`: The raw data of the file.
+/// - `offset`: The offset that failed, or `None` if only the header is to blame.
+/// - `what`: What was expected at the offset.
+/// 
+/// ### Returns
+/// - the dump, with a line naming each part
+fn parse_context(file_contents: &[u8], offset: Option<usize>, what: &str) -> String {
+    let mut context = format!("header:\n{}", hex_dump(file_contents, 0, HEADER_SIZE));
+
+    if let Some(offset) = offset {
+        let start = (offset.min(file_contents.len()).saturating_sub(0x10) & !0xF).max(HEADER_SIZE);
+        let dump = hex_dump(file_contents, start, 0x30);
+        if !dump.is_empty() {
+            context += &format!("around {} at 0x{:X}:\n{}", what, offset, dump);
+        }
+    }
+
+    context
+}
+
+/// Returns the offset of the first image block a file is too short to hold, if any.
+fn first_truncated_block(header: &Header, block_size: usize, file_len: usize) -> Option<usize> {
+    let required = header.image_data_offset.saturating_add((header.image_count as usize).saturating_mul(block_size));
+    if file_len >= required || block_size == 0 {
+        return None;
+    }
+
+    let held = file_len.saturating_sub(header.image_data_offset) / block_size;
+    Some(header.image_data_offset + held * block_size)
+}
+
+pub fn extract_bgst(
+    source: impl BgstSource,
+    do_mask: bool,
+) -> Result<()> {
+    extract_bgst_with(source, &ExtractOptions {
+        do_mask,
+        ..Default::default()
+    })
+}
+
+/// Extracts the images selected by the given options
+/// into a folder named after the input file.
+/// 
+/// ### Parameters
+/// - `source`: The bgst3 file. It must have a name to name the folder after.
+/// - `options`: Options that control which images are decoded and what is written.
+pub fn extract_bgst_with(
+    source: impl BgstSource,
+    options: &ExtractOptions,
+) -> Result<()> {
+    let Some(name) = source.name() else {
+        bail!("the source has no name to name the output folder after; use extract_bgst_to instead");
+    };
+
+    extract_bgst_to(source, &output_folder_name(&name), options)
+}
+
+/// Extracts the images selected by the given options into a specific folder.
+/// The folder is replaced if it already exists. A file without any grid entries
+/// has nothing to extract, so this says so and leaves the folder alone.
+/// 
+/// ### Parameters
+/// - `source`: The bgst3 file.
+/// - `folder_name`: The folder to extract into.
+/// - `options`: Options that control which images are decoded and what is written.
+pub fn extract_bgst_to(
+    source: impl BgstSource,
+    folder_name: &str,
+    options: &ExtractOptions,
+) -> Result<()> {
+    let input_name = source.name().unwrap_or_else(|| "<buffer>".to_string());
+
+    // embedded metadata records the name of the file the images came from
+    let named_options;
+    let options = match source.name() {
+        Some(name) if options.embed_meta && options.source_name.is_none() => {
+            named_options = ExtractOptions {
+                source_name: Path::new(&name).file_name().map(|name| name.to_string_lossy().into_owned()),
+                ..options.clone()
+            };
+            &named_options
+        }
+        _ => options,
+    };
+
+    println!("reading file...");
+
+    let file_contents = read_source(&source)?;
+
+    println!("validating header...");
+
+    if !Header::is_valid(&file_contents) {
+        if options.hex_dump {
+            print!("{}", parse_context(&file_contents, None, "the header"));
+        }
+        bail!(BgstError::InvalidFile(format!("file {} is not a valid BGST file", input_name)));
+    }
+
+    // the magic decides whether or not the file is accepted;
+    // the extension is only used to name the output folder
+    let has_bgst_extension = |name: &str| Path::new(name)
+        .extension()
+        .is_some_and(|extension| BGST_EXTENSIONS.iter().any(|known| extension.eq_ignore_ascii_case(known)));
+    if source.name().is_some_and(|name| !has_bgst_extension(&name)) {
+        println!("warning: file {} does not have a .bgst3 or .bgst extension", input_name);
+    }
+
+    let bgst = match Bgst::parse_unchecked(&file_contents) {
+        Ok(bgst) => bgst,
+        Err(err) => {
+            if options.hex_dump {
+                // the header is valid, so the file ends inside its entry table
+                let header = Header::from_validated_header_bytes(&file_contents);
+                let table_end = entry_offset(&header, entry_count(&header));
+                print!("{}", parse_context(&file_contents, Some(table_end), "the end of the entry table"));
+            }
+            return Err(err);
+        }
+    };
+
+    // a truncated file only fails once its images are decoded, so its context is printed up front
+    if options.hex_dump {
+        let block_size = options.decode.overrides.block_size.unwrap_or(bgst.header.block_size);
+        if let Some(offset) = first_truncated_block(&bgst.header, block_size, file_contents.len()) {
+            print!("{}", parse_context(&file_contents, Some(offset), "the first truncated image block"));
+        }
+    }
+
+    if bgst.grid_entries.is_empty() {
+        println!("file contains no grid entries, so there is nothing to extract");
+        return Ok(());
+    }
+
+    let folder_error = |err| BgstError::Write(format!("failed to create folder {}: {}", folder_name, err));
+
+    match fs::exists(folder_name) {
+        Ok(folder_exists) => {
+            if folder_exists {
+                // remove contents
+                fs::remove_dir_all(folder_name).map_err(folder_error)?;
+            }
+            fs::create_dir(folder_name).map_err(folder_error)?;
+        }
+
+        Err(_) => {
+            let _ = fs::create_dir(folder_name);
+        }
+    }
+
+    for warning in warnings::collect_warnings(&bgst, &file_contents) {
+        println!("warning: {}", warning);
+    }
+
+    if options.decode.repair {
+        if let Some(image_count) = bgst.header.repaired_image_count(file_contents.len()) {
+            println!(
+                "warning: the header declares {} images, but the file only holds {}, so {} are used",
+                bgst.header.image_count,
+                image_count,
+                image_count
+            );
+        }
+    }
+
+    if options.check_masks {
+        // only the images that are written are reported to the observer
+        let decode = DecodeOptions { observer: None, ..options.decode.clone() };
+        for warning in warnings::check_masked_tiles(&file_contents, &decode)? {
+            println!("warning: {}", warning);
+        }
+    }
+
+    // the blocks are timed as they're decoded for writing, keeping any observer that was set
+    let totals = options.verbose.then(|| Arc::new(Mutex::new((0, std::time::Duration::ZERO))));
+    let timed_options;
+    let options = match &totals {
+        Some(totals) => {
+            let totals = Arc::clone(totals);
+            let observer = options.decode.observer.clone();
+            let observer = DecodeObserver::new(move |event| {
+                if let Some(observer) = &observer {
+                    observer.notify(event);
+                }
+
+                if let DecodeEvent::Decoded(timing) = event {
+                    println!(
+                        "entry {} {} image {} decoded in {:.3} ms",
+                        timing.entry_index,
+                        if timing.is_mask { "mask" } else { "main" },
+                        timing.image_index,
+                        timing.duration.as_secs_f64() * 1000.0
+                    );
+
+                    let mut totals = totals.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+                    totals.0 += 1;
+                    totals.1 += timing.duration;
+                }
+            });
+
+            timed_options = ExtractOptions {
+                decode: DecodeOptions { observer: Some(observer), ..options.decode.clone() },
+                ..options.clone()
+            };
+            &timed_options
+        }
+        None => options,
+    };
+
+    let dangling_references = if options.unique_images {
+        println!("extracting and writing unique images...");
+        let written = unique::write_unique_images(&file_contents, options, folder_name)?;
+        println!("{} of {} image blocks are unique", written.dedup.unique_count(), written.dedup.total_count());
+        written.dangling_references
+    } else if options.apng && !options.decode.masks_only {
+        println!("extracting and writing images and masks as APNGs...");
+        apng::write_entry_apngs(&file_contents, options, folder_name)?
+    } else if options.flatten && !options.decode.masks_only {
+        println!("flattening and writing tiles...");
+        write_flattened_tiles(&file_contents, options, folder_name)?
+    } else if options.entry_names {
+        println!("extracting and writing images by entry...");
+        write_entry_images(&file_contents, options, folder_name)?
+    } else if options.do_mask && !options.decode.masks_only {
+        // each tile is masked and written as soon as its entry is decoded
+        println!("extracting, masking and writing tiles...");
+        write_flattened_tiles(&file_contents, options, folder_name)?
+    } else {
+        // masks aren't applied, so each image can be written as soon as it's decoded
+        println!("extracting and writing images...");
+        match options.format {
+            OutputFormat::Png => write_png_images(&file_contents, options, folder_name)?,
+            OutputFormat::Dds => dds::write_dds_images(&file_contents, options, folder_name)?,
+        }
+    };
+
+    // each one was warned about above
+    if !dangling_references.is_empty() {
+        println!("skipped {} out of range image indices", dangling_references.len());
+    }
+
+    if let Some(totals) = totals {
+        let (count, total) = *totals.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        println!("decoded {} image blocks in {:.3} ms", count, total.as_secs_f64() * 1000.0);
+    }
+
+    println!("done!");
+
+    Ok(())
+}
+
+/// Extracts many files in parallel, each into its own folder under `output_root`
+/// named after the file without its extension. A file that fails to extract
+/// doesn't stop the others from being extracted. Files that would be extracted into
+/// the same folder, such as `a/x.bgst3` and `b/x.bgst3`, all fail without being extracted.
+/// 
+/// ### Parameters
+/// - `paths`: The paths to the bgst3 files.
+/// - `output_root`: The folder to create the output folders in.
+/// - `options`: Options that control which images are decoded and what is written.
+/// 
+/// ### Returns
+/// - the result of extracting each file, in the same order as `paths`
+pub fn extract_many(
+    paths: &[PathBuf],
+    output_root: &Path,
+    options: &ExtractOptions,
+) -> Result<Vec<Result<()>>> {
+    fs::create_dir_all(output_root)
+        .map_err(|err| BgstError::Write(format!("failed to create folder {}: {}", output_root.display(), err)))?;
+
+    let folders: Vec<Option<PathBuf>> = paths
+        .iter()
+        .map(|path| path.file_stem().map(|stem| output_root.join(stem)))
+        .collect();
+
+    // extracting into a folder replaces it, so files sharing one would overwrite each other
+    let mut users: HashMap<&Path, Vec<&Path>> = HashMap::new();
+    for (path, folder) in paths.iter().zip(&folders) {
+        if let Some(folder) = folder {
+            users.entry(folder).or_default().push(path);
+        }
+    }
+
+    let results = paths
+        .par_iter()
+        .zip(&folders)
+        .map(|(path, folder)| {
+            let Some(folder) = folder else {
+                bail!(BgstError::FileNotFound(path.display().to_string()));
+            };
+
+            let sharing = &users[folder.as_path()];
+            if sharing.len() > 1 {
+                let others: Vec<String> = sharing
+                    .iter()
+                    .filter(|other| **other != path.as_path())
+                    .map(|other| other.display().to_string())
+                    .collect();
+                bail!(BgstError::Write(format!(
+                    "{} would be extracted into {}, the same folder as {}",
+                    path.display(),
+                    folder.display(),
+                    others.join(", ")
+                )));
+            }
+
+            extract_bgst_to(
+                path,
+                &folder.to_string_lossy(),
+                options
+            )
+        })
+        .collect();
+
+    Ok(results)
+}
+
+/// Runs a function on a thread pool of its own, so parallel work it starts,
+/// such as `extract_many`, uses at most the given number of threads.
+/// Without this, parallel work uses every core.
+/// 
+/// ### Parameters
+/// - `threads`: The number of threads, which must be at least 1.
+/// - `f`: The function to run.
+/// 
+/// ### Returns
+/// - what the function returned
+pub fn with_thread_limit<T: Send>(threads: usize, f: impl FnOnce() -> T + Send) -> Result<T> {
+    if threads == 0 {
+        bail!("the thread count must be at least 1");
+    }
+
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(threads)
+        .build()
+        .context("failed to create the thread pool")?;
+
+    Ok(pool.install(f))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Builds a file with the given entries directly after the header
+    /// and `image_count` zeroed image blocks after them.
+    fn build_file(image_count: u32, entries: &[[i16; 8]]) -> Vec<u8> {
+        let image_data_offset = HEADER_SIZE + entries.len() * GRID_ENTRY_SIZE;
+        let mut contents = build_header(HeaderParams {
+            image_width: 8,
+            image_height: 8,
+            grid_width: 4,
+            grid_height: 4,
+            image_count,
+            layer_enabled: [true; 12],
+            info_offset: HEADER_SIZE as u32,
+            image_data_offset: image_data_offset as u32,
+            ..Default::default()
+        }).to_vec();
+
+        for entry in entries {
+            for field in entry {
+                contents.extend_from_slice(&field.to_be_bytes());
+            }
+        }
+
+        contents.resize(image_data_offset + image_count as usize * COMPRESSED_IMAGE_SIZE, 0);
+        contents
+    }
+
+    #[test]
+    fn invalid_bgst() {
+        assert!(
+            !Header::is_valid(&[])
+        );
+    }
+
+    #[test]
+    fn header_round_trip() {
+        let mut layer_enabled = [false; 12];
+        layer_enabled[SceneIndex::Game as usize] = true;
+        layer_enabled[SceneIndex::Far01 as usize] = true;
+
+        let bytes = build_header(HeaderParams {
+            unk_4: 3,
+            image_width: 512,
+            image_height: 256,
+            grid_width: 8,
+            grid_height: 4,
+            image_count: 20,
+            layer_enabled,
+            info_offset: HEADER_SIZE as u32,
+            image_data_offset: 0x100,
+            tail: [0xAB; 16],
+        });
+
+        assert!(Header::is_valid(&bytes));
+
+        let header = Header::from_validated_header_bytes(&bytes);
+        assert_eq!(header._unk_4, 3);
+        assert_eq!(header.image_width, 512);
+        assert_eq!(header.image_height, 256);
+        assert_eq!(header.grid_width, 8);
+        assert_eq!(header.grid_height, 4);
+        assert_eq!(header.image_count, 20);
+        assert_eq!(header._layer_enabled, layer_enabled);
+        assert_eq!(header.info_offset, HEADER_SIZE);
+        assert_eq!(header.image_data_offset, 0x100);
+        assert_eq!(header.header_tail(), [0xAB; 16]);
+    }
+
+    #[test]
+    fn dangling_image_indices() {
+        let contents = build_file(2, &[
+            [1, 6, 0, 0, 0, 1, 0, 0],
+            [1, 6, 1, 0, 2, -1, 0, 0],
+            [1, 6, 2, 0, 1, 5, 0, 0],
+        ]);
+        let bgst = Bgst::parse(&contents).unwrap();
+
+        assert_eq!(dangling_references(&bgst), vec![
+            DanglingReference { entry_index: 1, image_index: 2, is_mask: false },
+            DanglingReference { entry_index: 2, image_index: 5, is_mask: true },
+        ]);
+        assert_eq!(decodable_image_count(&bgst), 3);
+    }
+
+    #[test]
+    fn mask_key_tolerance() {
+        let main = [10, 20, 30, 0xFF].repeat(2);
+        let mask = [0xFF, 0, 0xF8, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF];
+        let key = MaskKey { color: Rgba([0xFF, 0, 0xFF, 0xFF]), tolerance: 8 };
+
+        let masked = apply_mask_with(&main, &mask, 2, 1, key).unwrap();
+        assert_eq!(masked, [10, 20, 30, 0, 10, 20, 30, 0xFF]);
+
+        // black is still the default key
+        let masked = apply_mask(&main, &mask, 2, 1).unwrap();
+        assert_eq!(masked, main);
+    }
+
+    #[test]
+    fn tiles_pair_with_entries() {
+        let contents = build_file(2, &[
+            [1, 6, 0, 0, 0, 1, 0, 0],
+            [1, 6, 1, 0, -1, 1, 0, 0],
+            [1, 6, 2, 0, 1, -1, 0, 0],
+        ]);
+        let images = get_raw_images(&contents).unwrap();
+
+        let tiles: Vec<(RgbaImage, &GridEntry)> = masked_tiles(&images).collect::<Result<_>>().unwrap();
+        assert_eq!(tiles.len(), 2);
+        assert_eq!(tiles[0].1.grid_x(), 0);
+        assert_eq!(tiles[1].1.grid_x(), 2);
+        assert_eq!(tiles[0].0.dimensions(), (8, 8));
+    }
+
+    #[test]
+    fn output_folder_ignores_extension() {
+        assert_eq!(output_folder_name("bg/forest.bgst3"), "bg/forest");
+        assert_eq!(output_folder_name("bg/forest.bgst"), "bg/forest");
+        assert_eq!(output_folder_name("bg/forest.bin"), "bg/forest");
+        assert_eq!(output_folder_name("bg/forest"), "bg/forest_extracted");
+    }
+
+    #[test]
+    fn unknown_field_tally() {
+        let files = vec![
+            build_file(1, &[[1, 6, 0, 0, 0, -1, 2, 0], [1, 6, 1, 0, 0, -1, 2, 1]]),
+            build_file(1, &[[1, 6, 0, 0, 0, -1, 3, 0]]),
+            vec![0; 4],
+        ];
+
+        let stats = analyze_unknowns(&files);
+        assert_eq!(stats.file_count, 2);
+        assert_eq!(stats.skipped_files, 1);
+        assert_eq!(stats.unk_4.get(&0), Some(&2));
+        assert_eq!(stats.unk_c.get(&2), Some(&2));
+        assert_eq!(stats.unk_c.get(&3), Some(&1));
+        assert_eq!(stats.unk_e.get(&0), Some(&2));
+    }
+
+    #[test]
+    fn cache_reuses_decoded_files() {
+        let contents = build_file(1, &[[1, 6, 0, 0, 0, -1, 0, 0]]);
+        let mut cache = DecodeCache::new();
+
+        let first = cache.get_or_decode(&contents).unwrap();
+        let second = cache.get_or_decode(&contents).unwrap();
+        assert!(Arc::ptr_eq(&first, &second));
+
+        // a different file whose hash collides with the first is still decoded
+        let other = build_file(1, &[[1, 6, 1, 0, 0, -1, 0, 0]]);
+        let mut hasher = DefaultHasher::new();
+        other.hash(&mut hasher);
+        let (_, images) = cache.entries.values().next().unwrap()[0].clone();
+        cache.entries.insert(hasher.finish(), vec![(contents.clone(), images)]);
+
+        let decoded = cache.get_or_decode(&other).unwrap();
+        assert!(!Arc::ptr_eq(&first, &decoded));
+        assert_eq!(decoded.grid_entries[0].grid_x, 1);
+    }
+
+    #[test]
+    fn composite_covers_declared_grid() {
+        let contents = build_file(1, &[[1, 6, 0, 0, 0, -1, 0, 0]]);
+        let images = get_raw_images(&contents).unwrap();
+
+        let canvas = composite_scene(&images, SceneIndex::Game).unwrap().unwrap();
+        assert_eq!(canvas.dimensions(), (32, 32));
+        assert_eq!(canvas.get_pixel(0, 0)[3], 0xFF);
+        assert!(canvas.enumerate_pixels().all(|(x, y, pixel)| (x < 8 && y < 8) || pixel[3] == 0));
+
+        assert!(composite_scene(&images, SceneIndex::Map).unwrap().is_none());
+    }
+
+    #[test]
+    fn scene_pixel_bounds_cover_placed_tiles() {
+        let contents = build_file(0, &[
+            [1, 6, 1, 2, -1, -1, 0, 0],
+            [1, 6, 3, 1, -1, -1, 0, 0],
+            [1, 6, -1, 0, -1, -1, 0, 0],
+        ]);
+        let bgst = Bgst::parse(&contents).unwrap();
+
+        assert_eq!(scene_pixel_bounds(&bgst, SceneIndex::Game), Some((8, 8, 24, 16)));
+        assert_eq!(scene_pixel_bounds(&bgst, SceneIndex::Map), None);
+    }
+
+    #[test]
+    fn trailing_data_is_ignored() {
+        let mut contents = build_file(1, &[[1, 6, 0, 0, 0, -1, 0, 0]]);
+        contents.extend_from_slice(&[0xFF; 0x100]);
+
+        let images = get_raw_images(&contents).unwrap();
+        assert_eq!(images.images.len(), 1);
+    }
+
+    #[test]
+    fn disabled_entries_can_be_skipped() {
+        let contents = build_file(1, &[
+            [1, 6, 0, 0, 0, -1, 0, 0],
+            [0, 6, 1, 0, 0, -1, 0, 0],
+        ]);
+
+        let images = get_raw_images(&contents).unwrap();
+        assert_eq!(images.images.len(), 2);
+
+        let options = DecodeOptions {
+            skip_disabled: true,
+            ..Default::default()
+        };
+        let images = get_raw_images_with(&contents, &options).unwrap();
+        assert_eq!(images.images.len(), 1);
+    }
+
+    #[test]
+    fn batch_continues_past_failures() {
+        let root = std::env::temp_dir().join("bgst_batch_continues_past_failures");
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+
+        let good = root.join("good.bgst3");
+        fs::write(&good, build_file(1, &[[1, 6, 0, 0, 0, -1, 0, 0]])).unwrap();
+        let missing = root.join("missing.bgst3");
+
+        let output_root = root.join("out");
+        let results = extract_many(&[missing, good], &output_root, &ExtractOptions::default()).unwrap();
+
+        assert!(results[0].is_err());
+        assert!(results[1].is_ok());
+        assert!(output_root.join("good").join("0.png").exists());
+
+        let _ = fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn shared_images_are_written_once() {
+        let root = std::env::temp_dir().join("bgst_shared_images_are_written_once");
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+
+        let mut contents = build_file(2, &[
+            [1, 6, 0, 0, 0, 1, 0, 0],
+            [1, 6, 1, 0, 0, 1, 0, 0],
+            [1, 6, 2, 0, 1, -1, 0, 0],
+        ]);
+        // keep the blocks distinct, so they aren't merged as duplicates
+        contents[HEADER_SIZE + 3 * GRID_ENTRY_SIZE + COMPRESSED_IMAGE_SIZE] = 0xFF;
+        let options = ExtractOptions { unique_images: true, ..Default::default() };
+        let folder = root.to_string_lossy();
+        unique::write_unique_images(&contents, &options, &folder).unwrap();
+
+        let mut names: Vec<String> = fs::read_dir(&root)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        assert_eq!(names, ["img_0.png", "img_1.png", "img_1_mask.png", "metadata.json"]);
+
+        let metadata = fs::read_to_string(root.join(unique::METADATA_FILENAME)).unwrap();
+        assert_eq!(metadata.matches("\"img_1_mask\"").count(), 2);
+
+        let _ = fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn identical_blocks_are_decoded_once() {
+        let root = std::env::temp_dir().join("bgst_identical_blocks_are_decoded_once");
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+
+        let mut contents = build_file(3, &[
+            [1, 6, 0, 0, 0, -1, 0, 0],
+            [1, 6, 1, 0, 1, -1, 0, 0],
+            [1, 6, 2, 0, 2, -1, 0, 0],
+        ]);
+        contents[HEADER_SIZE + 3 * GRID_ENTRY_SIZE + COMPRESSED_IMAGE_SIZE] = 0xFF;
+
+        let bgst = Bgst::parse(&contents).unwrap();
+        let dedup = unique::dedup_blocks(bgst.header(), &contents).unwrap();
+        assert_eq!((dedup.unique_count(), dedup.total_count()), (2, 3));
+        assert_eq!([0, 1, 2].map(|index| dedup.canonical_index(index)), [0, 1, 0]);
+
+        let options = ExtractOptions { unique_images: true, ..Default::default() };
+        let written = unique::write_unique_images(&contents, &options, &root.to_string_lossy()).unwrap();
+        assert_eq!(written.dedup.unique_count(), 2);
+        assert!(root.join("img_1.png").exists());
+        assert!(!root.join("img_2.png").exists());
+
+        let _ = fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn buffers_can_be_extracted() {
+        let folder = std::env::temp_dir().join("bgst_buffers_can_be_extracted");
+        let contents = build_file(1, &[[1, 6, 0, 0, 0, -1, 0, 0]]);
+
+        assert!(extract_bgst_with(contents.as_slice(), &ExtractOptions::default()).is_err());
+
+        extract_bgst_to(contents.as_slice(), &folder.to_string_lossy(), &ExtractOptions::default()).unwrap();
+        assert!(folder.join("0.png").exists());
+
+        let _ = fs::remove_dir_all(&folder);
+    }
+
+    #[test]
+    fn indexed_png_round_trips() {
+        let image = RgbaImage::from_fn(16, 16, |x, y| {
+            Rgba([(x * 16) as u8, (y * 16) as u8, 0x40, if x < 8 { 0xFF } else { 0x80 }])
+        });
+
+        let png = indexed::encode_indexed_png(&image).unwrap().unwrap();
+        let decoded = image::load_from_memory(&png).unwrap().to_rgba8();
+        assert_eq!(decoded, image);
+
+        let noisy = RgbaImage::from_fn(32, 32, |x, y| Rgba([x as u8, y as u8, 0, 0xFF]));
+        assert!(indexed::encode_indexed_png(&noisy).unwrap().is_none());
+    }
+
+    #[cfg(feature = "gctex")]
+    #[test]
+    fn repacking_reencodes_blocks() {
+        let image = RgbaImage::from_fn(8, 8, |x, _| if x < 4 {
+            Rgba([0xFF, 0, 0, 0xFF])
+        } else {
+            Rgba([0, 0, 0xFF, 0xFF])
+        });
+
+        let mut contents = build_file(2, &[[1, 6, 0, 0, 0, 1, 0, 0]]);
+        let image_data_offset = HEADER_SIZE + GRID_ENTRY_SIZE;
+        for (index, format) in [(0, TextureFormat::CMPR), (1, TextureFormat::I4)] {
+            let block = pack::encode_block(image.as_raw(), 8, 8, format).unwrap();
+            let start = image_data_offset + index * COMPRESSED_IMAGE_SIZE;
+            contents[start..start + COMPRESSED_IMAGE_SIZE].copy_from_slice(&block);
+        }
+
+        pack::assert_lossless_roundtrip(&contents).unwrap();
+
+        contents[image_data_offset] ^= 0xFF;
+        let error = pack::assert_lossless_roundtrip(&contents).err().unwrap();
+        assert!(error.to_string().starts_with("entry 0: image 0 differs from the original at byte 0x0"));
+    }
+
+    #[cfg(feature = "gctex")]
+    #[test]
+    fn repacking_preserves_unknown_header_bytes() {
+        let mut contents = build_file(1, &[[1, 6, 0, 0, 0, -1, 0, 0]]);
+        contents[4..8].copy_from_slice(&[1, 2, 3, 4]);
+        contents[0x30..HEADER_SIZE].fill(0xAB);
+        let images = get_raw_images(&contents).unwrap();
+
+        let repacked = pack::pack_images_with_template(&images, &Default::default(), Some(&contents)).unwrap();
+        assert_eq!(repacked[..HEADER_SIZE], contents[..HEADER_SIZE]);
+
+        let repacked = pack::pack_images(&images, &Default::default()).unwrap();
+        assert!(repacked[0x30..HEADER_SIZE].iter().all(|byte| *byte == 0));
+    }
+
+    #[test]
+    fn images_can_be_named_by_entry() {
+        let folder = std::env::temp_dir().join("bgst_images_can_be_named_by_entry");
+        let contents = build_file(2, &[
+            [1, 6, 0, 0, 0, 1, 0, 0],
+            [1, 6, 1, 0, 1, -1, 0, 0],
+        ]);
+
+        let options = ExtractOptions { entry_names: true, ..Default::default() };
+        extract_bgst_to(contents, &folder.to_string_lossy(), &options).unwrap();
+
+        let mut names: Vec<String> = fs::read_dir(&folder)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        assert_eq!(names, ["entry0_main.png", "entry0_mask.png", "entry1_main.png"]);
+
+        fs::remove_dir_all(&folder).unwrap();
+    }
+
+    #[cfg(feature = "gctex")]
+    #[test]
+    fn manifests_rebuild_the_original_file() {
+        let folder = std::env::temp_dir().join("bgst_manifests_rebuild_the_original_file");
+        let _ = fs::remove_dir_all(&folder);
+        fs::create_dir_all(&folder).unwrap();
+
+        let image = RgbaImage::from_fn(8, 8, |x, _| if x < 4 {
+            Rgba([0xFF, 0, 0, 0xFF])
+        } else {
+            Rgba([0, 0, 0xFF, 0xFF])
+        });
+
+        let mut contents = build_file(2, &[
+            [1, 6, 0, 0, 0, 1, 3, 4],
+            [0, 7, 1, 0, 0, -1, 0, 0],
+        ]);
+        contents[4..8].copy_from_slice(&[1, 2, 3, 4]);
+        contents[0x30..HEADER_SIZE].fill(0xAB);
+        let image_data_offset = HEADER_SIZE + 2 * GRID_ENTRY_SIZE;
+        for (index, format) in [(0, TextureFormat::CMPR), (1, TextureFormat::I4)] {
+            let block = pack::encode_block(image.as_raw(), 8, 8, format).unwrap();
+            let start = image_data_offset + index * COMPRESSED_IMAGE_SIZE;
+            contents[start..start + COMPRESSED_IMAGE_SIZE].copy_from_slice(&block);
+        }
+
+        let options = ExtractOptions {
+            unique_images: true,
+            decode: DecodeOptions { scenes: SceneFilter::All, ..Default::default() },
+            ..Default::default()
+        };
+        unique::write_unique_images(&contents, &options, &folder.to_string_lossy()).unwrap();
+
+        let manifest = manifest::Manifest::load(&folder.join(unique::METADATA_FILENAME)).unwrap();
+        assert_eq!(manifest.version, manifest::MANIFEST_VERSION);
+        assert_eq!((manifest.entries[1].normalized_x, manifest.entries[1].normalized_y), (0.25, 0.0));
+        assert_eq!((manifest.entries[1].pixel_x, manifest.entries[1].pixel_y), (8, 0));
+        assert_eq!(pack::pack_bgst(&folder).unwrap(), contents);
+
+        fs::remove_dir_all(&folder).unwrap();
+    }
+
+    #[test]
+    fn flattened_tiles_are_named_by_entry() {
+        let folder = std::env::temp_dir().join("bgst_flattened_tiles_are_named_by_entry");
+        let contents = build_file(2, &[
+            [1, 6, 0, 0, -1, -1, 0, 0],
+            [1, 6, 1, 0, 0, 1, 0, 0],
+            [1, 6, 2, 0, 1, -1, 0, 0],
+        ]);
+        let options = ExtractOptions { flatten: true, ..Default::default() };
+        extract_bgst_to(contents.as_slice(), &folder.to_string_lossy(), &options).unwrap();
+
+        let mut names: Vec<String> = fs::read_dir(&folder)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        assert_eq!(names, ["1.png", "2.png"]);
+
+        let _ = fs::remove_dir_all(&folder);
+    }
+
+    #[test]
+    fn oversized_dimensions_are_rejected() {
+        let mut contents = build_file(0, &[]);
+        BigEndian::write_u32(&mut contents[0xC..0x10], 100_000);
+
+        let error = Bgst::parse(&contents).err().unwrap();
+        assert_eq!(error.to_string(), "image height 100000 exceeds the limit of 16384");
+
+        let limits = DimensionLimits { max_image_width: 4, ..Default::default() };
+        let error = get_raw_images_with(&contents, &DecodeOptions { limits, ..Default::default() }).err().unwrap();
+        assert_eq!(error.to_string(), "image width 8 exceeds the limit of 4");
+    }
+
+    #[cfg(feature = "gctex")]
+    #[test]
+    fn cmpr_transcodes_to_dxt1() {
+        let image = RgbaImage::from_fn(16, 8, |x, y| Rgba([(x * 16) as u8, (y * 32) as u8, 0x80, 0xFF]));
+        let cmpr = gctex::encode(TextureFormat::CMPR, image.as_raw(), 16, 8);
+        let expected = gctex::decode(&cmpr, 16, 8, TextureFormat::CMPR, &[], 0);
+
+        // the console interpolates between the endpoint colors slightly differently
+        let dds = dds::encode_cmpr_dds(&cmpr, 16, 8);
+        let decoded = image::load_from_memory_with_format(&dds, ImageFormat::Dds).unwrap().to_rgba8();
+        assert!(decoded.as_raw().iter().zip(&expected).all(|(a, b)| a.abs_diff(*b) <= 8));
+    }
+
+    #[test]
+    fn compaction_renumbers_images() {
+        let mut contents = build_file(4, &[
+            [1, 6, 0, 0, 3, -1, 0, 0],
+            [1, 6, 1, 0, 1, 3, 0, 0],
+        ]);
+        let image_data_offset = HEADER_SIZE + 2 * GRID_ENTRY_SIZE;
+        contents[image_data_offset + COMPRESSED_IMAGE_SIZE] = 0x11;
+        contents[image_data_offset + 3 * COMPRESSED_IMAGE_SIZE] = 0x33;
+
+        let mut bgst = Bgst::parse(&contents).unwrap();
+        compact_images(&mut bgst, &mut contents).unwrap();
+
+        assert_eq!(contents.len(), image_data_offset + 2 * COMPRESSED_IMAGE_SIZE);
+        assert_eq!(contents[image_data_offset], 0x11);
+        assert_eq!(contents[image_data_offset + COMPRESSED_IMAGE_SIZE], 0x33);
+
+        let reparsed = Bgst::parse(&contents).unwrap();
+        assert_eq!(reparsed.header().image_count, 2);
+        let indices: Vec<(i16, i16)> = reparsed.grid_entries()
+            .iter()
+            .map(|entry| (entry.main_image_index, entry.mask_image_index))
+            .collect();
+        assert_eq!(indices, [(1, -1), (0, 1)]);
+    }
+
+    #[test]
+    fn disabled_layers_are_skipped_by_default() {
+        let mut contents = build_file(2, &[
+            [1, 6, 0, 0, 0, -1, 0, 0],
+            [1, 7, 1, 0, 1, -1, 0, 0],
+        ]);
+        contents[0x1C + SceneIndex::Near01 as usize] = 0;
+
+        assert!(get_raw_images(&contents).is_ok());
+        assert_eq!(decodable_image_count(&Bgst::parse(&contents).unwrap()), 1);
+
+        let options = DecodeOptions { scenes: SceneFilter::All, ..Default::default() };
+        assert_eq!(get_raw_images_with(&contents, &options).unwrap().images.len(), 2);
+
+        let options = DecodeOptions { scenes: SceneFilter::Only(vec![SceneIndex::Near01]), ..Default::default() };
+        assert_eq!(get_raw_images_with(&contents, &options).unwrap().images.len(), 1);
+    }
+
+    #[cfg(all(feature = "builtin-cmpr", feature = "gctex"))]
+    #[test]
+    fn builtin_cmpr_matches_gctex() {
+        for (width, height) in [(8, 8), (24, 16), (12, 20)] {
+            let image = RgbaImage::from_fn(width, height, |x, y| {
+                let alpha = if (x + y) % 5 == 0 { 0 } else { 0xFF };
+                Rgba([(x * 37) as u8, (y * 53) as u8, ((x ^ y) * 11) as u8, alpha])
+            });
+
+            let encoded = gctex::encode(TextureFormat::CMPR, image.as_raw(), width, height);
+            let expected = gctex::decode(&encoded, width, height, TextureFormat::CMPR, &[], 0);
+            assert_eq!(cmpr::decode_cmpr_builtin(&encoded, width, height), expected);
+
+            // a reused buffer holding another image is overwritten completely
+            let mut reused = vec![0xAA; expected.len()];
+            cmpr::decode_cmpr_builtin_into(&mut reused, &encoded, width, height);
+            assert_eq!(reused, expected);
+        }
+    }
+
+    #[test]
+    fn scenes_are_blended_with_their_opacity() {
+        let mut contents = build_file(2, &[
+            [1, 6, 0, 0, 0, -1, 0, 0],
+            [1, 7, 0, 0, 1, -1, 0, 0],
+        ]);
+        // the Game tile is black and the Near01 tile is white
+        let image_data_offset = HEADER_SIZE + 2 * GRID_ENTRY_SIZE;
+        for block in contents[image_data_offset + COMPRESSED_IMAGE_SIZE..].chunks_exact_mut(8) {
+            block.copy_from_slice(&[0xFF, 0xFF, 0, 0, 0, 0, 0, 0]);
+        }
+        let images = get_raw_images(&contents).unwrap();
+
+        let opaque = composite_all(&images, &HashMap::new()).unwrap().unwrap();
+        assert_eq!(opaque.get_pixel(0, 0).0, [0xFF, 0xFF, 0xFF, 0xFF]);
+
+        let dimmed = composite_all(&images, &HashMap::from([(SceneIndex::Near01, 0.5)])).unwrap().unwrap();
+        assert!((0x70..0x90).contains(&dimmed.get_pixel(0, 0)[0]));
+
+        let hidden = composite_all(&images, &HashMap::from([(SceneIndex::Near01, 0.0)])).unwrap().unwrap();
+        assert_eq!(hidden.get_pixel(0, 0).0, [0, 0, 0, 0xFF]);
+
+        let everything_hidden = HashMap::from([(SceneIndex::Game, 0.0), (SceneIndex::Near01, 0.0)]);
+        assert!(composite_all(&images, &everything_hidden).unwrap().is_none());
+    }
+
+    #[test]
+    fn image_counts_are_broken_down() {
+        let mut contents = build_file(2, &[
+            [1, 6, 0, 0, 0, 1, 0, 0],
+            [1, 6, 1, 0, 0, -1, 0, 0],
+            [1, 7, 0, 0, 1, -1, 0, 0],
+        ]);
+        contents[0x1C + SceneIndex::Near01 as usize] = 0;
+
+        let images = get_raw_images(&contents).unwrap();
+        assert_eq!((images.main_count(), images.mask_count(), images.skipped_entries()), (2, 1, 1));
+        assert_eq!(images.images.len(), images.main_count() + images.mask_count());
+    }
+
+    #[test]
+    fn tiles_can_be_decoded_by_position() {
+        let mut contents = build_file(2, &[
+            [1, 6, 0, 0, 0, -1, 0, 0],
+            [1, 6, 1, 0, 0, -1, 0, 0],
+            [1, 6, 1, 0, 1, -1, 0, 0],
+        ]);
+        let image_data_offset = HEADER_SIZE + 3 * GRID_ENTRY_SIZE;
+        for block in contents[image_data_offset + COMPRESSED_IMAGE_SIZE..].chunks_exact_mut(8) {
+            block.copy_from_slice(&[0xFF, 0xFF, 0, 0, 0, 0, 0, 0]);
+        }
+
+        let tile = decode_at(&contents, SceneIndex::Game, 1, 0).unwrap().unwrap();
+        assert_eq!(tile.get_pixel(0, 0).0, [0xFF, 0xFF, 0xFF, 0xFF]);
+
+        let tile = decode_at(&contents, SceneIndex::Game, 0, 0).unwrap().unwrap();
+        assert_eq!(tile.get_pixel(0, 0).0, [0, 0, 0, 0xFF]);
+
+        assert!(decode_at(&contents, SceneIndex::Game, 2, 0).unwrap().is_none());
+        assert!(decode_at(&contents, SceneIndex::Near01, 0, 0).unwrap().is_none());
+    }
+
+    #[test]
+    fn scenes_can_be_peeked_without_image_data() {
+        let contents = build_file(2, &[
+            [1, 6, 0, 0, 0, -1, 0, 0],
+            [1, 6, 1, 0, 1, -1, 0, 0],
+            [1, 0, 0, 0, -1, -1, 0, 0],
+        ]);
+        let image_data_offset = HEADER_SIZE + 3 * GRID_ENTRY_SIZE;
+
+        let scenes = peek_scenes(&contents[..image_data_offset]).unwrap();
+        assert_eq!(scenes, BTreeMap::from([(SceneIndex::Far05, 1), (SceneIndex::Game, 2)]));
+
+        let error = peek_scenes(&contents[..image_data_offset - 1]).err().unwrap();
+        assert_eq!(error.to_string(), "file ends inside its entry table");
+    }
+
+    #[test]
+    fn entries_are_located_from_the_header() {
+        let contents = build_file(1, &[
+            [1, 6, 0, 0, 0, -1, 0, 0],
+            [1, 7, 3, 4, 0, -1, 0, 0],
+        ]);
+        let bgst = Bgst::parse(&contents).unwrap();
+
+        assert_eq!(entry_count(bgst.header()), 2);
+        let offset = entry_offset(bgst.header(), 1);
+        assert_eq!(offset, HEADER_SIZE + GRID_ENTRY_SIZE);
+        assert_eq!(contents[offset..offset + GRID_ENTRY_SIZE], bgst.grid_entries()[1].to_be_bytes());
+    }
+
+    #[test]
+    fn entries_serialize_to_the_bytes_they_were_parsed_from() {
+        // a fixed xorshift, so any failure can be reproduced
+        let mut state = 0x2545_F491u32;
+        let entries: Vec<[i16; 8]> = (0..64)
+            .map(|_| std::array::from_fn(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                state as i16
+            }))
+            .collect();
+
+        let contents = build_file(0, &entries);
+        let bgst = Bgst::parse(&contents).unwrap();
+
+        let serialized: Vec<u8> = bgst.grid_entries().iter().flat_map(GridEntry::to_be_bytes).collect();
+        assert_eq!(serialized, contents[HEADER_SIZE..]);
+    }
+
+    #[test]
+    fn tiled_maps_share_repeated_tiles() {
+        let contents = build_file(2, &[
+            [1, 6, 0, 0, 0, -1, 0, 0],
+            [1, 6, 1, 1, 0, -1, 0, 0],
+            [1, 7, 2, 0, 1, -1, 0, 0],
+        ]);
+        let images = get_raw_images(&contents).unwrap();
+
+        let map = tmx::build_tmx(&images, "tiles.png").unwrap();
+        assert_eq!(map.atlas.dimensions(), (16, 8));
+        assert!(map.tmx.contains("tilecount=\"2\""));
+        assert!(map.tmx.contains("<layer id=\"1\" name=\"Game\" width=\"4\" height=\"4\">"));
+        assert!(map.tmx.contains("1,0,0,0,\n0,1,0,0,\n0,0,0,0,\n0,0,0,0\n"));
+        assert!(map.tmx.contains("0,0,2,0,\n0,0,0,0,\n"));
+
+        let map = tmx::build_tmx(&images, "a&b <\"c\">.png").unwrap();
+        assert!(map.tmx.contains("<image source=\"a&amp;b &lt;&quot;c&quot;&gt;.png\""));
+    }
+
+    #[test]
+    fn files_without_entries_are_not_extracted() {
+        let folder = std::env::temp_dir().join("bgst_files_without_entries_are_not_extracted");
+        let _ = fs::remove_dir_all(&folder);
+        let contents = build_file(0, &[]);
+
+        assert!(get_raw_images(&contents).unwrap().images.is_empty());
+
+        extract_bgst_to(contents, &folder.to_string_lossy(), &ExtractOptions::default()).unwrap();
+        assert!(!folder.exists());
+    }
+
+    #[test]
+    fn thread_limits_bound_parallel_work() {
+        let threads = with_thread_limit(2, rayon::current_num_threads).unwrap();
+        assert_eq!(threads, 2);
+        assert!(with_thread_limit(0, || ()).is_err());
+    }
+
+    #[test]
+    fn entry_views_read_in_place() {
+        let contents = build_file(1, &[
+            [1, 6, 0, 0, 0, -1, 0, 0],
+            [0, 7, 3, 4, -1, 0, 5, -6],
+        ]);
+        let bgst = Bgst::parse(&contents).unwrap();
+
+        let views: Vec<GridEntryView> = entry_views(&contents).unwrap().collect();
+        assert_eq!(views.len(), 2);
+        assert!(!views[1].is_enabled());
+        assert_eq!((views[1].scene_index(), views[1].grid_x(), views[1].unk_e()), (7, 3, -6));
+
+        for (view, entry) in views.iter().zip(bgst.grid_entries()) {
+            assert_eq!(view.bytes(), entry.to_be_bytes());
+            assert_eq!(view.to_entry().to_be_bytes(), entry.to_be_bytes());
+        }
+    }
+
+    #[test]
+    fn filmstrips_keep_entry_order() {
+        let mut contents = build_file(2, &[
+            [1, 6, 0, 0, 1, -1, 0, 0],
+            [1, 6, 1, 0, 0, -1, 0, 0],
+            [1, 6, 2, 0, -1, -1, 0, 0],
+        ]);
+        let image_data_offset = HEADER_SIZE + 3 * GRID_ENTRY_SIZE;
+        for block in contents[image_data_offset + COMPRESSED_IMAGE_SIZE..].chunks_exact_mut(8) {
+            block.copy_from_slice(&[0xFF, 0xFF, 0, 0, 0, 0, 0, 0]);
+        }
+        let images = get_raw_images(&contents).unwrap();
+
+        let strip = filmstrip(&images, FilmstripDirection::Vertical).unwrap().unwrap();
+        assert_eq!(strip.dimensions(), (8, 16));
+        assert_eq!(strip.get_pixel(0, 0).0, [0xFF, 0xFF, 0xFF, 0xFF]);
+        assert_eq!(strip.get_pixel(0, 8).0, [0, 0, 0, 0xFF]);
+
+        let strip = filmstrip(&images, FilmstripDirection::Horizontal).unwrap().unwrap();
+        assert_eq!(strip.dimensions(), (16, 8));
+        assert_eq!(strip.get_pixel(8, 0).0, [0, 0, 0, 0xFF]);
+    }
+
+    #[test]
+    fn scenes_are_written_in_their_own_formats() {
+        let folder = std::env::temp_dir().join("bgst_scenes_are_written_in_their_own_formats");
+        let _ = fs::remove_dir_all(&folder);
+        let contents = build_file(1, &[
+            [1, 6, 0, 0, 0, -1, 0, 0],
+            [1, 4, 0, 0, 0, -1, 0, 0],
+        ]);
+        let images = get_raw_images(&contents).unwrap();
+
+        let formats = HashMap::from([(SceneIndex::Far01, ImageFormat::WebP)]);
+        export_scenes(&images, &folder.to_string_lossy(), &formats).unwrap();
+
+        assert!(folder.join("Game.png").exists());
+        let far = image::open(folder.join("Far01.webp")).unwrap();
+        assert_eq!(far.dimensions(), (32, 32));
+
+        fs::remove_dir_all(&folder).unwrap();
+    }
+
+    #[test]
+    fn oversized_canvases_are_rejected() {
+        let mut contents = build_file(1, &[[1, 6, 0, 0, 0, -1, 0, 0]]);
+        contents[0x10..0x14].copy_from_slice(&0x7FFF_FFFFu32.to_be_bytes());
+        let images = get_raw_images(&contents).unwrap();
+
+        let error = composite_scene(&images, SceneIndex::Game).err().unwrap();
+        assert!(error.to_string().starts_with("a canvas of 2147483647x4 tiles"));
+
+        let limits = DimensionLimits { max_canvas_pixels: 8 * 8 * 16 - 1, ..Default::default() };
+        let contents = build_file(1, &[[1, 6, 0, 0, 0, -1, 0, 0]]);
+        let images = get_raw_images_with(&contents, &DecodeOptions { limits, ..Default::default() }).unwrap();
+        assert!(composite_scene(&images, SceneIndex::Game).is_err());
+    }
+
+    #[cfg(feature = "yaz0")]
+    #[test]
+    fn yaz0_files_are_decompressed() {
+        // two literals, then a copy of 4 bytes from 2 bytes back
+        let mut compressed = b"Yaz0".to_vec();
+        compressed.extend_from_slice(&6u32.to_be_bytes());
+        compressed.extend_from_slice(&[0; 8]);
+        compressed.extend_from_slice(&[0b1100_0000, b'a', b'b', 0x20, 0x01]);
+        assert_eq!(yaz0::decompress(&compressed).unwrap(), b"ababab");
+
+        let contents = build_file(1, &[[1, 6, 0, 0, 0, -1, 0, 0]]);
+        assert_eq!(yaz0::decompress_if_compressed(contents.clone()).unwrap(), contents);
+
+        // buffers are decompressed the same as files, here with every byte stored as a literal
+        let mut stored = b"Yaz0".to_vec();
+        stored.extend_from_slice(&(contents.len() as u32).to_be_bytes());
+        stored.extend_from_slice(&[0; 8]);
+        for group in contents.chunks(8) {
+            stored.push(0xFF);
+            stored.extend_from_slice(group);
+        }
+        assert_eq!(read_source(stored.as_slice()).unwrap(), contents);
+
+        compressed.truncate(compressed.len() - 1);
+        assert!(yaz0::decompress(&compressed).is_err());
+
+        // a huge declared size isn't reserved up front
+        let mut huge = b"Yaz0".to_vec();
+        huge.extend_from_slice(&u32::MAX.to_be_bytes());
+        huge.extend_from_slice(&[0; 9]);
+        assert!(yaz0::decompress(&huge).is_err());
+    }
+
+    #[test]
+    fn entries_are_exported_as_csv() {
+        let contents = build_file(1, &[[1, 6, 2, 3, 0, -1, 7, -8]]);
+        let csv = csv::entries_csv(&Bgst::parse(&contents).unwrap());
+
+        let rows: Vec<&str> = csv.lines().collect();
+        assert_eq!(rows, [csv::CSV_COLUMNS, "0,1,6,Game,2,3,0,-1,7,-8"]);
+    }
+
+    #[test]
+    fn warnings_are_collected() {
+        let mut contents = build_file(2, &[
+            [1, 6, 0, 0, 0, 5, 0, 0],
+            [1, 6, 0, 0, 1, -1, 0, 0],
+            [1, 7, 1, 0, -1, -1, 0, 0],
+        ]);
+        contents[0x1C + SceneIndex::Near01 as usize] = 0;
+        let image_data_offset = HEADER_SIZE + 3 * GRID_ENTRY_SIZE;
+        contents[image_data_offset + COMPRESSED_IMAGE_SIZE] = 0xFF;
+
+        let outcome = warnings::get_raw_images_with_warnings(&contents, &DecodeOptions::default()).unwrap();
+        let kinds: Vec<(warnings::WarningKind, usize)> = outcome.warnings
+            .iter()
+            .map(|warning| (warning.kind, warning.entry_index))
+            .collect();
+
+        assert_eq!(kinds, [
+            (warnings::WarningKind::DanglingReference, 0),
+            (warnings::WarningKind::EmptyBlock, 0),
+            (warnings::WarningKind::Overlap, 1),
+            (warnings::WarningKind::DisabledLayer, 2),
+        ]);
+        assert_eq!(outcome.images.images.len(), 2);
+    }
+
+    #[test]
+    fn fully_masked_tiles_are_reported() {
+        let mut contents = build_file(3, &[
+            [1, 6, 0, 0, 0, 1, 0, 0],
+            [1, 6, 1, 0, 0, 2, 0, 0],
+        ]);
+        // mask 1 is black everywhere, which hides every pixel, and mask 2 is white
+        let image_data_offset = HEADER_SIZE + 2 * GRID_ENTRY_SIZE;
+        contents[image_data_offset + 2 * COMPRESSED_IMAGE_SIZE..].fill(0xFF);
+
+        let warnings = warnings::check_masked_tiles(&contents, &DecodeOptions::default()).unwrap();
+        let flagged: Vec<(warnings::WarningKind, usize)> = warnings
+            .iter()
+            .map(|warning| (warning.kind, warning.entry_index))
+            .collect();
+        assert_eq!(flagged, [(warnings::WarningKind::FullyMasked, 0)]);
+    }
+
+    #[test]
+    fn decoding_stops_at_the_image_cap() {
+        let contents = build_file(2, &[
+            [1, 6, 0, 0, 0, -1, 0, 0],
+            [1, 7, 0, 0, 0, -1, 0, 0],
+            [1, 6, 1, 0, 0, 1, 0, 0],
+            [1, 6, 2, 0, 1, -1, 0, 0],
+        ]);
+        let mut options = DecodeOptions {
+            scenes: SceneFilter::Only(vec![SceneIndex::Game]),
+            max_images: Some(2),
+            ..Default::default()
+        };
+
+        // entry 1 is filtered out, and entry 2 needs two images, which no longer fit after entry 0
+        let images = get_raw_images_with(&contents, &options).unwrap();
+        assert_eq!(images.images.len(), 1);
+
+        options.max_images = Some(3);
+        let images = get_raw_images_with(&contents, &options).unwrap();
+        assert_eq!(images.images.len(), 3);
+    }
+
+    #[test]
+    fn masks_can_be_decoded_alone() {
+        let contents = build_file(3, &[
+            [1, 6, 0, 0, 0, 1, 0, 0],
+            [1, 6, 1, 0, 2, -1, 0, 0],
+        ]);
+        let options = DecodeOptions { masks_only: true, ..Default::default() };
+
+        let images = get_raw_images_with(&contents, &options).unwrap();
+        assert_eq!(images.images.len(), 1);
+        assert_eq!(masked_tiles(&images).count(), 0);
+    }
+
+    #[test]
+    fn block_size_can_be_overridden() {
+        let mut contents = build_file(0, &[[1, 6, 0, 0, 0, 1, 0, 0]]);
+        BigEndian::write_u32(&mut contents[0x18..0x1C], 2);
+        contents.extend_from_slice(&[0; 0x40]);
+
+        assert!(get_raw_images(&contents).is_err());
+
+        let mut options = DecodeOptions::default();
+        options.overrides.block_size = Some(0x20);
+        assert_eq!(get_raw_images_with(&contents, &options).unwrap().images.len(), 2);
+    }
+
+    #[test]
+    fn mask_indices_share_the_block_stride() {
+        for format in [TextureFormat::CMPR, TextureFormat::I4] {
+            assert_eq!(texture::image_size(format, 512, 512), COMPRESSED_IMAGE_SIZE);
+        }
+
+        let mut contents = build_file(3, &[[1, 6, 0, 0, 0, 2, 0, 0]]);
+        let mask_start = HEADER_SIZE + GRID_ENTRY_SIZE + 2 * COMPRESSED_IMAGE_SIZE;
+        contents[mask_start..mask_start + 0x20].fill(0xFF);
+
+        let images = get_raw_images(&contents).unwrap();
+        assert!(images.images[1].iter().all(|channel| *channel == 0xFF));
+    }
+
+    #[test]
+    fn truncated_file_is_rejected() {
+        let mut contents = build_file(2, &[[1, 6, 0, 0, 0, -1, 0, 0]]);
+        contents.truncate(contents.len() - 0x10);
+
+        let error = get_raw_images(&contents).err().unwrap();
+        assert!(error.to_string().starts_with("file truncated by 16 bytes"));
+    }
+
+    #[test]
+    fn image_blocks_are_bounds_checked() {
+        let mut contents = build_file(2, &[[1, 6, 0, 0, 0, -1, 0, 0]]);
+        let second_block = contents.len() - COMPRESSED_IMAGE_SIZE;
+        contents[second_block] = 0xAB;
+        let bgst = Bgst::parse(&contents).unwrap();
+
+        let block = image_block(&bgst, &contents, 1).unwrap();
+        assert_eq!(block.len(), COMPRESSED_IMAGE_SIZE);
+        assert_eq!(block[0], 0xAB);
+        assert!(image_block(&bgst, &contents, 2).is_err());
+    }
+
+    #[test]
+    fn grid_rect_bounds() {
+        let region = GridRect { x: 0, y: 0, width: 4, height: 4 };
+
+        assert!(region.contains(0, 0));
+        assert!(region.contains(3, 3));
+        assert!(!region.contains(4, 0));
+        assert!(!region.contains(0, -1));
+    }
+
+    #[test]
+    fn images_are_looked_up_by_entry() {
+        let contents = build_file(2, &[
+            [1, 6, 0, 0, 0, 1, 0, 0],
+            [1, 6, 1, 0, 0, -1, 0, 0],
+            [1, 6, 2, 0, -1, 1, 0, 0],
+        ]);
+        let images = get_raw_images(&contents).unwrap();
+
+        assert_eq!(images.get_main(0), Some(images.images[0].as_slice()));
+        assert_eq!(images.get_mask(0), Some(images.images[1].as_slice()));
+        assert_eq!(images.get_main(1), Some(images.images[2].as_slice()));
+        assert_eq!(images.get_mask(1), None);
+        assert_eq!(images.get_main(2), None);
+        assert_eq!(images.get_mask(2), Some(images.images[3].as_slice()));
+        assert_eq!(images.get_main(3), None);
+
+        // entries keep their index in the file when earlier ones are skipped
+        let options = DecodeOptions { region: Some(GridRect { x: 1, y: 0, width: 2, height: 1 }), ..Default::default() };
+        let images = get_raw_images_with(&contents, &options).unwrap();
+        assert_eq!(images.get_main(0), None);
+        assert_eq!(images.get_main(1), Some(images.images[0].as_slice()));
+        assert_eq!(images.get_mask(2), Some(images.images[1].as_slice()));
+    }
+
+    #[test]
+    fn rows_are_padded_to_the_pitch() {
+        let contents = build_file(1, &[[1, 6, 0, 0, 0, -1, 0, 0]]);
+        let tight = get_raw_images(&contents).unwrap();
+        let padded = get_raw_images_with_pitch(&contents, &DecodeOptions::default(), 40).unwrap();
 
+        assert_eq!(padded.len(), 1);
+        assert_eq!(padded[0].len(), 8 * 40);
+        for (row, padded_row) in tight.images[0].chunks(32).zip(padded[0].chunks(40)) {
+            assert_eq!(&padded_row[..32], row);
+            assert_eq!(&padded_row[32..], &[0; 8]);
+        }
 
-pub fn apply_mask(
-    main_image: &Vec<u8>,
-    mask_image: &Vec<u8>,
-    width: u32,
-    height: u32
-) -> Result<Vec<u8>> {
-    if main_image.len() != mask_image.len() {
-        bail!("the image sizes are not equal!");
+        assert!(get_raw_images_with_pitch(&contents, &DecodeOptions::default(), 16).is_err());
     }
 
-    // decode the main and mask images from raw rgba bytes
+    #[test]
+    fn unknown_scenes_are_found() {
+        let contents = build_file(1, &[
+            [1, 0, 0, 0, 0, -1, 0, 0],
+            [1, 11, 0, 0, 0, -1, 0, 0],
+            [1, 12, 0, 0, 0, -1, 0, 0],
+            [1, -1, 0, 0, 0, -1, 0, 0],
+        ]);
 
-    let main_img: RgbaImage = ImageBuffer::from_raw(width, height, main_image.clone())
-        .ok_or_else(|| anyhow::anyhow!("failed to decode main image"))?;
-    let mask_img: RgbaImage = ImageBuffer::from_raw(width, height, mask_image.clone())
-        .ok_or_else(|| anyhow::anyhow!("failed to decode mask image"))?;
+        assert_eq!(validate_scenes(&Bgst::parse(&contents).unwrap()), [2, 3]);
+    }
 
-    let mut output_img = RgbaImage::new(width, height);
+    #[test]
+    fn tiles_are_annotated() {
+        let contents = build_file(1, &[[1, 6, 2, 3, 0, -1, 0, 0]]);
+        let bgst = Bgst::parse(&contents).unwrap();
+        let mut tile = RgbaImage::from_pixel(8, 8, Rgba([0x80; 4]));
+        annotate::annotate_tile(&mut tile, 7, &bgst.grid_entries[0]);
 
+        // the background starts at the top left, and the top of the 7 is white
+        assert_eq!(tile.get_pixel(0, 0), &Rgba([0, 0, 0, 0xFF]));
+        assert_eq!(tile.get_pixel(1, 1), &Rgba([0xFF; 4]));
+        // the second line starts below the first, and the right of the tile is untouched
+        assert_eq!(tile.get_pixel(0, 7), &Rgba([0, 0, 0, 0xFF]));
+        assert_eq!(tile.get_pixel(7, 0), &Rgba([0x80; 4]));
 
-    for (x, y, pixel) in output_img.enumerate_pixels_mut() {
-        let main_pixel = main_img.get_pixel(x, y);
-        let mask_pixel = mask_img.get_pixel(x, y);
+        // annotated images keep the names they'd have without it
+        let folder = std::env::temp_dir().join("bgst_tiles_are_annotated");
+        let options = ExtractOptions { annotate: true, ..Default::default() };
+        extract_bgst_to(contents.as_slice(), &folder.to_string_lossy(), &options).unwrap();
+        let image = image::open(folder.join("0.png")).unwrap().to_rgba8();
+        assert_eq!(image.get_pixel(1, 1), &Rgba([0xFF; 4]));
 
-        // if the mask pixel is black (r=0, g=0, b=0), set alpha of main image to 0
-        if mask_pixel[0] == 0 && mask_pixel[1] == 0 && mask_pixel[2] == 0 {
-            *pixel = Rgba([main_pixel[0], main_pixel[1], main_pixel[2], 0]); // make transparent
-        } else {
-            *pixel = *main_pixel; // keep original pixel
+        let options = ExtractOptions { format: OutputFormat::Dds, ..options };
+        extract_bgst_to(contents.as_slice(), &folder.to_string_lossy(), &options).unwrap();
+        let dds = fs::read(folder.join("0.dds")).unwrap();
+        assert_eq!(dds[128 + (8 + 1) * 4..][..4], [0xFF; 4]);
+
+        let _ = fs::remove_dir_all(&folder);
+    }
+
+    #[cfg(feature = "gctex")]
+    #[test]
+    fn masks_are_decoded_in_the_given_format() {
+        let mut contents = build_file(2, &[[1, 6, 0, 0, 0, 1, 0, 0]]);
+        let mask_block = HEADER_SIZE + GRID_ENTRY_SIZE + COMPRESSED_IMAGE_SIZE;
+        let mask: Vec<u8> = (0..64).map(|i| i * 4).collect();
+        contents[mask_block..mask_block + 64].copy_from_slice(&mask);
+
+        let images = get_raw_images_as(&contents, TextureFormat::CMPR, TextureFormat::I8).unwrap();
+        let expected = gctex::decode(&mask, 8, 8, TextureFormat::I8, &[], 0);
+        assert_eq!(images.get_mask(0), Some(expected.as_slice()));
+
+        let default = get_raw_images(&contents).unwrap();
+        assert_ne!(default.get_mask(0), Some(expected.as_slice()));
+    }
+
+    #[test]
+    fn changed_tiles_are_found() {
+        let original = build_file(2, &[
+            [1, 6, 0, 0, 0, -1, 0, 0],
+            [1, 6, 1, 0, 1, -1, 0, 0],
+        ]);
+        let mut modified = build_file(2, &[
+            [1, 6, 0, 0, 0, -1, 0, 0],
+            [1, 6, 1, 0, 1, -1, 0, 0],
+            [1, 6, 2, 0, 0, -1, 0, 0],
+        ]);
+        let second_block = modified.len() - COMPRESSED_IMAGE_SIZE;
+        modified[second_block..second_block + 2].copy_from_slice(&[0xFF, 0xFF]);
+
+        let changed = diff::changed_entries(&original, &modified, &DecodeOptions::default()).unwrap();
+        assert_eq!(changed, [1, 2]);
+        assert!(diff::changed_entries(&original, &original, &DecodeOptions::default()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn padded_entry_tables_are_parsed() {
+        let entries = [[1, 6, 0, 0, 0, -1, 0, 0], [1, 6, 1, 0, 0, -1, 0, 0]];
+        let mut contents = build_file(1, &entries);
+
+        // move the image data 8 bytes further, leaving padding after the entries
+        let image_data_offset = HEADER_SIZE + 2 * GRID_ENTRY_SIZE;
+        contents.splice(image_data_offset..image_data_offset, [0xEE; 8]);
+        BigEndian::write_u32(&mut contents[0x2C..0x30], image_data_offset as u32 + 8);
+
+        let bgst = Bgst::parse(&contents).unwrap();
+        assert_eq!(bgst.grid_entries().len(), 2);
+        assert_eq!(entry_table_padding(bgst.header()), 8);
+        assert!(warnings::collect_warnings(&bgst, &contents)
+            .iter()
+            .any(|warning| warning.kind == warnings::WarningKind::PaddedEntryTable));
+        assert_eq!(get_raw_images(&contents).unwrap().main_count(), 2);
+    }
+
+    #[test]
+    fn tiles_are_written_as_tiff_pages() {
+        let contents = build_file(1, &[
+            [1, 6, 0, 0, 0, -1, 0, 0],
+            [1, 6, 1, 0, 0, -1, 0, 0],
+            [1, 6, 2, 0, -1, 0, 0, 0],
+        ]);
+        let images = get_raw_images(&contents).unwrap();
+
+        let mut buffer = Cursor::new(Vec::new());
+        assert_eq!(tiff::write_tiff(&images, &mut buffer).unwrap(), 2);
+
+        let mut decoder = ::tiff::decoder::Decoder::new(Cursor::new(buffer.into_inner())).unwrap();
+        let mut pages = 1;
+        assert_eq!(decoder.dimensions().unwrap(), (8, 8));
+        while decoder.more_images() {
+            decoder.next_image().unwrap();
+            pages += 1;
         }
+        assert_eq!(pages, 2);
     }
 
-    let output_bytes = output_img.into_raw();
+    #[cfg(feature = "gctex")]
+    #[test]
+    fn linear_blocks_are_tiled_before_decoding() {
+        // two 8x4 tiles of I8, side by side
+        let linear: Vec<u8> = (0..64).collect();
+        let tiled = swizzle::tile_linear(&linear, 16, 4, TextureFormat::I8);
+        assert_eq!(&tiled[..8], &linear[..8]);
+        assert_eq!(&tiled[8..16], &linear[16..24]);
+        assert_eq!(&tiled[32..40], &linear[8..16]);
 
-    Ok(output_bytes)
-}
+        // a smooth gradient, stored linearly
+        let mut contents = build_file(1, &[[1, 6, 0, 0, 0, -1, 0, 0]]);
+        let block = HEADER_SIZE + GRID_ENTRY_SIZE;
+        let gradient: Vec<u8> = (0..128).map(|i| (i % 16 * 8 + i / 16 * 4) as u8).collect();
+        contents[block..block + 128].copy_from_slice(&gradient);
 
-/// Does not work properly!
-pub fn get_png_images(
-    raw_images: &ImageList,
-    do_mask: bool,
-) -> Result<Vec<Vec<u8>>> {
-    let mut result = Vec::new();
+        let options = |layout| DecodeOptions {
+            overrides: FormatOverrides {
+                image_width: Some(16),
+                image_height: Some(8),
+                main_format: Some(TextureFormat::I8),
+                layout: Some(layout),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let decode = |layout| get_raw_images_with(&contents, &options(layout)).unwrap().images.remove(0);
 
-    if do_mask {
-        for i in 0..raw_images.grid_entries.len() {
-            let entry = &raw_images.grid_entries[i];
-            
-            let mut decoded;
-
-            if entry.main_image_index > -1 && entry.main_image_index < raw_images.images.len() as i16 {
-                let main_image = &raw_images.images[entry.main_image_index as usize];
-                decoded = main_image.to_owned();
-
-                if entry.mask_image_index > -1 && entry.mask_image_index < raw_images.images.len() as i16 {
-                    let mask_image = &raw_images.images[entry.mask_image_index as usize];
-
-                    let masked = apply_mask(
-                        main_image,
-                        mask_image,
-                        raw_images.image_width,
-                        raw_images.image_height
-                    )?;
-
-                    decoded = masked;
-                }
-            } else {
-                continue;
-            }
+        let linear = decode(TileLayout::Linear);
+        let expected = gctex::decode(
+            &swizzle::tile_linear(&gradient, 16, 8, TextureFormat::I8),
+            16,
+            8,
+            TextureFormat::I8,
+            &[],
+            0
+        );
+        assert_eq!(linear, expected);
+        assert_eq!(linear[(3 * 16 + 9) * 4], gradient[3 * 16 + 9]);
+        assert!(swizzle::roughness(&linear, 16, 8) < swizzle::roughness(&decode(TileLayout::Tiled), 16, 8));
+        assert_eq!(decode(TileLayout::Detect), linear);
+    }
 
-            if let Some(img) = RgbaImage::from_raw(
-                raw_images.image_width,
-                raw_images.image_height,
-                decoded
-            ) {
-                let mut buffer = Cursor::new(Vec::new());
-    
-                img.write_to(&mut buffer, ImageFormat::Png)?;
-    
-                result.push(buffer.into_inner());
+    #[test]
+    fn files_can_be_extracted_asynchronously() {
+        use std::future::Future;
+        use std::task::{Context, Poll, Wake, Waker};
+
+        struct ThreadWaker(std::thread::Thread);
+        impl Wake for ThreadWaker {
+            fn wake(self: Arc<Self>) {
+                self.0.unpark();
             }
         }
-    } else {
-        for raw_image in &raw_images.images {
-            if let Some(img) = RgbaImage::from_raw(
-                raw_images.image_width,
-                raw_images.image_height,
-                raw_image.to_owned()
-            ) {
-                let mut buffer = Cursor::new(Vec::new());
-    
-                img.write_to(&mut buffer, ImageFormat::Png)?;
-    
-                result.push(buffer.into_inner());
+
+        let folder = std::env::temp_dir().join("bgst_files_can_be_extracted_asynchronously");
+        let contents = build_file(2, &[[1, 6, 0, 0, 0, 1, 0, 0]]);
+        let (sender, receiver) = std::sync::mpsc::channel();
+
+        let mut task = Box::pin(tasks::extract_bgst_async(
+            contents,
+            folder.to_string_lossy().into_owned(),
+            ExtractOptions::default(),
+            Some(sender)
+        ));
+        let waker = Waker::from(Arc::new(ThreadWaker(std::thread::current())));
+        let mut cx = Context::from_waker(&waker);
+        let result = loop {
+            match task.as_mut().poll(&mut cx) {
+                Poll::Ready(result) => break result,
+                Poll::Pending => std::thread::park(),
             }
+        };
+
+        result.unwrap();
+        assert!(folder.join("0.png").exists());
+        let events: Vec<tasks::ExtractProgress> = receiver.try_iter().collect();
+        assert_eq!(events, [
+            tasks::ExtractProgress::Started,
+            tasks::ExtractProgress::Decoded { done: 1, total: 2 },
+            tasks::ExtractProgress::Decoded { done: 2, total: 2 },
+            tasks::ExtractProgress::Finished(Ok(())),
+        ]);
+
+        let _ = fs::remove_dir_all(&folder);
+    }
+
+    #[test]
+    fn alpha_is_stripped() {
+        let folder = std::env::temp_dir().join("bgst_alpha_is_stripped");
+        // all 0xFF decodes to transparent CMPR
+        let mut contents = build_file(2, &[[1, 6, 0, 0, 0, 1, 0, 0]]);
+        let block = HEADER_SIZE + GRID_ENTRY_SIZE;
+        contents[block..block + 32].fill(0xFF);
+        let options = ExtractOptions { strip_alpha: true, ..Default::default() };
+
+        extract_bgst_to(contents.as_slice(), &folder.to_string_lossy(), &options).unwrap();
+        let image = image::open(folder.join("0.png")).unwrap().to_rgba8();
+        assert!(image.pixels().all(|pixel| pixel[3] == 0xFF));
+
+        // a black I4 mask has zero alpha, which is kept
+        let mask = image::open(folder.join("1.png")).unwrap().to_rgba8();
+        assert!(mask.pixels().all(|pixel| pixel[3] == 0));
+
+        // the same goes for uncompressed DDS textures, whose alpha is the fourth byte of every pixel
+        let options = ExtractOptions { format: OutputFormat::Dds, ..options };
+        extract_bgst_to(contents.as_slice(), &folder.to_string_lossy(), &options).unwrap();
+        let alpha = |name: &str| fs::read(folder.join(name)).unwrap()[128..].chunks(4).map(|pixel| pixel[3]).collect::<Vec<u8>>();
+        assert!(alpha("0.dds").iter().all(|alpha| *alpha == 0xFF));
+        assert!(alpha("1.dds").iter().all(|alpha| *alpha == 0));
+
+        let _ = fs::remove_dir_all(&folder);
+    }
+
+    #[test]
+    fn referenced_indices_are_collected() {
+        let contents = build_file(4, &[
+            [1, 6, 0, 0, 0, 1, 0, 0],
+            [1, 6, 1, 0, 0, -1, 0, 0],
+            [1, 6, 2, 0, 2, 9, 0, 0],
+        ]);
+        let (mains, masks) = referenced_indices(&Bgst::parse(&contents).unwrap());
+
+        assert_eq!(mains.into_iter().collect::<Vec<i16>>(), [0, 2]);
+        assert_eq!(masks.into_iter().collect::<Vec<i16>>(), [1]);
+    }
+
+    #[test]
+    fn entries_are_exported_as_json_lines() {
+        let contents = build_file(1, &[[1, 6, 2, 3, 0, -1, 7, -8], [0, 7, 0, 0, -1, -1, 0, 0]]);
+        let mut output = Vec::new();
+        jsonl::write_entries_jsonl(&Bgst::parse(&contents).unwrap(), &mut output).unwrap();
+
+        let lines: Vec<serde_json::Value> = String::from_utf8(output)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["grid_y"], 3);
+        assert_eq!(lines[0]["unk_e"], -8);
+        assert_eq!(lines[1]["entry_index"], 1);
+        assert_eq!(lines[1]["enabled"], 0);
+    }
+
+    #[test]
+    fn sheets_are_resliced_into_tiles() {
+        let sheet = RgbaImage::from_fn(16, 16, |x, y| Rgba([(x / 8) as u8, (y / 8) as u8, 0, 0xFF]));
+        let tiles = reslice_contact_sheet(&sheet, 2, 8, 8).unwrap();
+
+        assert_eq!(tiles.len(), 4);
+        assert!(tiles.iter().all(|tile| tile.dimensions() == (8, 8)));
+        assert_eq!(tiles[1].get_pixel(7, 7), &Rgba([1, 0, 0, 0xFF]));
+        assert_eq!(tiles[2].get_pixel(0, 0), &Rgba([0, 1, 0, 0xFF]));
+
+        assert!(reslice_contact_sheet(&sheet, 3, 8, 8).is_err());
+        assert!(reslice_contact_sheet(&sheet, 2, 8, 6).is_err());
+    }
+
+    #[test]
+    fn entries_are_decoded_into_buffers() {
+        let mut contents = build_file(2, &[
+            [1, 6, 0, 0, 0, -1, 0, 0],
+            [1, 6, 1, 0, 0, 1, 0, 0],
+            [1, 6, 2, 0, -1, -1, 0, 0],
+        ]);
+        let block = HEADER_SIZE + 3 * GRID_ENTRY_SIZE;
+        for chunk in contents[block..block + 32].chunks_exact_mut(8) {
+            chunk.copy_from_slice(&[0xFF, 0xFF, 0, 0, 0, 0, 0, 0]);
         }
+
+        let mut out = vec![0xAA; 8 * 8 * 4];
+        decode_entry_into(&contents, 0, &mut out).unwrap();
+        assert_eq!(out, get_raw_images(&contents).unwrap().get_main(0).unwrap());
+
+        // a zeroed mask is black everywhere, so it hides every pixel
+        decode_entry_into(&contents, 1, &mut out).unwrap();
+        assert!(out.chunks_exact(4).all(|pixel| pixel == [0xFF, 0xFF, 0xFF, 0]));
+
+        assert!(decode_entry_into(&contents, 2, &mut out).is_err());
+        assert!(decode_entry_into(&contents, 3, &mut out).is_err());
+        assert!(decode_entry_into(&contents, 0, &mut out[..16]).is_err());
     }
 
-    Ok(result)
-}
+    #[test]
+    fn off_by_one_image_counts_are_repaired() {
+        let mut contents = build_file(2, &[
+            [1, 6, 0, 0, 0, -1, 0, 0],
+            [1, 6, 1, 0, 1, -1, 0, 0],
+        ]);
+        contents.truncate(contents.len() - COMPRESSED_IMAGE_SIZE);
+        assert!(get_raw_images(&contents).is_err());
 
+        let options = DecodeOptions { repair: true, ..Default::default() };
+        let images = get_raw_images_with(&contents, &options).unwrap();
+        assert_eq!(images.main_count(), 1);
+        assert_eq!(images.dangling_references().len(), 1);
 
-pub fn extract_bgst(
-    input_filename: &str,
-    do_mask: bool,
-) -> Result<()> {
+        // only one missing block is repaired
+        contents.truncate(contents.len() - 1);
+        assert!(get_raw_images_with(&contents, &options).is_err());
+    }
+
+    #[test]
+    fn image_lists_can_be_built_from_tiles() {
+        let red = RgbaImage::from_pixel(8, 8, Rgba([0xFF, 0, 0, 0xFF]));
+        let mask = RgbaImage::from_fn(8, 8, |x, _| if x < 4 { Rgba([0, 0, 0, 0xFF]) } else { Rgba([0xFF; 4]) });
+        let images = ImageListBuilder::new(8, 8, 2, 1)
+            .tile(SceneIndex::Game, 0, 0, red.clone(), None)
+            .tile(SceneIndex::Game, 1, 0, red.clone(), Some(mask))
+            .build()
+            .unwrap();
 
-    println!("checking if file exists...");
+        assert_eq!((images.main_count(), images.mask_count()), (2, 1));
+        let composite = composite_scene(&images, SceneIndex::Game).unwrap().unwrap();
+        assert_eq!(composite.dimensions(), (16, 8));
+        assert_eq!(composite.get_pixel(3, 0)[3], 0xFF);
+        assert_eq!(composite.get_pixel(11, 0)[3], 0);
+        assert_eq!(composite.get_pixel(12, 0), &Rgba([0xFF, 0, 0, 0xFF]));
 
-    if !fs::exists(input_filename).unwrap() {
-        bail!(format!("file {} does not exist", input_filename));
+        let wrong_size = RgbaImage::new(4, 4);
+        assert!(ImageListBuilder::new(8, 8, 1, 1).tile(SceneIndex::Game, 0, 0, wrong_size, None).build().is_err());
     }
 
-    let file_contents = fs::read(input_filename)?;
+    #[test]
+    fn flipped_tiles_and_composites_are_upside_down() {
+        let mut contents = build_file(2, &[[1, 6, 0, 0, 0, -1, 0, 0], [1, 6, 0, 1, 1, -1, 0, 0]]);
+        // the first image is white on its first block row only, the second is black
+        contents[HEADER_SIZE + 32..HEADER_SIZE + 40].copy_from_slice(&[0xFF, 0xFF, 0, 0, 0, 0, 0, 0]);
+        contents[HEADER_SIZE + 40..HEADER_SIZE + 48].copy_from_slice(&[0xFF, 0xFF, 0, 0, 0, 0, 0, 0]);
 
-    println!("validating header...");
+        let upright = get_raw_images(&contents).unwrap();
+        let options = DecodeOptions { flip_v: true, ..Default::default() };
+        let flipped = get_raw_images_with(&contents, &options).unwrap();
+        assert_eq!(upright.images, flipped.images);
 
-    if !Header::is_valid(&file_contents) {
-        bail!(format!("file {} is not a valid BGST file", input_filename));
+        let upright = composite_scene(&upright, SceneIndex::Game).unwrap().unwrap();
+        let flipped = composite_scene(&flipped, SceneIndex::Game).unwrap().unwrap();
+        assert_eq!(imageops::flip_vertical(&upright), flipped);
+
+        let folder = std::env::temp_dir().join(format!("bgst_flip_{}", std::process::id()));
+        let folder_name = folder.to_string_lossy().to_string();
+        let options = ExtractOptions { decode: options, ..Default::default() };
+        extract_bgst_to(contents.as_slice(), &folder_name, &options).unwrap();
+        let tile = image::open(folder.join("0.png")).unwrap().to_rgba8();
+        assert_eq!(tile.get_pixel(0, 0), &Rgba([0, 0, 0, 0xFF]));
+        assert_eq!(tile.get_pixel(0, 7), &Rgba([0xFF; 4]));
+        fs::remove_dir_all(&folder).unwrap();
     }
 
-    println!("extracting raw images...");
-    let raw_image_list = get_raw_images(&file_contents)?;
+    #[test]
+    fn files_with_any_mask_use_masks() {
+        let without = build_file(2, &[[1, 6, 0, 0, 0, -1, 0, 0], [1, 6, 1, 0, 1, -1, 0, 0]]);
+        assert!(!uses_masks(&Bgst::parse(&without).unwrap()));
 
-    println!("converting to png...");
+        let with = build_file(2, &[[1, 6, 0, 0, 0, -1, 0, 0], [1, 6, 1, 0, 0, 1, 0, 0]]);
+        assert!(uses_masks(&Bgst::parse(&with).unwrap()));
+    }
 
-    let png_images = get_png_images(
-        &raw_image_list,
-        do_mask
-    )?;
+    #[test]
+    fn metadata_names_scenes() {
+        let contents = build_file(1, &[[1, 8, 0, 0, 0, -1, 0, 0], [1, 12, 0, 0, 0, -1, 0, 0]]);
+        let bgst = Bgst::parse(&contents).unwrap();
 
-    println!("writing files...");
+        let csv = csv::entries_csv(&bgst);
+        let names: Vec<&str> = csv.lines().skip(1).map(|row| row.split(',').nth(3).unwrap()).collect();
+        assert_eq!(names, ["Near02", "Unknown(12)"]);
 
-    let folder_name = input_filename
-        .strip_suffix(".bgst3")
-        .unwrap()
-        .to_string();
+        let mut output = Vec::new();
+        jsonl::write_entries_jsonl(&bgst, &mut output).unwrap();
+        let first: serde_json::Value = serde_json::from_str(String::from_utf8(output).unwrap().lines().next().unwrap()).unwrap();
+        assert_eq!(first["scene_name"], "Near02");
+        assert_eq!(SceneIndex::name_of(-1), "Unknown(-1)");
+    }
 
-    match fs::exists(&folder_name) {
-        Ok(folder_exists) => {
-            if folder_exists {
-                // remove contents
-                fs::remove_dir_all(&folder_name).unwrap();
+    #[test]
+    fn masks_can_be_used_as_alpha() {
+        let main = [10, 20, 30, 0xFF].repeat(3);
+        let mask = [0, 0, 0, 0xFF, 0x88, 0x88, 0x88, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF];
+
+        let masked = apply_mask_with(&main, &mask, 3, 1, MaskMode::MaskAsAlpha).unwrap();
+        assert_eq!(masked, [10, 20, 30, 0, 10, 20, 30, 0x88, 10, 20, 30, 0xFF]);
+
+        let keyed = apply_mask_with(&main, &mask, 3, 1, MaskMode::default()).unwrap();
+        assert_eq!(keyed, apply_mask(&main, &mask, 3, 1).unwrap());
+    }
+
+    #[test]
+    fn every_decoded_block_is_timed() {
+        let contents = build_file(3, &[[1, 6, 0, 0, 0, 1, 0, 0], [1, 6, 1, 0, 2, -1, 0, 0], [1, 6, 2, 0, 7, -1, 0, 0]]);
+
+        let timings = timing::time_blocks(&contents, &DecodeOptions::default()).unwrap();
+        let blocks: Vec<(usize, i16, bool)> = timings
+            .iter()
+            .map(|timing| (timing.entry_index, timing.image_index, timing.is_mask))
+            .collect();
+        assert_eq!(blocks, [(0, 0, false), (0, 1, true), (1, 2, false)]);
+
+        let options = DecodeOptions { masks_only: true, ..Default::default() };
+        assert_eq!(timing::time_blocks(&contents, &options).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn entries_can_be_read_with_another_stride() {
+        let stride = GRID_ENTRY_SIZE + 4;
+        let image_data_offset = HEADER_SIZE + 2 * stride;
+        let mut contents = build_header(HeaderParams {
+            image_width: 8,
+            image_height: 8,
+            grid_width: 4,
+            grid_height: 4,
+            image_count: 1,
+            layer_enabled: [true; 12],
+            info_offset: HEADER_SIZE as u32,
+            image_data_offset: image_data_offset as u32,
+            ..Default::default()
+        }).to_vec();
+        for entry in [[1i16, 6, 0, 0, 0, -1, 0, 0, 9, 9], [1, 6, 3, 2, 0, -1, 0, 0, 9, 9]] {
+            for field in entry {
+                contents.extend_from_slice(&field.to_be_bytes());
             }
-            fs::create_dir(&folder_name).unwrap();
         }
+        contents.resize(image_data_offset + COMPRESSED_IMAGE_SIZE, 0);
 
-        Err(_) => {
-            let _ = fs::create_dir(&folder_name);
+        let bgst = Bgst::parse(&contents).unwrap();
+        assert_eq!(bgst.header().infer_entry_stride(2), Some(stride));
+        assert_eq!(bgst.header().infer_entry_stride(3), None);
+
+        let bgst = Bgst::parse_with_stride(&contents, stride).unwrap();
+        assert_eq!(entry_count(bgst.header()), 2);
+        assert_eq!((bgst.grid_entries()[1].grid_x, bgst.grid_entries()[1].grid_y), (3, 2));
+        assert!(Bgst::parse_with_stride(&contents, GRID_ENTRY_SIZE - 1).is_err());
+
+        let options = DecodeOptions {
+            overrides: FormatOverrides { entry_stride: Some(stride), ..Default::default() },
+            ..Default::default()
+        };
+        assert_eq!(get_raw_images_with(&contents, &options).unwrap().main_count(), 2);
+    }
+
+    #[test]
+    fn reports_gather_every_validator() {
+        let mut contents = build_file(3, &[
+            [1, 6, 0, 0, 0, -1, 0, 0],
+            [1, 6, 0, 0, 0, 5, 0, 0],
+            [1, 13, 1, 0, 2, -1, 0, 0],
+        ]);
+        contents.extend_from_slice(&[0; 4]);
+
+        let report = report::inspect(&contents).unwrap();
+        assert_eq!(report.entry_count, 3);
+        assert_eq!(report.scene_counts, BTreeMap::from([("Game".to_string(), 2), ("Unknown(13)".to_string(), 1)]));
+        assert_eq!((report.main_images.as_slice(), report.mask_images.as_slice()), (&[0, 2][..], &[][..]));
+        assert_eq!(report.orphaned_images, [1]);
+        assert_eq!(report.unknown_scenes, [2]);
+        assert_eq!(report.dangling_references.len(), 1);
+        assert!(report.warnings.iter().any(|warning| warning.kind == warnings::WarningKind::Overlap));
+        assert_eq!((report.size.trailing_bytes, report.size.error.as_deref()), (4, None));
+        assert!(!report.is_clean());
+
+        let json = serde_json::to_value(&report).unwrap();
+        assert_eq!(json["header"]["image_count"], 3);
+        assert!(report.to_string().contains("orphaned images: 1"));
+    }
+
+    #[test]
+    fn provenance_is_embedded_in_pngs() {
+        let contents = build_file(2, &[[1, 8, 2, 3, 0, 1, 0, 0]]);
+        let folder = std::env::temp_dir().join(format!("bgst_provenance_{}", std::process::id()));
+        let folder_name = folder.to_string_lossy().to_string();
+
+        let options = ExtractOptions { embed_meta: true, source_name: Some("forest.bgst3".to_string()), ..Default::default() };
+        extract_bgst_to(contents.as_slice(), &folder_name, &options).unwrap();
+        let mask = provenance::read_provenance(&fs::read(folder.join("1.png")).unwrap()).unwrap().unwrap();
+        assert_eq!(mask, provenance::TileProvenance {
+            source: Some("forest.bgst3".to_string()),
+            entry_index: 0,
+            scene_index: 8,
+            grid_x: 2,
+            grid_y: 3,
+            image_index: Some(1),
+            is_mask: true,
+        });
+
+        let named = ExtractOptions { entry_names: true, ..options.clone() };
+        extract_bgst_to(contents.as_slice(), &folder_name, &named).unwrap();
+        let main = provenance::read_provenance(&fs::read(folder.join("entry0_main.png")).unwrap()).unwrap().unwrap();
+        assert_eq!((main.image_index, main.is_mask), (Some(0), false));
+
+        let options = ExtractOptions { do_mask: true, indexed: true, ..options };
+        extract_bgst_to(contents.as_slice(), &folder_name, &options).unwrap();
+        let tile = provenance::read_provenance(&fs::read(folder.join("0.png")).unwrap()).unwrap().unwrap();
+        assert_eq!((tile.image_index, tile.grid_y), (None, 3));
+
+        extract_bgst_to(contents.as_slice(), &folder_name, &ExtractOptions::default()).unwrap();
+        assert!(provenance::read_provenance(&fs::read(folder.join("0.png")).unwrap()).unwrap().is_none());
+        fs::remove_dir_all(&folder).unwrap();
+    }
+
+    #[test]
+    fn blank_images_are_left_out() {
+        let contents = build_file(3, &[
+            [1, 6, 0, 0, 0, -1, 0, 0],
+            [1, 6, 1, 0, 2, -1, 0, 0],
+            [1, 6, 2, 0, 1, 2, 0, 0],
+        ]);
+        let options = DecodeOptions { blank_index: Some(2), ..Default::default() };
+
+        let images = get_raw_images_with(&contents, &options).unwrap();
+        let entries: Vec<(i16, i16)> = images.grid_entries
+            .iter()
+            .map(|entry| (entry.main_image_index, entry.mask_image_index))
+            .collect();
+        assert_eq!(entries, [(0, -1), (1, -1)]);
+        assert_eq!((images.main_count(), images.mask_count()), (2, 0));
+
+        let composite = composite_scene(&images, SceneIndex::Game).unwrap().unwrap();
+        assert_eq!(composite.get_pixel(8, 0)[3], 0);
+        assert_eq!(composite.get_pixel(16, 0)[3], 0xFF);
+
+        // the masks of entries with a blank main image are still decoded when only masks are
+        let contents = build_file(3, &[[1, 6, 0, 0, 2, 1, 0, 0]]);
+        let options = DecodeOptions { masks_only: true, ..options };
+        assert_eq!(get_raw_images_with(&contents, &options).unwrap().mask_count(), 1);
+    }
+
+    #[test]
+    fn entries_are_laid_out_as_a_grid() {
+        let contents = build_file(3, &[
+            [1, 6, 1, 2, 0, -1, 0, 0],
+            [1, 6, 1, 2, 1, -1, 0, 0],
+            [1, 6, 3, 0, 2, -1, 0, 0],
+            [1, 6, 4, 0, 2, -1, 0, 0],
+            [1, 7, 0, 0, 2, -1, 0, 0],
+        ]);
+        let bgst = Bgst::parse(&contents).unwrap();
+
+        let game = grid(&bgst, SceneIndex::Game);
+        assert_eq!((game.len(), game[0].len()), (4, 4));
+        assert_eq!(game[2][1].map(|entry| entry.main_image_index), Some(1));
+        assert_eq!(game[0][3].map(|entry| entry.main_image_index), Some(2));
+        let placed = game.iter().flatten().filter(|cell| cell.is_some()).count();
+        assert_eq!(placed, 2);
+
+        assert!(grid(&bgst, SceneIndex::Map).iter().flatten().all(|cell| cell.is_none()));
+    }
+
+    #[test]
+    fn dimensions_that_arent_whole_tiles_are_cropped() {
+        let file = |width, height| {
+            let image_data_offset = HEADER_SIZE + GRID_ENTRY_SIZE;
+            let mut contents = build_header(HeaderParams {
+                image_width: width,
+                image_height: height,
+                grid_width: 1,
+                grid_height: 1,
+                image_count: 1,
+                layer_enabled: [true; 12],
+                info_offset: HEADER_SIZE as u32,
+                image_data_offset: image_data_offset as u32,
+                ..Default::default()
+            }).to_vec();
+            for field in [1i16, 6, 0, 0, 0, -1, 0, 0] {
+                contents.extend_from_slice(&field.to_be_bytes());
+            }
+
+            // 16x24 pixels of CMPR, with a different color in every 4x4 block
+            contents.extend((0..2 * 3 * 4).flat_map(|block: u8| [block * 10, block, 0, 0, 0, 0, 0, 0]));
+            contents.resize(image_data_offset + COMPRESSED_IMAGE_SIZE, 0);
+            contents
+        };
+
+        let padded = RgbaImage::from_raw(16, 24, get_raw_images(&file(16, 24)).unwrap().images.remove(0)).unwrap();
+        let cropped = get_raw_images(&file(12, 20)).unwrap().images.remove(0);
+        assert_eq!(cropped, imageops::crop_imm(&padded, 0, 0, 12, 20).to_image().into_raw());
+    }
+
+    #[test]
+    fn atlases_are_described_as_json_hashes() {
+        let contents = build_file(2, &[
+            [1, 6, 0, 0, 0, -1, 0, 0],
+            [1, 6, 3, 2, 1, -1, 0, 0],
+            [1, 7, 1, 1, 0, -1, 0, 0],
+        ]);
+        let images = get_raw_images(&contents).unwrap();
+        let atlas = atlas::build_atlas(&images).unwrap();
+        assert_eq!((atlas.tile_count, atlas.frames.len()), (2, 3));
+
+        let json: serde_json::Value = serde_json::from_str(&atlas::atlas_json_hash(&atlas, "forest.atlas.png").unwrap()).unwrap();
+        assert_eq!(json["frames"]["Game_3_2"]["frame"], serde_json::json!({ "x": 8, "y": 0, "w": 8, "h": 8 }));
+        assert_eq!(json["frames"]["Near01_1_1"]["frame"]["x"], 0);
+        assert_eq!(json["frames"]["Game_0_0"]["sourceSize"], serde_json::json!({ "w": 8, "h": 8 }));
+        assert_eq!(json["meta"]["image"], "forest.atlas.png");
+        assert_eq!(json["meta"]["size"], serde_json::json!({ "w": 16, "h": 8 }));
+    }
+
+    #[test]
+    fn decoded_footprint_is_estimated() {
+        let contents = build_file(2, &[[1, 6, 0, 0, 0, 1, 0, 0], [1, 6, 1, 0, 1, -1, 0, 0], [1, 6, 2, 0, 5, -1, 0, 0]]);
+        let bgst = Bgst::parse(&contents).unwrap();
+
+        let images = get_raw_images(&contents).unwrap();
+        let decoded: usize = images.images.iter().map(Vec::len).sum();
+        assert_eq!(estimated_decoded_bytes(&bgst), decoded as u64);
+        assert_eq!(estimated_decoded_bytes(&bgst), 3 * 8 * 8 * 4);
+    }
+
+    #[test]
+    fn masked_entries_are_written_as_two_frame_apngs() {
+        let mut contents = build_file(2, &[[1, 6, 0, 0, 0, 1, 0, 0], [1, 6, 1, 0, 1, -1, 0, 0]]);
+        let mask_block = HEADER_SIZE + 2 * GRID_ENTRY_SIZE + COMPRESSED_IMAGE_SIZE;
+        contents[mask_block..mask_block + 32].fill(0xFF);
+
+        let folder = std::env::temp_dir().join(format!("bgst_apng_{}", std::process::id()));
+        let folder_name = folder.to_string_lossy().to_string();
+        let options = ExtractOptions { apng: true, ..Default::default() };
+        extract_bgst_to(contents.as_slice(), &folder_name, &options).unwrap();
+
+        let mut reader = png::Decoder::new(fs::File::open(folder.join("entry0.png")).unwrap()).read_info().unwrap();
+        assert_eq!(reader.info().animation_control().map(|control| control.num_frames), Some(2));
+        let mut frame = vec![0; reader.output_buffer_size()];
+        reader.next_frame(&mut frame).unwrap();
+        assert_eq!(frame[..4], [0, 0, 0, 0xFF]);
+        reader.next_frame(&mut frame).unwrap();
+        assert_eq!(frame[..4], [0xFF; 4]);
+
+        let reader = png::Decoder::new(fs::File::open(folder.join("entry1.png")).unwrap()).read_info().unwrap();
+        assert!(reader.info().animation_control().is_none());
+        fs::remove_dir_all(&folder).unwrap();
+    }
+
+    #[test]
+    fn nonzero_enabled_values_count_as_enabled() {
+        let contents = build_file(1, &[[2, 6, 0, 0, 0, -1, 0, 0], [0, 6, 1, 0, 0, -1, 0, 0]]);
+        let bgst = Bgst::parse(&contents).unwrap();
+
+        let entry = &bgst.grid_entries()[0];
+        assert_eq!(entry.enabled(), 2);
+        assert!(entry.is_enabled());
+        assert_eq!(entry_views(&contents).unwrap().next().unwrap().enabled(), 2);
+
+        let options = DecodeOptions { skip_disabled: true, ..Default::default() };
+        let (_, selected, _) = select_entries(&contents, &options).unwrap();
+        assert_eq!(selected.iter().map(|(index, _)| *index).collect::<Vec<_>>(), [0]);
+    }
+
+    #[test]
+    fn hex_dump_lines_have_offsets_and_ascii() {
+        let bytes: Vec<u8> = b"BGST".iter().copied().chain(0..20).collect();
+        let dump = hex_dump(&bytes, 2, 100);
+        let lines: Vec<&str> = dump.lines().collect();
+
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0], format!("00000002  53 54 00 01 02 03 04 05 06 07 08 09 0A 0B 0C 0D  ST{}", ".".repeat(14)));
+        assert_eq!(lines[1], format!("00000012  0E 0F 10 11 12 13{}  ......", " ".repeat(30)));
+        assert!(hex_dump(&bytes, 100, 16).is_empty());
+    }
+
+    #[test]
+    fn parse_context_shows_where_files_go_wrong() {
+        let mut contents = build_file(2, &[[1, 6, 0, 0, 0, 1, 0, 0]]);
+        contents.truncate(contents.len() - 8);
+        let bgst = Bgst::parse(&contents).unwrap();
+
+        let offset = first_truncated_block(bgst.header(), COMPRESSED_IMAGE_SIZE, contents.len()).unwrap();
+        assert_eq!(offset, HEADER_SIZE + GRID_ENTRY_SIZE + COMPRESSED_IMAGE_SIZE);
+        assert_eq!(first_truncated_block(bgst.header(), COMPRESSED_IMAGE_SIZE, contents.len() + 8), None);
+
+        // the dump starts a line before the truncated block and takes three lines
+        let context = parse_context(&contents, Some(offset), "the block");
+        assert!(context.starts_with("header:\n00000000  42 47 53 54"));
+        assert!(context.contains(&format!("around the block at 0x{:X}:\n{:08X}", offset, offset - 0x10)));
+        assert!(context.contains(&format!("\n{:08X}  ", offset)));
+        assert_eq!(context.lines().count(), 1 + HEADER_SIZE / 16 + 1 + 3);
+        assert_eq!(parse_context(&contents, None, "the header").lines().count(), 1 + HEADER_SIZE / 16);
+    }
+
+    #[test]
+    fn manifests_record_the_area_of_every_scene() {
+        let contents = build_file(1, &[
+            [1, 6, 1, 2, 0, -1, 0, 0],
+            [1, 6, 3, 0, 0, -1, 0, 0],
+            [1, 6, -1, 0, 0, -1, 0, 0],
+            [1, 0, 0, 0, 0, -1, 0, 0],
+        ]);
+        let scenes = manifest::scene_bounds(&Bgst::parse(&contents).unwrap());
+
+        assert_eq!(scenes.len(), 2);
+        assert_eq!((scenes[0].scene_index, scenes[0].tile_count), (0, 1));
+        let game = &scenes[1];
+        assert_eq!((game.scene_name.as_str(), game.tile_count), ("Game", 2));
+        assert_eq!((game.grid_x, game.grid_y, game.grid_columns, game.grid_rows), (1, 0, 3, 3));
+        assert_eq!((game.pixel_x, game.pixel_y, game.pixel_width, game.pixel_height), (8, 0, 24, 24));
+    }
+
+    #[cfg(feature = "ndarray")]
+    #[test]
+    fn entries_decode_into_arrays() {
+        // an all-black mask hides the whole tile
+        let contents = build_file(2, &[[1, 6, 0, 0, 0, 1, 0, 0], [1, 6, 1, 0, 0, -1, 0, 0]]);
+
+        let masked = ndarray::decode_entry_ndarray(&contents, 0).unwrap();
+        assert_eq!(masked.shape(), [8, 8, 4]);
+        assert!(masked.slice(::ndarray::s![.., .., 3]).iter().all(|alpha| *alpha == 0));
+
+        let unmasked = ndarray::decode_entry_ndarray(&contents, 1).unwrap();
+        assert_eq!(unmasked.slice(::ndarray::s![0, 0, ..]).to_vec(), [0, 0, 0, 0xFF]);
+        assert!(ndarray::decode_entry_ndarray(&contents, 2).is_err());
+    }
+
+    #[test]
+    fn masked_extraction_pairs_images_by_entry() {
+        // entry 0 uses the second block as its main image, entry 1 as its mask
+        let mut contents = build_file(2, &[[1, 6, 0, 0, 1, -1, 0, 0], [1, 6, 1, 0, 0, 1, 0, 0]]);
+        let second_block = HEADER_SIZE + 2 * GRID_ENTRY_SIZE + COMPRESSED_IMAGE_SIZE;
+        for block in contents[second_block..second_block + COMPRESSED_IMAGE_SIZE].chunks_mut(8) {
+            block.copy_from_slice(&[0xFF, 0xFF, 0, 0, 0, 0, 0, 0]);
         }
+
+        let folder = std::env::temp_dir().join("bgst_masked_extraction_pairs_images_by_entry");
+        let folder_name = folder.to_string_lossy().to_string();
+        let options = ExtractOptions { do_mask: true, ..Default::default() };
+        extract_bgst_to(contents.as_slice(), &folder_name, &options).unwrap();
+
+        let first = image::open(folder.join("0.png")).unwrap().to_rgba8();
+        assert_eq!(first.get_pixel(0, 0).0, [0xFF; 4]);
+        let second = image::open(folder.join("1.png")).unwrap().to_rgba8();
+        assert_eq!(second.get_pixel(0, 0).0, [0, 0, 0, 0xFF]);
+        fs::remove_dir_all(&folder).unwrap();
     }
 
+    #[cfg(feature = "gctex")]
+    #[test]
+    fn manifests_keep_every_entry_and_raw_enabled_values() {
+        let folder = std::env::temp_dir().join("bgst_manifests_keep_every_entry_and_raw_enabled_values");
+        let _ = fs::remove_dir_all(&folder);
+        fs::create_dir_all(&folder).unwrap();
+
+        // the second entry is on a layer the header disables
+        let mut contents = build_file(2, &[[2, 6, 0, 0, 0, -1, 0, 0], [1, 7, 1, 0, 1, -1, 0, 0]]);
+        contents[0x1C + 7] = 0;
+        let image = RgbaImage::from_pixel(8, 8, Rgba([0, 0, 0xFF, 0xFF]));
+        let block = pack::encode_block(image.as_raw(), 8, 8, TextureFormat::CMPR).unwrap();
+        let image_data_offset = HEADER_SIZE + 2 * GRID_ENTRY_SIZE;
+        for index in 0..2 {
+            let start = image_data_offset + index * COMPRESSED_IMAGE_SIZE;
+            contents[start..start + COMPRESSED_IMAGE_SIZE].copy_from_slice(&block);
+        }
+
+        // the scene filter is kept, so the entry on the disabled layer is listed without its image
+        let options = ExtractOptions { unique_images: true, ..Default::default() };
+        unique::write_unique_images(&contents, &options, &folder.to_string_lossy()).unwrap();
 
-    for i in 0..png_images.len() {
-        let filename = folder_name.to_owned() + "/" + i.to_string().as_str() + ".png";
+        let manifest = manifest::Manifest::load(&folder.join(unique::METADATA_FILENAME)).unwrap();
+        assert_eq!(manifest.entries.len(), 2);
+        assert_eq!(manifest.entries[0].enabled, 2);
+        assert_eq!(manifest.entries[1].main_image, None);
+        assert_eq!(manifest.images.len(), 1);
 
-        let _ = fs::write(
-            String::from(filename),
-            &png_images[i]
-        );
+        let options = ExtractOptions {
+            unique_images: true,
+            decode: DecodeOptions { scenes: SceneFilter::All, ..Default::default() },
+            ..Default::default()
+        };
+        unique::write_unique_images(&contents, &options, &folder.to_string_lossy()).unwrap();
+        assert_eq!(pack::pack_bgst(&folder).unwrap(), contents);
+
+        fs::remove_dir_all(&folder).unwrap();
     }
 
-    println!("done!");
+    #[test]
+    fn reports_only_look_for_orphans_among_held_blocks() {
+        let mut contents = build_file(2, &[[1, 6, 0, 0, 1, -1, 0, 0]]);
+        assert_eq!(report::inspect(&contents).unwrap().orphaned_images, [0]);
 
-    Ok(())
-}
+        // a huge declared count is only checked against the blocks the file holds
+        BigEndian::write_u32(&mut contents[0x18..0x1C], 0x7FFF_FFFF);
+        let report = report::inspect(&contents).unwrap();
+        assert_eq!(report.orphaned_images, [0]);
+        assert!(report.size.error.is_some());
+    }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    #[test]
+    fn exporters_fail_on_masks_that_cannot_be_applied() {
+        let tile = RgbaImage::from_pixel(8, 8, Rgba([0xFF; 4]));
+        let mut images = ImageListBuilder::new(8, 8, 2, 1)
+            .tile(SceneIndex::Game, 0, 0, tile.clone(), None)
+            .tile(SceneIndex::Game, 1, 0, tile.clone(), Some(tile))
+            .build()
+            .unwrap();
+        images.images[2].truncate(4);
+
+        assert_eq!(masked_tiles(&images).filter(Result::is_err).count(), 1);
+        assert!(composite_scene(&images, SceneIndex::Game).is_err());
+        assert!(filmstrip(&images, FilmstripDirection::Vertical).is_err());
+        assert!(atlas::build_atlas(&images).is_err());
+        assert!(tiff::write_tiff(&images, Cursor::new(Vec::new())).is_err());
+    }
 
     #[test]
-    fn invalid_bgst() {
-        assert!(
-            !Header::is_valid(&vec![])
-        );
+    fn alpha_channels_are_not_written_for_masks() {
+        let folder = std::env::temp_dir().join("bgst_alpha_channels_are_not_written_for_masks");
+        let contents = build_file(2, &[[1, 6, 0, 0, 0, 1, 0, 0]]);
+        let options = ExtractOptions { write_alpha: true, ..Default::default() };
+        extract_bgst_to(contents.as_slice(), &folder.to_string_lossy(), &options).unwrap();
+
+        let mut names: Vec<String> = fs::read_dir(&folder)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        assert_eq!(names, ["0.png", "0_alpha.png", "1.png"]);
+
+        fs::remove_dir_all(&folder).unwrap();
+    }
+
+    #[cfg(feature = "ffi")]
+    #[test]
+    fn ffi_decodes_pngs_that_can_be_freed() {
+        let contents = build_file(2, &[[1, 6, 0, 0, 0, 1, 0, 0]]);
+        unsafe {
+            assert!(ffi::bgst_validate(contents.as_ptr(), contents.len()));
+            assert_eq!(ffi::bgst_image_count(contents.as_ptr(), contents.len()), 2);
+
+            for index in 0..2 {
+                let mut out_ptr = std::ptr::null_mut();
+                let mut out_len = 0;
+                let code = ffi::bgst_decode_png(contents.as_ptr(), contents.len(), index, &mut out_ptr, &mut out_len);
+                assert_eq!(code, ffi::BGST_OK);
+                assert!(!out_ptr.is_null());
+
+                let png = std::slice::from_raw_parts(out_ptr, out_len);
+                let image = image::load_from_memory_with_format(png, ImageFormat::Png).unwrap();
+                assert_eq!((image.width(), image.height()), (8, 8));
+                ffi::bgst_free(out_ptr, out_len);
+            }
+
+            let mut out_ptr = std::ptr::null_mut();
+            let mut out_len = 0;
+            let code = ffi::bgst_decode_png(contents.as_ptr(), contents.len(), 2, &mut out_ptr, &mut out_len);
+            assert_eq!(code, ffi::BGST_ERROR_INDEX);
+            assert!(out_ptr.is_null());
+        }
+    }
+
+    #[test]
+    fn files_with_the_same_output_folder_are_not_extracted() {
+        let root = std::env::temp_dir().join("bgst_files_with_the_same_output_folder_are_not_extracted");
+        let _ = fs::remove_dir_all(&root);
+        let input = root.join("input");
+        fs::create_dir_all(input.join("a")).unwrap();
+        fs::create_dir_all(input.join("b")).unwrap();
+
+        let contents = build_file(1, &[[1, 6, 0, 0, 0, -1, 0, 0]]);
+        let paths: Vec<PathBuf> = ["a/x.bgst3", "b/x.bgst3", "a/y.bgst3"].iter().map(|name| input.join(name)).collect();
+        for path in &paths {
+            fs::write(path, &contents).unwrap();
+        }
+
+        let output = root.join("output");
+        let results = extract_many(&paths, &output, &ExtractOptions::default()).unwrap();
+        assert!(results[0].is_err());
+        assert!(results[1].is_err());
+        assert!(results[2].is_ok());
+        assert!(!output.join("x").exists());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[cfg(feature = "gctex")]
+    #[test]
+    fn unique_folders_are_packed_with_their_block_size() {
+        let folder = std::env::temp_dir().join("bgst_unique_folders_are_packed_with_their_block_size");
+        let _ = fs::remove_dir_all(&folder);
+        fs::create_dir_all(&folder).unwrap();
+
+        let mut contents = build_file(0, &[[1, 6, 0, 0, 0, 1, 0, 0]]);
+        BigEndian::write_u32(&mut contents[0x18..0x1C], 2);
+        let image = RgbaImage::from_pixel(8, 8, Rgba([0xFF; 4]));
+        for format in [TextureFormat::CMPR, TextureFormat::I4] {
+            contents.extend_from_slice(&pack::encode_block(image.as_raw(), 8, 8, format).unwrap()[..0x20]);
+        }
+
+        let mut options = ExtractOptions { unique_images: true, ..Default::default() };
+        options.decode.overrides.block_size = Some(0x20);
+        unique::write_unique_images(&contents, &options, &folder.to_string_lossy()).unwrap();
+        assert_eq!(pack::pack_bgst(&folder).unwrap(), contents);
+
+        // the bytes past the known fields of wider entries weren't kept
+        let path = folder.join(unique::METADATA_FILENAME);
+        let mut manifest = manifest::Manifest::load(&path).unwrap();
+        manifest.entry_stride = 0x14;
+        manifest.save(&path).unwrap();
+        assert!(pack::pack_bgst(&folder).is_err());
+
+        fs::remove_dir_all(&folder).unwrap();
+    }
+
+    #[cfg(feature = "gctex")]
+    #[test]
+    fn builtin_formats_match_gctex() {
+        let formats = [
+            TextureFormat::I4, TextureFormat::I8, TextureFormat::IA4, TextureFormat::IA8,
+            TextureFormat::RGB565, TextureFormat::RGB5A3, TextureFormat::RGBA8, TextureFormat::C4,
+            TextureFormat::C8, TextureFormat::C14X2, TextureFormat::CMPR,
+        ];
+        for (width, height) in [(8, 8), (24, 16), (12, 20)] {
+            for format in formats {
+                let expected = gctex::compute_image_size(format, width, height) as usize;
+                assert_eq!(texture::image_size(format, width, height), expected, "{:?}", format);
+            }
+
+            let encoded: Vec<u8> = (0..texture::image_size(TextureFormat::I4, width, height)).map(|i| (i * 37) as u8).collect();
+            let mut decoded = vec![0xAA; width as usize * height as usize * 4];
+            texture::decode_i4_into(&mut decoded, &encoded, width, height);
+            assert_eq!(decoded, gctex::decode(&encoded, width, height, TextureFormat::I4, &[], 0));
+        }
+    }
+
+    #[cfg(not(feature = "gctex"))]
+    #[test]
+    fn formats_besides_cmpr_and_i4_need_gctex() {
+        let contents = build_file(2, &[[1, 6, 0, 0, 0, 1, 0, 0]]);
+        assert!(get_raw_images(&contents).is_ok());
+
+        let overrides = FormatOverrides { mask_format: Some(TextureFormat::I8), ..Default::default() };
+        let error = get_raw_images_with(&contents, &DecodeOptions { overrides, ..Default::default() }).err().unwrap();
+        assert_eq!(error.to_string(), "decoding I8 needs the `gctex` feature");
+
+        let image = RgbaImage::new(8, 8);
+        assert!(pack::encode_block(image.as_raw(), 8, 8, TextureFormat::CMPR).is_err());
     }
 }
@@ -0,0 +1,223 @@
+//! The manifest written next to uniquely extracted images. It records everything
+//! needed to rebuild the file from those images: the header fields, every entry,
+//! and which file on disk holds each image block. `pack::pack_bgst` reads it back.
+
+use anyhow::{Context, Result, bail};
+use std::fs;
+use std::path::Path;
+use serde::{Deserialize, Serialize};
+use crate::{Bgst, BgstError, SceneIndex, scene_pixel_bounds};
+
+/// The version of the manifest schema written by this crate.
+/// Manifests with any other version are rejected when read.
+pub const MANIFEST_VERSION: u32 = 3;
+
+/// One grid entry, with the names of the images it uses.
+///
+/// ### Fields
+/// - `enabled`: The raw `enabled` value, which may be other than `0` or `1`; see `GridEntry::enabled`.
+/// - `entry_index`: The index of the entry in the original file.
+/// - `main_image`: The name of the main image, without the extension, if it was written.
+/// - `mask_image`: The name of the mask image, without the extension, if it was written.
+/// - `normalized_x`: `grid_x` divided by the grid width, so `0.0` is the left edge
+///   and `1.0` the right. This and the positions below are only written for reference
+///   and aren't read back, so they default when missing.
+/// - `normalized_y`: `grid_y` divided by the grid height, so `0.0` is the top edge.
+/// - `pixel_x`: The left edge of the tile, in pixels.
+/// - `pixel_y`: The top edge of the tile, in pixels.
+/// - `scene_name`: The name of the entry's scene; see `SceneIndex::name_of`. Only written for reference.
+/// - the remaining fields are the values of the entry.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    pub entry_index: usize,
+    pub enabled: i16,
+    pub scene_index: i16,
+    pub grid_x: i16,
+    pub grid_y: i16,
+    pub main_image_index: i16,
+    pub mask_image_index: i16,
+    pub main_image: Option<String>,
+    pub mask_image: Option<String>,
+    pub unk_c: i16,
+    pub unk_e: i16,
+    #[serde(default)]
+    pub normalized_x: f32,
+    #[serde(default)]
+    pub normalized_y: f32,
+    #[serde(default)]
+    pub pixel_x: i64,
+    #[serde(default)]
+    pub pixel_y: i64,
+    #[serde(default)]
+    pub scene_name: String,
+}
+
+/// Returns a cell as a fraction of the number of cells, or 0 if there are none.
+///
+/// ### Parameters
+/// - `cell`: The column or row.
+/// - `cells`: The number of columns or rows.
+pub fn normalized(cell: i16, cells: u32) -> f32 {
+    if cells == 0 {
+        0.0
+    } else {
+        cell as f32 / cells as f32
+    }
+}
+
+/// The area a scene's tiles cover, so an editor can place and cull the scene without decoding it.
+/// Only written for reference, like the positions of entries.
+///
+/// ### Fields
+/// - `scene_index`: The index of the scene.
+/// - `scene_name`: The name of the scene; see `SceneIndex::name_of`.
+/// - `tile_count`: The number of entries placed on the scene. Entries with a negative
+///   grid position aren't placed, the same as in `scene_pixel_bounds`.
+/// - `grid_x`: The first column a tile is placed in.
+/// - `grid_y`: The first row a tile is placed in.
+/// - `grid_columns`: The number of columns from the first to the last tile.
+/// - `grid_rows`: The number of rows from the first to the last tile.
+/// - `pixel_x`: The left edge of the covered area, in pixels.
+/// - `pixel_y`: The top edge of the covered area, in pixels.
+/// - `pixel_width`: The width of the covered area, in pixels.
+/// - `pixel_height`: The height of the covered area, in pixels.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ManifestScene {
+    pub scene_index: i16,
+    pub scene_name: String,
+    pub tile_count: usize,
+    pub grid_x: u32,
+    pub grid_y: u32,
+    pub grid_columns: u32,
+    pub grid_rows: u32,
+    pub pixel_x: u32,
+    pub pixel_y: u32,
+    pub pixel_width: u32,
+    pub pixel_height: u32,
+}
+
+/// Measures every known scene that has tiles placed on it.
+///
+/// ### Parameters
+/// - `bgst`: The parsed BGST file.
+///
+/// ### Returns
+/// - the area of every scene with tiles, in scene order
+pub fn scene_bounds(bgst: &Bgst) -> Vec<ManifestScene> {
+    let (image_width, image_height) = (bgst.header().image_width(), bgst.header().image_height());
+
+    SceneIndex::ALL
+        .iter()
+        .filter_map(|scene| {
+            let (pixel_x, pixel_y, pixel_width, pixel_height) = scene_pixel_bounds(bgst, *scene)?;
+            let tile_count = bgst
+                .grid_entries()
+                .iter()
+                .filter(|entry| entry.scene_index() == *scene as i16)
+                .filter(|entry| entry.grid_x() >= 0 && entry.grid_y() >= 0)
+                .count();
+
+            Some(ManifestScene {
+                scene_index: *scene as i16,
+                scene_name: SceneIndex::name_of(*scene as i16),
+                tile_count,
+                grid_x: pixel_x.checked_div(image_width).unwrap_or(0),
+                grid_y: pixel_y.checked_div(image_height).unwrap_or(0),
+                grid_columns: pixel_width.checked_div(image_width).unwrap_or(0),
+                grid_rows: pixel_height.checked_div(image_height).unwrap_or(0),
+                pixel_x,
+                pixel_y,
+                pixel_width,
+                pixel_height,
+            })
+        })
+        .collect()
+}
+
+/// The file that holds the contents of one image block.
+///
+/// ### Fields
+/// - `image_index`: The index of the image block.
+/// - `format`: The texture format the block is encoded as, such as `CMPR` or `I4`.
+/// - `filename`: The file holding the decoded image, relative to the manifest.
+///   Byte-identical blocks share one file.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ManifestImage {
+    pub image_index: i16,
+    pub format: String,
+    pub filename: String,
+}
+
+/// Everything needed to rebuild a file from its extracted images.
+///
+/// ### Fields
+/// - `version`: The version of the schema, `MANIFEST_VERSION` when written by this crate.
+/// - `unk_4`: The unknown header value at `0x4`.
+/// - `reserved`: The header bytes from `0x30` to the end of the header, which aren't understood.
+/// - `block_size`: The distance between image blocks the images were decoded with, in bytes.
+/// - `entry_stride`: The distance between grid entries the file was read with, in bytes.
+/// - `entries`: Every entry of the file, in order, whether or not it was extracted.
+///   Entries whose images weren't extracted don't name them.
+/// - `images`: The file holding every image block the entries use.
+/// - `scenes`: The area covered by every scene the extracted entries are on; see `scene_bounds`.
+///   Only written for reference, so it defaults when missing.
+/// - the remaining fields are the values of the header.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Manifest {
+    pub version: u32,
+    pub unk_4: u32,
+    pub image_width: u32,
+    pub image_height: u32,
+    pub grid_width: u32,
+    pub grid_height: u32,
+    pub image_count: u32,
+    pub layer_enabled: [bool; 12],
+    pub reserved: [u8; 16],
+    pub block_size: usize,
+    pub entry_stride: usize,
+    pub entries: Vec<ManifestEntry>,
+    pub images: Vec<ManifestImage>,
+    #[serde(default)]
+    pub scenes: Vec<ManifestScene>,
+}
+
+impl Manifest {
+    /// Reads a manifest, checking that its schema version is supported.
+    ///
+    /// ### Parameters
+    /// - `path`: The path to the manifest.
+    ///
+    /// ### Returns
+    /// - the manifest
+    pub fn load(path: &Path) -> Result<Manifest> {
+        if !path.exists() {
+            bail!(BgstError::FileNotFound(path.display().to_string()));
+        }
+
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        let manifest: Manifest = serde_json::from_str(&text)
+            .map_err(|err| BgstError::InvalidFile(format!("failed to parse {}: {}", path.display(), err)))?;
+
+        if manifest.version != MANIFEST_VERSION {
+            bail!(BgstError::InvalidFile(format!(
+                "{} has manifest version {}, but only version {} is supported",
+                path.display(),
+                manifest.version,
+                MANIFEST_VERSION
+            )));
+        }
+
+        Ok(manifest)
+    }
+
+    /// Writes the manifest as pretty-printed JSON.
+    ///
+    /// ### Parameters
+    /// - `path`: The path to write the manifest to.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        fs::write(path, serde_json::to_string_pretty(self)?)
+            .map_err(|err| BgstError::Write(format!("failed to write {}: {}", path.display(), err)))?;
+        Ok(())
+    }
+}
@@ -0,0 +1,31 @@
+//! Decoding into `ndarray` arrays, for pixel statistics and other analysis,
+//! enabled with the `ndarray` feature.
+
+use anyhow::{Result, bail};
+use ::ndarray::Array3;
+use crate::{BgstError, DimensionLimits, Header, decode_entry_into};
+
+/// Decodes the main image of an entry, with its mask applied, into an array
+/// indexed as `[y, x, channel]`, the same layout as a numpy image.
+/// The image is decoded the same way as by `decode_entry_into`.
+///
+/// ### Parameters
+/// - `data`: The raw data of a bgst3 file.
+/// - `index`: The index of the entry in the file.
+///
+/// ### Returns
+/// - an `image_height` by `image_width` by 4 array of RGBA values
+pub fn decode_entry_ndarray(data: &[u8], index: usize) -> Result<Array3<u8>> {
+    if !Header::is_valid(data) {
+        bail!(BgstError::InvalidFile("file is not a valid BGST file".to_string()));
+    }
+
+    let header = Header::from_validated_header_bytes(data);
+    header.check_dimensions(&DimensionLimits::default())?;
+
+    let (width, height) = (header.image_width as usize, header.image_height as usize);
+    let mut pixels = vec![0; width * height * 4];
+    decode_entry_into(data, index, &mut pixels)?;
+
+    Ok(Array3::from_shape_vec((height, width, 4), pixels)?)
+}
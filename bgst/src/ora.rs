@@ -0,0 +1,115 @@
+//! Export of composited scenes to OpenRaster (`.ora`), a zip of PNG layers
+//! described by a `stack.xml` that Krita and GIMP can open with layers intact.
+
+use anyhow::{Result, bail};
+use std::fs::File;
+use std::io::{Cursor, Seek, Write};
+use image::*;
+use zip::{CompressionMethod, ZipWriter};
+use zip::write::SimpleFileOptions;
+use crate::{BgstError, ImageList, SceneIndex, composite_scene};
+
+/// The largest width or height of the thumbnail stored in the archive.
+const THUMBNAIL_SIZE: u32 = 256;
+
+fn encode_png(image: &RgbaImage) -> Result<Vec<u8>> {
+    let mut buffer = Cursor::new(Vec::new());
+    image.write_to(&mut buffer, ImageFormat::Png)?;
+    Ok(buffer.into_inner())
+}
+
+/// Writes every scene that has tiles placed on it as a layer of an OpenRaster image.
+/// Layers are stacked in scene order, so nearer scenes are drawn over farther ones.
+///
+/// ### Parameters
+/// - `images`: The decoded images.
+/// - `writer`: Where to write the archive.
+pub fn write_ora<W: Write + Seek>(
+    images: &ImageList,
+    writer: W
+) -> Result<()> {
+    let mut layers = Vec::new();
+
+    for scene in SceneIndex::ALL {
+        if let Some(layer) = composite_scene(images, scene)? {
+            layers.push((scene, layer));
+        }
+    }
+
+    if layers.is_empty() {
+        bail!("no scenes have tiles placed on them");
+    }
+
+    let width = layers.iter().map(|(_, layer)| layer.width()).max().unwrap_or(0);
+    let height = layers.iter().map(|(_, layer)| layer.height()).max().unwrap_or(0);
+
+    let mut merged = RgbaImage::new(width, height);
+    for (_, layer) in &layers {
+        imageops::overlay(&mut merged, layer, 0, 0);
+    }
+
+    // the topmost layer comes first in the stack
+    let mut stack = String::new();
+    stack += "<?xml version='1.0' encoding='UTF-8'?>\n";
+    stack += &format!("<image version=\"0.0.3\" w=\"{}\" h=\"{}\">\n", width, height);
+    stack += "  <stack>\n";
+    for (scene, _) in layers.iter().rev() {
+        stack += &format!(
+            "    <layer name=\"{0}\" src=\"data/{0}.png\" x=\"0\" y=\"0\" visibility=\"visible\" />\n",
+            scene.name()
+        );
+    }
+    stack += "  </stack>\n";
+    stack += "</image>\n";
+
+    let scale = THUMBNAIL_SIZE as f32 / width.max(height) as f32;
+    let thumbnail = if scale < 1.0 {
+        imageops::thumbnail(
+            &merged,
+            ((width as f32 * scale) as u32).max(1),
+            ((height as f32 * scale) as u32).max(1)
+        )
+    } else {
+        merged.clone()
+    };
+
+    let mut archive = ZipWriter::new(writer);
+    let stored = SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
+    let deflated = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+
+    // the mimetype must be the first file and must not be compressed
+    archive.start_file("mimetype", stored)?;
+    archive.write_all(b"image/openraster")?;
+
+    archive.start_file("stack.xml", deflated)?;
+    archive.write_all(stack.as_bytes())?;
+
+    for (scene, layer) in &layers {
+        archive.start_file(format!("data/{}.png", scene.name()), stored)?;
+        archive.write_all(&encode_png(layer)?)?;
+    }
+
+    archive.start_file("mergedimage.png", stored)?;
+    archive.write_all(&encode_png(&merged)?)?;
+
+    archive.start_file("Thumbnails/thumbnail.png", stored)?;
+    archive.write_all(&encode_png(&thumbnail)?)?;
+
+    archive.finish()?;
+
+    Ok(())
+}
+
+/// Writes every scene of a file as a layer of an OpenRaster image.
+///
+/// ### Parameters
+/// - `images`: The decoded images.
+/// - `output_filename`: The path of the `.ora` file to create.
+pub fn export_ora(
+    images: &ImageList,
+    output_filename: &str
+) -> Result<()> {
+    let file = File::create(output_filename)
+        .map_err(|err| BgstError::Write(format!("failed to create {}: {}", output_filename, err)))?;
+    write_ora(images, file)
+}
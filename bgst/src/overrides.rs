@@ -0,0 +1,273 @@
+//! Per-file decoding overrides for files that don't follow the usual layout,
+//! read from a TOML config such as:
+//!
+//! ```toml
+//! # applies to every file
+//! [default]
+//! mask_format = "I4"
+//!
+//! # applies to files with this name, on top of the defaults
+//! [files."forest.bgst3"]
+//! image_width = 256
+//! image_height = 256
+//! block_size = 0x8000
+//! layout = "linear"
+//! entry_stride = 0x14
+//!
+//! # formats for specific image indices
+//! [files."forest.bgst3".images]
+//! 7 = "I8"
+//! ```
+
+use anyhow::{Result, bail, Context};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use crate::texture::TextureFormat;
+use serde::Deserialize;
+use crate::GRID_ENTRY_SIZE;
+use crate::swizzle::{TileLayout, parse_layout};
+
+/// Converts the name of a texture format, such as `"CMPR"` or `"I8"`.
+///
+/// ### Parameters
+/// - `name`: The name of the format.
+///
+/// ### Returns
+/// - the texture format
+pub fn parse_texture_format(name: &str) -> Result<TextureFormat> {
+    let format = match name.to_ascii_uppercase().as_str() {
+        "I4" => TextureFormat::I4,
+        "I8" => TextureFormat::I8,
+        "IA4" => TextureFormat::IA4,
+        "IA8" => TextureFormat::IA8,
+        "RGB565" => TextureFormat::RGB565,
+        "RGB5A3" => TextureFormat::RGB5A3,
+        "RGBA8" => TextureFormat::RGBA8,
+        "CMPR" => TextureFormat::CMPR,
+        _ => bail!("unknown texture format {}", name),
+    };
+
+    Ok(format)
+}
+
+/// Overrides applied to a file when it is decoded.
+/// Anything left unset keeps the value from the file or the usual format.
+///
+/// ### Fields
+/// - `image_width`: Replaces the image width from the header.
+/// - `image_height`: Replaces the image height from the header.
+/// - `main_format`: The format of main images, instead of CMPR.
+/// - `mask_format`: The format of mask images, instead of I4.
+/// - `block_size`: The distance between image blocks, in bytes, instead of `COMPRESSED_IMAGE_SIZE`.
+///   Main and mask images share one array of blocks, so they share one block size.
+/// - `image_formats`: Formats for specific image indices, used whether the image is a main or a mask.
+/// - `layout`: How image blocks are laid out, instead of in tiles.
+/// - `entry_stride`: The distance between grid entries, in bytes, instead of `GRID_ENTRY_SIZE`.
+#[derive(Clone, Debug, Default)]
+pub struct FormatOverrides {
+    pub image_width: Option<u32>,
+    pub image_height: Option<u32>,
+    pub block_size: Option<usize>,
+    pub main_format: Option<TextureFormat>,
+    pub mask_format: Option<TextureFormat>,
+    pub image_formats: BTreeMap<usize, TextureFormat>,
+    pub layout: Option<TileLayout>,
+    pub entry_stride: Option<usize>,
+}
+
+impl FormatOverrides {
+    /// Returns the format an image should be decoded as.
+    ///
+    /// ### Parameters
+    /// - `image_index`: The image index.
+    /// - `is_mask`: Whether the image is used as a mask.
+    ///
+    /// ### Returns
+    /// - the texture format
+    pub fn format_for(&self, image_index: usize, is_mask: bool) -> TextureFormat {
+        if let Some(format) = self.image_formats.get(&image_index) {
+            return *format;
+        }
+
+        if is_mask {
+            self.mask_format.unwrap_or(TextureFormat::I4)
+        } else {
+            self.main_format.unwrap_or(TextureFormat::CMPR)
+        }
+    }
+
+    /// Layers another set of overrides on top of these ones, so its values win where both are set.
+    pub fn merged_with(&self, other: &FormatOverrides) -> FormatOverrides {
+        let mut image_formats = self.image_formats.clone();
+        image_formats.extend(&other.image_formats);
+
+        FormatOverrides {
+            image_width: other.image_width.or(self.image_width),
+            image_height: other.image_height.or(self.image_height),
+            block_size: other.block_size.or(self.block_size),
+            main_format: other.main_format.or(self.main_format),
+            mask_format: other.mask_format.or(self.mask_format),
+            image_formats,
+            layout: other.layout.or(self.layout),
+            entry_stride: other.entry_stride.or(self.entry_stride),
+        }
+    }
+}
+
+/// One table of the config, as written.
+#[derive(Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawOverrides {
+    image_width: Option<u32>,
+    image_height: Option<u32>,
+    block_size: Option<usize>,
+    main_format: Option<String>,
+    mask_format: Option<String>,
+    layout: Option<String>,
+    entry_stride: Option<usize>,
+    #[serde(default)]
+    images: BTreeMap<String, String>,
+}
+
+impl RawOverrides {
+    fn resolve(self) -> Result<FormatOverrides> {
+        let mut image_formats = BTreeMap::new();
+
+        for (index, format) in &self.images {
+            let index = index
+                .parse::<usize>()
+                .with_context(|| format!("invalid image index {}", index))?;
+            image_formats.insert(index, parse_texture_format(format)?);
+        }
+
+        if self.block_size == Some(0) {
+            bail!("block_size must not be 0");
+        }
+        if self.entry_stride.is_some_and(|stride| stride < GRID_ENTRY_SIZE) {
+            bail!("entry_stride must be at least {:#x}", GRID_ENTRY_SIZE);
+        }
+
+        Ok(FormatOverrides {
+            image_width: self.image_width,
+            image_height: self.image_height,
+            block_size: self.block_size,
+            main_format: self.main_format.as_deref().map(parse_texture_format).transpose()?,
+            mask_format: self.mask_format.as_deref().map(parse_texture_format).transpose()?,
+            image_formats,
+            layout: self.layout.as_deref().map(parse_layout).transpose()?,
+            entry_stride: self.entry_stride,
+        })
+    }
+}
+
+/// The whole config, as written.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawConfig {
+    #[serde(default)]
+    default: RawOverrides,
+    #[serde(default)]
+    files: BTreeMap<String, RawOverrides>,
+}
+
+/// A set of decoding overrides, shared by every file and specific to named files.
+///
+/// ### Fields
+/// - `default`: The overrides applied to every file.
+/// - `files`: The overrides applied to files with a given name.
+#[derive(Clone, Debug, Default)]
+pub struct FormatConfig {
+    pub default: FormatOverrides,
+    pub files: BTreeMap<String, FormatOverrides>,
+}
+
+impl FormatConfig {
+    /// Parses a config from TOML.
+    ///
+    /// ### Parameters
+    /// - `text`: The contents of the config.
+    ///
+    /// ### Returns
+    /// - a `FormatConfig` struct
+    pub fn parse(text: &str) -> Result<FormatConfig> {
+        let raw: RawConfig = toml::from_str(text)?;
+
+        let mut files = BTreeMap::new();
+        for (name, overrides) in raw.files {
+            let overrides = overrides
+                .resolve()
+                .with_context(|| format!("in the overrides for {}", name))?;
+            files.insert(name, overrides);
+        }
+
+        Ok(FormatConfig {
+            default: raw.default.resolve().context("in the default overrides")?,
+            files,
+        })
+    }
+
+    /// Reads a config from a TOML file.
+    ///
+    /// ### Parameters
+    /// - `path`: The path to the config.
+    ///
+    /// ### Returns
+    /// - a `FormatConfig` struct
+    pub fn load(path: &Path) -> Result<FormatConfig> {
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+
+        FormatConfig::parse(&text)
+            .with_context(|| format!("failed to parse {}", path.display()))
+    }
+
+    /// Returns the overrides for a file. Files are matched by their file name,
+    /// and their overrides are layered on top of the defaults.
+    ///
+    /// ### Parameters
+    /// - `filename`: The path to the file.
+    ///
+    /// ### Returns
+    /// - the overrides to decode the file with
+    pub fn overrides_for(&self, filename: &str) -> FormatOverrides {
+        let name = Path::new(filename)
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+
+        match self.files.get(&name) {
+            Some(overrides) => self.default.merged_with(overrides),
+            None => self.default.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn file_overrides_layer_on_defaults() {
+        let config = FormatConfig::parse(r#"
+            [default]
+            mask_format = "I8"
+
+            [files."forest.bgst3"]
+            image_width = 256
+
+            [files."forest.bgst3".images]
+            7 = "RGB5A3"
+        "#).unwrap();
+
+        let overrides = config.overrides_for("bg/forest.bgst3");
+        assert_eq!(overrides.image_width, Some(256));
+        assert_eq!(overrides.format_for(0, false), TextureFormat::CMPR);
+        assert_eq!(overrides.format_for(0, true), TextureFormat::I8);
+        assert_eq!(overrides.format_for(7, true), TextureFormat::RGB5A3);
+
+        let overrides = config.overrides_for("bg/cave.bgst3");
+        assert_eq!(overrides.image_width, None);
+        assert_eq!(overrides.format_for(7, false), TextureFormat::CMPR);
+    }
+}
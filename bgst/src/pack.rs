@@ -0,0 +1,332 @@
+//! Building BGST files from decoded images, the inverse of extraction.
+
+use anyhow::{Result, bail};
+use std::collections::BTreeMap;
+use std::collections::btree_map::Entry;
+use std::path::Path;
+use crate::{
+    BgstError, Bgst, COMPRESSED_IMAGE_SIZE, DecodeOptions, HEADER_SIZE, GRID_ENTRY_SIZE, GridEntry, Header,
+    HeaderParams, ImageList, SceneFilter, build_header, get_raw_images_with
+};
+use crate::manifest::Manifest;
+use crate::unique::METADATA_FILENAME;
+use crate::overrides::{FormatOverrides, parse_texture_format};
+use crate::texture::{self, TextureFormat};
+
+/// The ranges of the header that aren't understood, copied from a template when packing.
+const UNKNOWN_HEADER_RANGES: [std::ops::Range<usize>; 2] = [4..8, 0x30..HEADER_SIZE];
+
+/// Encodes the RGBA of an image into a zero-padded image block.
+///
+/// ### Parameters
+/// - `rgba`: The RGBA of the image.
+/// - `width`: The width of the image, in pixels.
+/// - `height`: The height of the image, in pixels.
+/// - `format`: The texture format to encode the image as.
+///
+/// ### Returns
+/// - the image block
+pub fn encode_block(
+    rgba: &[u8],
+    width: u32,
+    height: u32,
+    format: TextureFormat
+) -> Result<Vec<u8>> {
+    encode_sized_block(rgba, width, height, format, COMPRESSED_IMAGE_SIZE)
+}
+
+/// Encodes an image the same as `encode_block`, into a block of the given size.
+fn encode_sized_block(
+    rgba: &[u8],
+    width: u32,
+    height: u32,
+    format: TextureFormat,
+    block_size: usize
+) -> Result<Vec<u8>> {
+    if rgba.len() != width as usize * height as usize * 4 {
+        bail!(
+            "a {}x{} image needs {} bytes of RGBA, got {}",
+            width,
+            height,
+            width as usize * height as usize * 4,
+            rgba.len()
+        );
+    }
+
+    let required = texture::image_size(format, width, height);
+    if required > block_size {
+        bail!(
+            "a {}x{} image needs {} bytes as {:?}, but image blocks are only {} bytes",
+            width,
+            height,
+            required,
+            format,
+            block_size
+        );
+    }
+
+    let mut block = texture::encode(rgba, width, height, format)?;
+    block.resize(block_size, 0);
+    Ok(block)
+}
+
+/// Builds a BGST file from decoded images, re-encoding every image its entries use.
+/// Entries are written in order directly after the header, and image indices
+/// no entry uses are left as zeroed blocks.
+///
+/// ### Parameters
+/// - `images`: The decoded images.
+/// - `overrides`: The formats to encode images as, which should match the ones they were decoded with.
+///
+/// ### Returns
+/// - the raw data of the bgst3 file
+pub fn pack_images(images: &ImageList, overrides: &FormatOverrides) -> Result<Vec<u8>> {
+    pack_images_with_template(images, overrides, None)
+}
+
+/// Builds a BGST file from decoded images, the same as `pack_images`, copying the
+/// header fields that aren't understood from a template instead of zeroing them.
+/// These are the value at `0x4` and the bytes from `0x30` to the end of the header.
+///
+/// ### Parameters
+/// - `images`: The decoded images.
+/// - `overrides`: The formats to encode images as, which should match the ones they were decoded with.
+/// - `template`: The header of the original file, or the whole file, if any.
+///
+/// ### Returns
+/// - the raw data of the bgst3 file
+pub fn pack_images_with_template(
+    images: &ImageList,
+    overrides: &FormatOverrides,
+    template: Option<&[u8]>
+) -> Result<Vec<u8>> {
+    if template.is_some_and(|template| !Header::is_valid(template)) {
+        bail!(BgstError::InvalidFile("template is not a valid BGST header".to_string()));
+    }
+
+    let mut blocks: BTreeMap<usize, Vec<u8>> = BTreeMap::new();
+
+    for (entry, main, mask) in images.entry_images() {
+        for (image_index, is_mask, rgba) in [
+            (entry.main_image_index, false, main),
+            (entry.mask_image_index, true, mask)
+        ] {
+            let Some(rgba) = rgba else {
+                continue;
+            };
+
+            // the first entry to use an image decides what it contains
+            let image_index = image_index as usize;
+            if let Entry::Vacant(slot) = blocks.entry(image_index) {
+                let format = overrides.format_for(image_index, is_mask);
+                slot.insert(encode_block(rgba, images.image_width, images.image_height, format)?);
+            }
+        }
+    }
+
+    let params = HeaderParams {
+        unk_4: images.unk_4,
+        image_width: images.image_width,
+        image_height: images.image_height,
+        grid_width: images.grid_width,
+        grid_height: images.grid_height,
+        image_count: images.image_count,
+        layer_enabled: images.layer_enabled,
+        ..Default::default()
+    };
+
+    Ok(assemble(params, &images.grid_entries, blocks, COMPRESSED_IMAGE_SIZE, template))
+}
+
+/// Lays out a file: the header, the entries directly after it, and the image blocks,
+/// with indices that have no block left zeroed. The offsets in `params` are filled in.
+/// Every block must be `block_size` bytes long.
+fn assemble(
+    mut params: HeaderParams,
+    entries: &[GridEntry],
+    blocks: BTreeMap<usize, Vec<u8>>,
+    block_size: usize,
+    template: Option<&[u8]>
+) -> Vec<u8> {
+    let image_data_offset = HEADER_SIZE + entries.len() * GRID_ENTRY_SIZE;
+    let image_count = params.image_count as usize;
+    params.info_offset = HEADER_SIZE as u32;
+    params.image_data_offset = image_data_offset as u32;
+
+    let mut contents = build_header(params).to_vec();
+
+    if let Some(template) = template {
+        for range in UNKNOWN_HEADER_RANGES {
+            contents[range.clone()].copy_from_slice(&template[range]);
+        }
+    }
+
+    for entry in entries {
+        contents.extend_from_slice(&entry.to_be_bytes());
+    }
+
+    contents.resize(image_data_offset + image_count * block_size, 0);
+    for (image_index, block) in blocks {
+        let start = image_data_offset + image_index * block_size;
+        contents[start..start + block_size].copy_from_slice(&block);
+    }
+
+    contents
+}
+
+/// Rebuilds a file from a folder written by a unique extraction, following its manifest.
+/// Every image block the manifest lists is re-encoded from its file in the block's format,
+/// and the header, including the bytes that aren't understood, is restored from the manifest.
+/// Every entry is written, but only the image blocks that were extracted are restored;
+/// the rest are left zeroed. Image blocks are as far apart as they were when extracted, but
+/// files read with a larger entry stride are refused, as the bytes past the known fields of
+/// their entries weren't kept.
+///
+/// ### Parameters
+/// - `folder`: The folder holding the manifest and the images.
+///
+/// ### Returns
+/// - the raw data of the bgst3 file
+pub fn pack_bgst(folder: &Path) -> Result<Vec<u8>> {
+    let manifest = Manifest::load(&folder.join(METADATA_FILENAME))?;
+
+    if manifest.entry_stride != GRID_ENTRY_SIZE {
+        bail!(BgstError::InvalidFile(format!(
+            "the entries were read {} bytes apart, but only files with {}-byte entries can be packed",
+            manifest.entry_stride,
+            GRID_ENTRY_SIZE
+        )));
+    }
+
+    let mut blocks = BTreeMap::new();
+    for image in &manifest.images {
+        if image.image_index < 0 || image.image_index as u32 >= manifest.image_count {
+            bail!(BgstError::InvalidFile(format!(
+                "the manifest lists image {}, but the file only has {} images",
+                image.image_index,
+                manifest.image_count
+            )));
+        }
+
+        let path = folder.join(&image.filename);
+        if !path.exists() {
+            bail!(BgstError::FileNotFound(path.display().to_string()));
+        }
+
+        let decoded = image::open(&path)
+            .map_err(|err| BgstError::InvalidFile(format!("failed to read {}: {}", path.display(), err)))?
+            .to_rgba8();
+
+        if decoded.dimensions() != (manifest.image_width, manifest.image_height) {
+            bail!(BgstError::InvalidFile(format!(
+                "{} is {}x{}, but images in this file are {}x{}",
+                path.display(),
+                decoded.width(),
+                decoded.height(),
+                manifest.image_width,
+                manifest.image_height
+            )));
+        }
+
+        let format = parse_texture_format(&image.format)?;
+        blocks.insert(
+            image.image_index as usize,
+            encode_sized_block(decoded.as_raw(), manifest.image_width, manifest.image_height, format, manifest.block_size)?
+        );
+    }
+
+    let entries: Vec<GridEntry> = manifest.entries
+        .iter()
+        .map(|entry| GridEntry {
+            enabled: entry.enabled,
+            scene_index: entry.scene_index,
+            grid_x: entry.grid_x,
+            grid_y: entry.grid_y,
+            main_image_index: entry.main_image_index,
+            mask_image_index: entry.mask_image_index,
+            _unk_c: entry.unk_c,
+            _unk_e: entry.unk_e,
+        })
+        .collect();
+
+    let params = HeaderParams {
+        unk_4: manifest.unk_4,
+        image_width: manifest.image_width,
+        image_height: manifest.image_height,
+        grid_width: manifest.grid_width,
+        grid_height: manifest.grid_height,
+        image_count: manifest.image_count,
+        layer_enabled: manifest.layer_enabled,
+        tail: manifest.reserved,
+        ..Default::default()
+    };
+
+    Ok(assemble(params, &entries, blocks, manifest.block_size, None))
+}
+
+/// Extracts a file, repacks it and checks that every image block its entries use
+/// is re-encoded byte for byte. CMPR encoding isn't guaranteed to be lossless,
+/// so this checks that an encoder reproduces the original data.
+///
+/// ### Parameters
+/// - `original`: The raw data of a bgst3 file.
+///
+/// ### Returns
+/// - an error naming the first block that differs, and the first pixel that differs in it
+pub fn assert_lossless_roundtrip(original: &[u8]) -> Result<()> {
+    // every entry is repacked, including those on disabled layers
+    let options = DecodeOptions { scenes: SceneFilter::All, ..Default::default() };
+    let images = get_raw_images_with(original, &options)?;
+    let repacked = pack_images_with_template(&images, &FormatOverrides::default(), Some(original))?;
+
+    let original_bgst = Bgst::parse(original)?;
+    let repacked_bgst = Bgst::parse(&repacked)?;
+    let header = original_bgst.header();
+
+    for (entry_index, entry) in original_bgst.grid_entries().iter().enumerate() {
+        for (image_index, is_mask) in [(entry.main_image_index, false), (entry.mask_image_index, true)] {
+            if !header.has_image(image_index) {
+                continue;
+            }
+
+            let format = FormatOverrides::default().format_for(image_index as usize, is_mask);
+            let size = texture::image_size(format, header.image_width, header.image_height);
+
+            let before = &header.image_block(original, image_index as usize)?[..size];
+            let after = &repacked_bgst.header().image_block(&repacked, image_index as usize)?[..size];
+
+            let Some(offset) = before.iter().zip(after).position(|(a, b)| a != b) else {
+                continue;
+            };
+
+            let mut message = format!(
+                "entry {}: image {} differs from the original at byte 0x{:X} of its block",
+                entry_index,
+                image_index,
+                offset
+            );
+
+            let before = texture::decode(before, header.image_width, header.image_height, format)?;
+            let after = texture::decode(after, header.image_width, header.image_height, format)?;
+            let pixel = before
+                .chunks_exact(4)
+                .zip(after.chunks_exact(4))
+                .position(|(a, b)| a != b);
+
+            match pixel {
+                Some(pixel) => message += &format!(
+                    "; the first differing pixel is ({}, {}), {:?} in the original and {:?} when repacked",
+                    pixel as u32 % header.image_width,
+                    pixel as u32 / header.image_width,
+                    &before[pixel * 4..pixel * 4 + 4],
+                    &after[pixel * 4..pixel * 4 + 4]
+                ),
+                None => message += ", but every decoded pixel is the same",
+            }
+
+            bail!(BgstError::Decode(message));
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,66 @@
+//! An animated GIF preview that flips through every decoded image.
+
+use anyhow::Result;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use image::*;
+use image::codecs::gif::{GifEncoder, Repeat};
+use crate::{BgstError, DecodeOptions, for_each_raw_image};
+
+/// How much the GIF encoder trades color quality for speed, from 1 to 30.
+/// Previews favour speed since they can have hundreds of frames.
+const ENCODE_SPEED: i32 = 10;
+
+/// Writes every selected image as a frame of a looping GIF, in decode order.
+/// Each image is encoded as soon as it is decoded.
+///
+/// ### Parameters
+/// - `bgst_contents`: The raw data of a bgst3 file.
+/// - `options`: Options that control which images are decoded.
+/// - `writer`: Where to write the GIF.
+/// - `fps`: How many frames are shown per second.
+pub fn write_gif_preview<W: Write>(
+    bgst_contents: &[u8],
+    options: &DecodeOptions,
+    writer: W,
+    fps: u32
+) -> Result<()> {
+    let mut encoder = GifEncoder::new_with_speed(writer, ENCODE_SPEED);
+    encoder.set_repeat(Repeat::Infinite)?;
+
+    let delay = Delay::from_numer_denom_ms(1000, fps.max(1));
+
+    for_each_raw_image(bgst_contents, options, |header, decoded| {
+        if let Some(img) = RgbaImage::from_raw(
+            header.image_width(),
+            header.image_height(),
+            decoded
+        ) {
+            encoder.encode_frame(Frame::from_parts(img, 0, 0, delay))
+                .map_err(|err| BgstError::Write(format!("failed to encode frame: {}", err)))?;
+        }
+
+        Ok(())
+    })?;
+
+    Ok(())
+}
+
+/// Writes every selected image of a file as a frame of a looping GIF.
+///
+/// ### Parameters
+/// - `bgst_contents`: The raw data of a bgst3 file.
+/// - `options`: Options that control which images are decoded.
+/// - `output_filename`: The path of the GIF to create.
+/// - `fps`: How many frames are shown per second.
+pub fn export_gif_preview(
+    bgst_contents: &[u8],
+    options: &DecodeOptions,
+    output_filename: &str,
+    fps: u32
+) -> Result<()> {
+    let file = File::create(output_filename)
+        .map_err(|err| BgstError::Write(format!("failed to create {}: {}", output_filename, err)))?;
+
+    write_gif_preview(bgst_contents, options, BufWriter::new(file), fps)
+}
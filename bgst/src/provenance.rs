@@ -0,0 +1,135 @@
+//! Provenance embedded in written PNGs as `tEXt` chunks, recording which file and entry
+//! each image came from, so it can be placed back even if the manifest is lost.
+
+use anyhow::Result;
+use std::io::Cursor;
+use image::RgbaImage;
+use crate::{GridEntry, SceneIndex};
+
+/// The prefix of every keyword this crate writes.
+const KEYWORD_PREFIX: &str = "bgst:";
+
+/// Where a written image came from.
+///
+/// ### Fields
+/// - `source`: The name of the file it was extracted from, if known.
+///   Characters outside Latin-1, which `tEXt` can't hold, are written as `?`.
+/// - `entry_index`: The index of the entry it belongs to.
+/// - `scene_index`: The raw scene index of the entry.
+/// - `grid_x`: The column of the entry.
+/// - `grid_y`: The row of the entry.
+/// - `image_index`: The image block that was written, or `None` for a finished tile
+///   made from the entry's main image and mask.
+/// - `is_mask`: Whether or not the image block is the entry's mask.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TileProvenance {
+    pub source: Option<String>,
+    pub entry_index: usize,
+    pub scene_index: i16,
+    pub grid_x: i16,
+    pub grid_y: i16,
+    pub image_index: Option<i16>,
+    pub is_mask: bool,
+}
+
+impl TileProvenance {
+    /// Describes an image written for an entry.
+    ///
+    /// ### Parameters
+    /// - `source`: The name of the file it was extracted from, if known.
+    /// - `entry_index`: The index of the entry.
+    /// - `entry`: The entry.
+    /// - `image`: The image block and whether it's the mask, or `None` for a finished tile.
+    pub fn new(source: Option<&str>, entry_index: usize, entry: &GridEntry, image: Option<(i16, bool)>) -> TileProvenance {
+        TileProvenance {
+            source: source.map(str::to_string),
+            entry_index,
+            scene_index: entry.scene_index,
+            grid_x: entry.grid_x,
+            grid_y: entry.grid_y,
+            image_index: image.map(|(image_index, _)| image_index),
+            is_mask: image.is_some_and(|(_, is_mask)| is_mask),
+        }
+    }
+
+    /// Returns the keyword and text of every chunk that records this provenance.
+    pub fn text_chunks(&self) -> Vec<(String, String)> {
+        let mut chunks = Vec::new();
+        let mut add = |key: &str, text: String| chunks.push((format!("{}{}", KEYWORD_PREFIX, key), text));
+
+        if let Some(source) = &self.source {
+            add("source", source.chars().map(|c| if (c as u32) < 0x100 { c } else { '?' }).collect());
+        }
+        add("entry_index", self.entry_index.to_string());
+        add("scene_index", self.scene_index.to_string());
+        add("scene", SceneIndex::name_of(self.scene_index));
+        add("grid_x", self.grid_x.to_string());
+        add("grid_y", self.grid_y.to_string());
+        if let Some(image_index) = self.image_index {
+            add("image_index", image_index.to_string());
+            add("is_mask", self.is_mask.to_string());
+        }
+
+        chunks
+    }
+}
+
+/// Encodes an RGBA image as a PNG with text chunks before its image data.
+///
+/// ### Parameters
+/// - `image`: The image.
+/// - `chunks`: The keyword and text of every chunk.
+///
+/// ### Returns
+/// - the encoded PNG
+pub fn encode_png_with_text(image: &RgbaImage, chunks: &[(String, String)]) -> Result<Vec<u8>> {
+    let mut buffer = Vec::new();
+    let mut encoder = png::Encoder::new(&mut buffer, image.width(), image.height());
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    for (keyword, text) in chunks {
+        encoder.add_text_chunk(keyword.clone(), text.clone())?;
+    }
+
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(image.as_raw())?;
+    writer.finish()?;
+
+    Ok(buffer)
+}
+
+/// Reads the provenance back from a PNG written with it.
+///
+/// ### Parameters
+/// - `png_data`: The encoded PNG.
+///
+/// ### Returns
+/// - the provenance, or `None` if the PNG doesn't record an entry
+pub fn read_provenance(png_data: &[u8]) -> Result<Option<TileProvenance>> {
+    let reader = png::Decoder::new(Cursor::new(png_data)).read_info()?;
+
+    let mut provenance = TileProvenance::default();
+    let mut has_entry = false;
+    for chunk in &reader.info().uncompressed_latin1_text {
+        let Some(key) = chunk.keyword.strip_prefix(KEYWORD_PREFIX) else {
+            continue;
+        };
+
+        let text = chunk.text.as_str();
+        match key {
+            "source" => provenance.source = Some(text.to_string()),
+            "entry_index" => {
+                provenance.entry_index = text.parse()?;
+                has_entry = true;
+            }
+            "scene_index" => provenance.scene_index = text.parse()?,
+            "grid_x" => provenance.grid_x = text.parse()?,
+            "grid_y" => provenance.grid_y = text.parse()?,
+            "image_index" => provenance.image_index = Some(text.parse()?),
+            "is_mask" => provenance.is_mask = text.parse()?,
+            _ => {}
+        }
+    }
+
+    Ok(has_entry.then_some(provenance))
+}
@@ -0,0 +1,192 @@
+//! A summary of everything the validators find in a file, gathered by one call,
+//! for tools such as GUIs and CI checks that want a complete diagnosis.
+//! Only the header and entry table are parsed; no images are decoded.
+
+use anyhow::Result;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use serde::Serialize;
+use crate::warnings::{Warning, collect_warnings};
+use crate::{
+    Bgst, DanglingReference, SceneIndex, dangling_references, entry_count, entry_table_padding,
+    referenced_indices, validate_scenes
+};
+
+/// The fields of a file's header.
+///
+/// ### Fields
+/// - `unk_4`: The unknown value at offset `0x4`.
+/// - `image_width`: The width of every image, in pixels.
+/// - `image_height`: The height of every image, in pixels.
+/// - `grid_width`: The number of columns the grid has.
+/// - `grid_height`: The number of rows the grid has.
+/// - `image_count`: The number of images the file declares.
+/// - `layer_enabled`: Which of the 12 rendering layers are available to entries.
+/// - `info_offset`: The offset of the entry table.
+/// - `image_data_offset`: The offset of the image blocks.
+/// - `tail`: The bytes from `0x30` to the end of the header.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct HeaderSummary {
+    pub unk_4: u32,
+    pub image_width: u32,
+    pub image_height: u32,
+    pub grid_width: u32,
+    pub grid_height: u32,
+    pub image_count: u32,
+    pub layer_enabled: [bool; 12],
+    pub info_offset: usize,
+    pub image_data_offset: usize,
+    pub tail: [u8; 16],
+}
+
+/// Whether a file is the size its header says it should be.
+///
+/// ### Fields
+/// - `file_size`: The size of the file, in bytes.
+/// - `required_size`: The size needed to hold every declared image block, in bytes.
+/// - `trailing_bytes`: The number of bytes after the last image block.
+/// - `entry_table_padding`: The number of bytes after the last whole entry; see `entry_table_padding`.
+/// - `repaired_image_count`: The image count the file really has room for, if it declares one too many.
+/// - `error`: Why the file can't be decoded as it is, if it's truncated.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct SizeCheck {
+    pub file_size: usize,
+    pub required_size: usize,
+    pub trailing_bytes: usize,
+    pub entry_table_padding: usize,
+    pub repaired_image_count: Option<u32>,
+    pub error: Option<String>,
+}
+
+/// Everything the validators find in a file.
+///
+/// ### Fields
+/// - `header`: The fields of the header.
+/// - `entry_count`: The number of grid entries.
+/// - `scene_counts`: The number of entries on each scene that has any, by `SceneIndex::name_of`.
+/// - `main_images`: The image indices referenced as main images.
+/// - `mask_images`: The image indices referenced as masks.
+/// - `orphaned_images`: The image indices no entry references, among the blocks the file holds.
+///   Declared images past the end of the file aren't listed, since the count can't be trusted.
+/// - `unknown_scenes`: The entries whose scene isn't one of the known scenes.
+/// - `dangling_references`: The image indices that are out of range.
+/// - `warnings`: Every non-fatal problem, including overlapping entries; see `collect_warnings`.
+/// - `size`: Whether the file is the size the header says it should be.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Report {
+    pub header: HeaderSummary,
+    pub entry_count: usize,
+    pub scene_counts: BTreeMap<String, usize>,
+    pub main_images: Vec<i16>,
+    pub mask_images: Vec<i16>,
+    pub orphaned_images: Vec<u32>,
+    pub unknown_scenes: Vec<usize>,
+    pub dangling_references: Vec<DanglingReference>,
+    pub warnings: Vec<Warning>,
+    pub size: SizeCheck,
+}
+
+impl Report {
+    /// Returns if nothing is wrong with the file: it isn't truncated, and there are no warnings.
+    pub fn is_clean(&self) -> bool {
+        self.size.error.is_none() && self.warnings.is_empty() && self.unknown_scenes.is_empty()
+    }
+}
+
+/// Parses a file and runs every validator on it. Problems are reported rather than
+/// returned as errors, so only files that can't be parsed at all fail.
+///
+/// ### Parameters
+/// - `data`: The raw data of a bgst3 file.
+///
+/// ### Returns
+/// - the report
+pub fn inspect(data: &[u8]) -> Result<Report> {
+    let bgst = Bgst::parse(data)?;
+    let header = bgst.header();
+
+    let mut scene_counts = BTreeMap::new();
+    for entry in bgst.grid_entries() {
+        *scene_counts.entry(SceneIndex::name_of(entry.scene_index)).or_insert(0) += 1;
+    }
+
+    let (main_images, mask_images) = referenced_indices(&bgst);
+    let referenced: BTreeSet<u32> = main_images
+        .iter()
+        .chain(&mask_images)
+        .filter_map(|index| u32::try_from(*index).ok())
+        .collect();
+    let held_images = data.len().saturating_sub(header.image_data_offset) / header.block_size;
+    let orphaned_images = (0..header.image_count.min(held_images.try_into().unwrap_or(u32::MAX)))
+        .filter(|index| !referenced.contains(index))
+        .collect();
+
+    let required_size = header.image_data_offset + header.image_data_size();
+
+    Ok(Report {
+        header: HeaderSummary {
+            unk_4: header._unk_4,
+            image_width: header.image_width,
+            image_height: header.image_height,
+            grid_width: header.grid_width,
+            grid_height: header.grid_height,
+            image_count: header.image_count,
+            layer_enabled: header._layer_enabled,
+            info_offset: header.info_offset,
+            image_data_offset: header.image_data_offset,
+            tail: header.header_tail(),
+        },
+        entry_count: entry_count(header),
+        scene_counts,
+        main_images: main_images.into_iter().collect(),
+        mask_images: mask_images.into_iter().collect(),
+        orphaned_images,
+        unknown_scenes: validate_scenes(&bgst),
+        dangling_references: dangling_references(&bgst),
+        warnings: collect_warnings(&bgst, data),
+        size: SizeCheck {
+            file_size: data.len(),
+            required_size,
+            trailing_bytes: data.len().saturating_sub(required_size),
+            entry_table_padding: entry_table_padding(header),
+            repaired_image_count: header.repaired_image_count(data.len()),
+            error: header.check_file_size(data.len()).err().map(|err| format!("{:#}", err)),
+        },
+    })
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let header = &self.header;
+        writeln!(f, "images: {} of {}x{} pixels", header.image_count, header.image_width, header.image_height)?;
+        writeln!(f, "grid: {}x{}", header.grid_width, header.grid_height)?;
+        writeln!(f, "entries: {}", self.entry_count)?;
+        for (scene, count) in &self.scene_counts {
+            writeln!(f, "  {}: {}", scene, count)?;
+        }
+
+        writeln!(f, "referenced main images: {}", self.main_images.len())?;
+        writeln!(f, "referenced mask images: {}", self.mask_images.len())?;
+        writeln!(f, "orphaned images: {}", self.orphaned_images.len())?;
+
+        writeln!(
+            f,
+            "file size: 0x{:X}, 0x{:X} required, 0x{:X} trailing",
+            self.size.file_size,
+            self.size.required_size,
+            self.size.trailing_bytes
+        )?;
+        if let Some(error) = &self.size.error {
+            writeln!(f, "error: {}", error)?;
+        }
+
+        for entry_index in &self.unknown_scenes {
+            writeln!(f, "warning: entry {} is on an unknown scene", entry_index)?;
+        }
+        for warning in &self.warnings {
+            writeln!(f, "warning: {}", warning)?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,146 @@
+//! Support for image blocks that were dumped already deswizzled. GameCube textures are stored
+//! in tiles, which the decoders expect; linear data is rearranged into tiles before it is decoded.
+//! Decoding data in the wrong layout produces a blocky scramble, which `roughness` measures.
+
+use anyhow::{Result, bail};
+use crate::texture::{self, TextureFormat};
+
+/// How the data of an image block is laid out.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TileLayout {
+    /// Tiles, the way the GameCube stores textures.
+    #[default]
+    Tiled,
+    /// Rows of pixels from the top, or rows of 4x4 blocks for CMPR.
+    Linear,
+    /// Whichever of the two decodes to the smoother image, checked for every image.
+    Detect,
+}
+
+/// Converts the name of a layout, such as `"tiled"`, `"linear"` or `"detect"`.
+///
+/// ### Parameters
+/// - `name`: The name of the layout.
+///
+/// ### Returns
+/// - the layout
+pub fn parse_layout(name: &str) -> Result<TileLayout> {
+    let layout = match name.to_ascii_lowercase().as_str() {
+        "tiled" => TileLayout::Tiled,
+        "linear" => TileLayout::Linear,
+        "detect" => TileLayout::Detect,
+        _ => bail!("unknown layout {}", name),
+    };
+
+    Ok(layout)
+}
+
+/// Returns the width and height of a tile of a format, in pixels, and its bits per pixel,
+/// or `None` for formats that aren't rearranged.
+fn tile_shape(format: TextureFormat) -> Option<(usize, usize, usize)> {
+    match format {
+        TextureFormat::I4 => Some((8, 8, 4)),
+        TextureFormat::I8 | TextureFormat::IA4 => Some((8, 4, 8)),
+        TextureFormat::IA8 | TextureFormat::RGB565 | TextureFormat::RGB5A3 => Some((4, 4, 16)),
+        _ => None,
+    }
+}
+
+/// Rearranges linear data into the tiles the decoders expect. Bytes past the end of the data
+/// are read as zero, and formats without a known tile shape are returned unchanged.
+///
+/// ### Parameters
+/// - `data`: The linear data.
+/// - `width`: The width of the image, in pixels.
+/// - `height`: The height of the image, in pixels.
+/// - `format`: The texture format of the data.
+///
+/// ### Returns
+/// - the data in tiles
+pub fn tile_linear(data: &[u8], width: u32, height: u32, format: TextureFormat) -> Vec<u8> {
+    let (width, height) = (width as usize, height as usize);
+    let byte_at = |offset: usize| data.get(offset).copied().unwrap_or(0);
+    let mut tiled = Vec::with_capacity(texture::image_size(format, width as u32, height as u32));
+
+    match format {
+        // 8x8 tiles of four 8-byte 4x4 blocks, from the top left
+        TextureFormat::CMPR => {
+            let blocks_across = width.div_ceil(4);
+            for tile_y in 0..height.div_ceil(8) {
+                for tile_x in 0..width.div_ceil(8) {
+                    for (sub_x, sub_y) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+                        let block = (tile_y * 2 + sub_y) * blocks_across + tile_x * 2 + sub_x;
+                        tiled.extend((0..8).map(|i| byte_at(block * 8 + i)));
+                    }
+                }
+            }
+        }
+        // 4x4 tiles of the alpha and red of every pixel, followed by the green and blue
+        TextureFormat::RGBA8 => {
+            for tile_y in 0..height.div_ceil(4) {
+                for tile_x in 0..width.div_ceil(4) {
+                    let pixels: Vec<usize> = (0..16)
+                        .map(|i| ((tile_y * 4 + i / 4) * width + tile_x * 4 + i % 4) * 4)
+                        .collect();
+                    for pixel in &pixels {
+                        tiled.extend([byte_at(pixel + 3), byte_at(*pixel)]);
+                    }
+                    for pixel in &pixels {
+                        tiled.extend([byte_at(pixel + 1), byte_at(pixel + 2)]);
+                    }
+                }
+            }
+        }
+        _ => {
+            let Some((tile_width, tile_height, bits)) = tile_shape(format) else {
+                return data.to_vec();
+            };
+
+            let row_len = width * bits / 8;
+            let tile_row_len = tile_width * bits / 8;
+            for tile_y in 0..height.div_ceil(tile_height) {
+                for tile_x in 0..width.div_ceil(tile_width) {
+                    for row in 0..tile_height {
+                        let start = (tile_y * tile_height + row) * row_len + tile_x * tile_row_len;
+                        tiled.extend((0..tile_row_len).map(|i| byte_at(start + i)));
+                    }
+                }
+            }
+        }
+    }
+
+    tiled
+}
+
+/// Measures how much neighbouring pixels of an RGBA image differ, summing the difference of
+/// the color channels of every pixel and the ones to its right and below it.
+/// Images decoded in the wrong layout are much rougher than the right one.
+///
+/// ### Parameters
+/// - `rgba`: The RGBA of the image.
+/// - `width`: The width of the image, in pixels.
+/// - `height`: The height of the image, in pixels.
+///
+/// ### Returns
+/// - the roughness, which only means something compared to another image of the same size
+pub fn roughness(rgba: &[u8], width: u32, height: u32) -> u64 {
+    let (width, height) = (width as usize, height as usize);
+    let difference = |a: usize, b: usize| -> u64 {
+        (0..3).map(|channel| rgba[a * 4 + channel].abs_diff(rgba[b * 4 + channel]) as u64).sum()
+    };
+
+    let mut total = 0;
+    for y in 0..height {
+        for x in 0..width {
+            let pixel = y * width + x;
+            if x + 1 < width {
+                total += difference(pixel, pixel + 1);
+            }
+            if y + 1 < height {
+                total += difference(pixel, pixel + width);
+            }
+        }
+    }
+
+    total
+}
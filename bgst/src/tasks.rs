@@ -0,0 +1,131 @@
+//! Async wrappers around extraction for applications that can't block their event loop.
+//! The work runs on its own thread and the returned future completes when it's done,
+//! so it can be awaited from any executor without depending on a particular runtime.
+
+use anyhow::{Result, anyhow};
+use std::future::Future;
+use std::panic::{self, AssertUnwindSafe};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc::Sender;
+use std::task::{Context, Poll, Waker};
+use std::thread;
+use crate::{BgstSource, ExtractOptions, extract_bgst_to};
+use crate::timing::{DecodeEvent, DecodeObserver};
+
+/// The state shared between a task's thread and its future.
+struct Shared<T> {
+    result: Option<Result<T>>,
+    waker: Option<Waker>,
+}
+
+/// A future for work running on its own thread, which completes with the work's result.
+/// A panic in the work is returned as an error instead of being lost.
+pub struct BlockingTask<T> {
+    shared: Arc<Mutex<Shared<T>>>,
+}
+
+impl<T: Send + 'static> BlockingTask<T> {
+    /// Starts running work on a new thread.
+    ///
+    /// ### Parameters
+    /// - `work`: The work to run.
+    ///
+    /// ### Returns
+    /// - a future that completes with the result of the work
+    pub fn spawn(work: impl FnOnce() -> Result<T> + Send + 'static) -> BlockingTask<T> {
+        let shared = Arc::new(Mutex::new(Shared { result: None, waker: None }));
+        let task_shared = Arc::clone(&shared);
+
+        thread::spawn(move || {
+            let result = panic::catch_unwind(AssertUnwindSafe(work))
+                .unwrap_or_else(|_| Err(anyhow!("the task panicked")));
+
+            let mut shared = task_shared.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+            shared.result = Some(result);
+            if let Some(waker) = shared.waker.take() {
+                waker.wake();
+            }
+        });
+
+        BlockingTask { shared }
+    }
+}
+
+impl<T> Future for BlockingTask<T> {
+    type Output = Result<T>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut shared = self.shared.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+
+        match shared.result.take() {
+            Some(result) => Poll::Ready(result),
+            None => {
+                shared.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+/// The progress of an extraction started with `extract_bgst_async`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ExtractProgress {
+    /// The extraction has started on its thread.
+    Started,
+    /// Another image block was decoded, `done` of the `total` the selected entries use.
+    /// Some extractions don't decode every one, so `done` can stop short of `total`.
+    Decoded { done: usize, total: usize },
+    /// The extraction has finished, successfully or with the given error.
+    Finished(Result<(), String>),
+}
+
+/// Extracts a file like `extract_bgst_to` without blocking the calling thread.
+/// Reading, decoding and writing all happen on a separate thread.
+///
+/// ### Parameters
+/// - `source`: Where to read the bgst3 file from.
+/// - `folder_name`: The folder to extract into. It is replaced if it exists.
+/// - `options`: Options that control which images are decoded and what is written.
+/// - `progress`: If set, receives an `ExtractProgress` when the extraction starts,
+///   after every image block it decodes, and when it finishes. Any observer already set
+///   in the options is still told about every block. Nothing stops if the receiver is dropped.
+pub async fn extract_bgst_async(
+    source: impl BgstSource + Send + 'static,
+    folder_name: String,
+    mut options: ExtractOptions,
+    progress: Option<Sender<ExtractProgress>>
+) -> Result<()> {
+    if let Some(progress) = progress.clone() {
+        let observer = options.decode.observer.take();
+        let counts = Mutex::new((0, 0));
+
+        options.decode.observer = Some(DecodeObserver::new(move |event| {
+            if let Some(observer) = &observer {
+                observer.notify(event);
+            }
+
+            let mut counts = counts.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+            match event {
+                DecodeEvent::Selected { images } => *counts = (0, *images),
+                DecodeEvent::Decoded(_) => {
+                    counts.0 += 1;
+                    let _ = progress.send(ExtractProgress::Decoded { done: counts.0, total: counts.1 });
+                }
+            }
+        }));
+    }
+
+    BlockingTask::spawn(move || {
+        let report = |event| {
+            if let Some(progress) = &progress {
+                let _ = progress.send(event);
+            }
+        };
+
+        report(ExtractProgress::Started);
+        let result = extract_bgst_to(source, &folder_name, &options);
+        report(ExtractProgress::Finished(result.as_ref().map(|_| ()).map_err(|err| format!("{:#}", err))));
+        result
+    }).await
+}
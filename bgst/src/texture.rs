@@ -0,0 +1,153 @@
+//! The texture formats image blocks are stored in, and the decoders used for them.
+//! With the `gctex` feature, which is on by default, every format is decoded by `gctex`
+//! (CMPR by `cmpr` instead when `builtin-cmpr` is enabled).
+//! Without it, only the formats of the usual main and mask images can be decoded:
+//! CMPR by `cmpr`, so `builtin-cmpr` is required, and I4 by the decoder here.
+//! Decoding any other format, and encoding images to pack them, then fails.
+
+use anyhow::Result;
+#[cfg(not(feature = "gctex"))]
+use anyhow::bail;
+#[cfg(not(feature = "gctex"))]
+use crate::BgstError;
+#[cfg(feature = "builtin-cmpr")]
+use crate::cmpr;
+
+#[cfg(not(any(feature = "gctex", feature = "builtin-cmpr")))]
+compile_error!("bgst needs the `gctex` or `builtin-cmpr` feature to decode images");
+
+#[cfg(feature = "gctex")]
+pub use gctex::TextureFormat;
+
+/// The texture formats of the GameCube, named the same as in `gctex`.
+#[cfg(not(feature = "gctex"))]
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum TextureFormat {
+    I4 = 0,
+    I8,
+    IA4,
+    IA8,
+    RGB565,
+    RGB5A3,
+    RGBA8,
+
+    C4 = 0x8,
+    C8,
+    C14X2,
+    CMPR = 0xE,
+}
+
+/// Returns the size of an image in a format, padded to whole tiles.
+///
+/// ### Parameters
+/// - `format`: The texture format of the image.
+/// - `width`: The width of the image, in pixels.
+/// - `height`: The height of the image, in pixels.
+///
+/// ### Returns
+/// - the size of the image, in bytes
+pub fn image_size(format: TextureFormat, width: u32, height: u32) -> usize {
+    let (tile_width, tile_height, bits_per_pixel) = match format {
+        TextureFormat::I4 | TextureFormat::C4 | TextureFormat::CMPR => (8, 8, 4),
+        TextureFormat::I8 | TextureFormat::IA4 | TextureFormat::C8 => (8, 4, 8),
+        TextureFormat::IA8 | TextureFormat::RGB565 | TextureFormat::RGB5A3 | TextureFormat::C14X2 => (4, 4, 16),
+        TextureFormat::RGBA8 => (4, 4, 32),
+        #[cfg(feature = "gctex")]
+        TextureFormat::ExtensionRawRGBA32 => (1, 1, 32),
+    };
+
+    let width = (width as usize).div_ceil(tile_width) * tile_width;
+    let height = (height as usize).div_ceil(tile_height) * tile_height;
+    width * height * bits_per_pixel / 8
+}
+
+/// Decodes tiled data to RGBA.
+///
+/// ### Parameters
+/// - `encoded`: The tiled data, covering the image rounded up to whole tiles.
+/// - `width`: The width of the image, in pixels.
+/// - `height`: The height of the image, in pixels.
+/// - `format`: The texture format of the data.
+///
+/// ### Returns
+/// - the RGBA of the image
+pub(crate) fn decode(encoded: &[u8], width: u32, height: u32, format: TextureFormat) -> Result<Vec<u8>> {
+    match format {
+        #[cfg(feature = "builtin-cmpr")]
+        TextureFormat::CMPR => Ok(cmpr::decode_cmpr_builtin(encoded, width, height)),
+        #[cfg(feature = "gctex")]
+        _ => Ok(gctex::decode(encoded, width, height, format, &[], 0)),
+        #[cfg(not(feature = "gctex"))]
+        _ => {
+            let mut rgba = vec![0u8; width as usize * height as usize * 4];
+            decode_into(&mut rgba, encoded, width, height, format)?;
+            Ok(rgba)
+        }
+    }
+}
+
+/// Decodes tiled data to RGBA in a buffer the caller owns.
+///
+/// ### Parameters
+/// - `rgba`: Where to write the RGBA, exactly `width * height * 4` bytes long.
+/// - `encoded`: The tiled data, covering the image rounded up to whole tiles.
+/// - `width`: The width of the image, in pixels.
+/// - `height`: The height of the image, in pixels.
+/// - `format`: The texture format of the data.
+pub(crate) fn decode_into(rgba: &mut [u8], encoded: &[u8], width: u32, height: u32, format: TextureFormat) -> Result<()> {
+    match format {
+        #[cfg(feature = "builtin-cmpr")]
+        TextureFormat::CMPR => cmpr::decode_cmpr_builtin_into(rgba, encoded, width, height),
+        #[cfg(feature = "gctex")]
+        _ => gctex::decode_into(rgba, encoded, width, height, format, &[], 0),
+        #[cfg(not(feature = "gctex"))]
+        TextureFormat::I4 => decode_i4_into(rgba, encoded, width, height),
+        #[cfg(not(feature = "gctex"))]
+        _ => bail!(BgstError::Decode(format!("decoding {:?} needs the `gctex` feature", format))),
+    }
+
+    Ok(())
+}
+
+/// Encodes RGBA into tiled data.
+///
+/// ### Parameters
+/// - `rgba`: The RGBA of the image.
+/// - `width`: The width of the image, in pixels.
+/// - `height`: The height of the image, in pixels.
+/// - `format`: The texture format to encode the image as.
+///
+/// ### Returns
+/// - the tiled data
+#[cfg(feature = "gctex")]
+pub(crate) fn encode(rgba: &[u8], width: u32, height: u32, format: TextureFormat) -> Result<Vec<u8>> {
+    Ok(gctex::encode(format, rgba, width, height))
+}
+
+/// Encoding needs `gctex`, so without it every image fails to encode.
+#[cfg(not(feature = "gctex"))]
+pub(crate) fn encode(_rgba: &[u8], _width: u32, _height: u32, format: TextureFormat) -> Result<Vec<u8>> {
+    bail!(BgstError::Write(format!("encoding {:?} needs the `gctex` feature", format)))
+}
+
+/// Decodes I4 data, 8x8 tiles of 4-bit intensities, to RGBA with the intensity in every channel,
+/// the same as `gctex`. Pixels the data doesn't cover are zeroed.
+#[cfg(any(test, not(feature = "gctex")))]
+pub(crate) fn decode_i4_into(rgba: &mut [u8], encoded: &[u8], width: u32, height: u32) {
+    let (width, height) = (width as usize, height as usize);
+    let tiles_wide = width.div_ceil(8);
+    rgba.fill(0);
+
+    for (i, byte) in encoded.iter().enumerate() {
+        let tile = i / 32;
+        let (tile_x, tile_y) = ((tile % tiles_wide) * 8, (tile / tiles_wide) * 8);
+        let (x, y) = (tile_x + (i % 4) * 2, tile_y + (i % 32) / 4);
+
+        for (offset, intensity) in [(0, byte >> 4), (1, byte & 0xF)] {
+            if x + offset < width && y < height {
+                let start = (y * width + x + offset) * 4;
+                rgba[start..start + 4].fill(intensity * 0x11);
+            }
+        }
+    }
+}
@@ -0,0 +1,51 @@
+//! Export of every tile to one multi-page TIFF, so a whole file can be archived
+//! or opened in tools that page through images, instead of writing a folder of PNGs.
+
+use anyhow::{Result, bail};
+use std::fs::File;
+use std::io::{Seek, Write};
+use ::tiff::encoder::{TiffEncoder, colortype, compression::Deflate};
+use crate::{BgstError, ImageList, masked_tiles};
+
+/// Writes the finished tile of every entry that has a main image as a page of a TIFF,
+/// with masks applied, in entry order. Pages are RGBA and compressed with Deflate.
+///
+/// ### Parameters
+/// - `images`: The decoded images.
+/// - `writer`: Where to write the TIFF.
+///
+/// ### Returns
+/// - the number of pages written
+pub fn write_tiff<W: Write + Seek>(images: &ImageList, writer: W) -> Result<usize> {
+    let mut encoder = TiffEncoder::new(writer)?;
+    let mut pages = 0;
+
+    for tile in masked_tiles(images) {
+        let (tile, _) = tile?;
+        encoder.write_image_with_compression::<colortype::RGBA8, _>(
+            tile.width(),
+            tile.height(),
+            Deflate::default(),
+            tile.as_raw()
+        )?;
+        pages += 1;
+    }
+
+    if pages == 0 {
+        bail!("no entries have a main image to write to the TIFF");
+    }
+
+    Ok(pages)
+}
+
+/// Writes every tile of a file as a page of a TIFF.
+///
+/// ### Parameters
+/// - `images`: The decoded images.
+/// - `output_filename`: The path of the `.tiff` file to create.
+pub fn export_tiff(images: &ImageList, output_filename: &str) -> Result<()> {
+    let file = File::create(output_filename)
+        .map_err(|err| BgstError::Write(format!("failed to create {}: {}", output_filename, err)))?;
+    write_tiff(images, file)?;
+    Ok(())
+}
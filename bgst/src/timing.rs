@@ -0,0 +1,95 @@
+//! Timing of every image block's decode, for spotting blocks that are unusually slow,
+//! and observers that are told about every block as it's decoded, such as for progress.
+
+use anyhow::Result;
+use std::fmt;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use crate::{DecodeOptions, decode_block, select_entries};
+
+/// How long one image block took to decode.
+///
+/// ### Fields
+/// - `entry_index`: The index of the entry that uses the block.
+/// - `image_index`: The index of the image block.
+/// - `is_mask`: Whether or not the block was decoded as the entry's mask.
+/// - `duration`: How long decoding took.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BlockTiming {
+    pub entry_index: usize,
+    pub image_index: i16,
+    pub is_mask: bool,
+    pub duration: Duration,
+}
+
+/// Something that happened while decoding a file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DecodeEvent {
+    /// The entries to decode were picked out, and use this many images in total.
+    /// Some may not need decoding, such as identical blocks when extracting unique images.
+    Selected { images: usize },
+    /// An image block was decoded.
+    Decoded(BlockTiming),
+}
+
+/// Receives a `DecodeEvent` for everything that happens while decoding with the
+/// `DecodeOptions` it's set on. Events are sent from the thread doing the decoding.
+#[derive(Clone)]
+pub struct DecodeObserver(Arc<dyn Fn(&DecodeEvent) + Send + Sync>);
+
+impl DecodeObserver {
+    /// Creates an observer that calls a function with every event.
+    ///
+    /// ### Parameters
+    /// - `on_event`: The function to call.
+    pub fn new(on_event: impl Fn(&DecodeEvent) + Send + Sync + 'static) -> DecodeObserver {
+        DecodeObserver(Arc::new(on_event))
+    }
+
+    /// Sends an event to the observer.
+    pub fn notify(&self, event: &DecodeEvent) {
+        (self.0)(event)
+    }
+}
+
+impl fmt::Debug for DecodeObserver {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("DecodeObserver")
+    }
+}
+
+/// Decodes every image block selected by the given options, timing each one.
+/// Blocks are decoded one at a time on the calling thread so their times don't overlap.
+/// Blocks are decoded once per entry that uses them, the same as when extracting.
+///
+/// ### Parameters
+/// - `bgst_contents`: The raw data of a bgst3 file.
+/// - `options`: Options that control which images are decoded.
+///
+/// ### Returns
+/// - the time of every decoded block, in decode order
+pub fn time_blocks(bgst_contents: &[u8], options: &DecodeOptions) -> Result<Vec<BlockTiming>> {
+    let (header, selected, _) = select_entries(bgst_contents, options)?;
+    let mut timings = Vec::new();
+
+    for (entry_index, entry) in &selected {
+        for (image_index, is_mask) in [(entry.main_image_index, false), (entry.mask_image_index, true)] {
+            if !header.has_image(image_index) || (options.masks_only && !is_mask) {
+                continue;
+            }
+
+            let format = options.overrides.format_for(image_index as usize, is_mask);
+            let start = Instant::now();
+            decode_block(&header, bgst_contents, *entry_index, image_index, format, is_mask)?;
+
+            timings.push(BlockTiming {
+                entry_index: *entry_index,
+                image_index,
+                is_mask,
+                duration: start.elapsed(),
+            });
+        }
+    }
+
+    Ok(timings)
+}
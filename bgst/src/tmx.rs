@@ -0,0 +1,154 @@
+//! Export of the grid to a Tiled (`.tmx`) map, so backgrounds can be opened in the Tiled editor.
+//! Every distinct tile is drawn once into an atlas that serves as the map's tileset,
+//! and every scene with tiles placed on it becomes a tile layer.
+
+use anyhow::Result;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::Path;
+use image::*;
+use crate::{BgstError, ImageList, SceneIndex};
+use crate::atlas::build_atlas;
+
+/// A Tiled map and the atlas its tileset draws from.
+///
+/// ### Fields
+/// - `tmx`: The contents of the `.tmx` file.
+/// - `atlas`: The tileset image, with every distinct tile in rows from the top left.
+pub struct TiledMap {
+    pub tmx: String,
+    pub atlas: RgbaImage,
+}
+
+/// Escapes text for use inside a double-quoted XML attribute.
+///
+/// ### Parameters
+/// - `text`: The text to escape.
+fn escape_attribute(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped += "&amp;",
+            '<' => escaped += "&lt;",
+            '>' => escaped += "&gt;",
+            '"' => escaped += "&quot;",
+            '\'' => escaped += "&apos;",
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Builds a Tiled map of every placed tile, with masks applied.
+/// Entries that use the same main and mask image share one tile in the tileset.
+/// Layers are named after their scene and ordered from the farthest to the nearest,
+/// and a later entry on the same cell replaces an earlier one.
+///
+/// ### Parameters
+/// - `images`: The decoded images.
+/// - `atlas_source`: The path of the atlas as written into the map, relative to the map.
+///   It's escaped, so it can hold any characters.
+///
+/// ### Returns
+/// - the map and its atlas
+pub fn build_tmx(images: &ImageList, atlas_source: &str) -> Result<TiledMap> {
+    let atlas = build_atlas(images)?;
+    let mut layers: BTreeMap<SceneIndex, HashMap<(u32, u32), u32>> = BTreeMap::new();
+    let mut columns = images.grid_width;
+    let mut rows = images.grid_height;
+
+    for frame in &atlas.frames {
+        columns = columns.max(frame.grid_x + 1);
+        rows = rows.max(frame.grid_y + 1);
+        layers.entry(frame.scene).or_default().insert((frame.grid_x, frame.grid_y), frame.tile);
+    }
+
+    // the layers hold a cell for every tile a composite would, so they get the same limit
+    images.canvas_size(columns, rows)?;
+
+    let tile_width = atlas.tile_width;
+    let tile_height = atlas.tile_height;
+    let tile_count = atlas.tile_count;
+    let atlas_columns = atlas.columns;
+    let atlas = atlas.image;
+
+    let mut tmx = String::new();
+    tmx += "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n";
+    tmx += &format!(
+        "<map version=\"1.10\" orientation=\"orthogonal\" renderorder=\"right-down\" width=\"{}\" height=\"{}\" \
+         tilewidth=\"{}\" tileheight=\"{}\" infinite=\"0\" nextlayerid=\"{}\" nextobjectid=\"1\">\n",
+        columns,
+        rows,
+        tile_width,
+        tile_height,
+        layers.len() + 1
+    );
+    tmx += &format!(
+        " <tileset firstgid=\"1\" name=\"tiles\" tilewidth=\"{}\" tileheight=\"{}\" tilecount=\"{}\" columns=\"{}\">\n",
+        tile_width,
+        tile_height,
+        tile_count,
+        atlas_columns
+    );
+    tmx += &format!(
+        "  <image source=\"{}\" width=\"{}\" height=\"{}\"/>\n",
+        escape_attribute(atlas_source),
+        atlas.width(),
+        atlas.height()
+    );
+    tmx += " </tileset>\n";
+
+    for (layer_id, (scene, cells)) in layers.iter().enumerate() {
+        tmx += &format!(
+            " <layer id=\"{}\" name=\"{}\" width=\"{}\" height=\"{}\">\n",
+            layer_id + 1,
+            scene.name(),
+            columns,
+            rows
+        );
+        tmx += "  <data encoding=\"csv\">\n";
+
+        // global tile ids start at 1, and 0 leaves a cell empty
+        let data: Vec<String> = (0..rows)
+            .map(|y| {
+                (0..columns)
+                    .map(|x| cells.get(&(x, y)).map_or(0, |id| id + 1).to_string())
+                    .collect::<Vec<String>>()
+                    .join(",")
+            })
+            .collect();
+        tmx += &data.join(",\n");
+
+        tmx += "\n  </data>\n";
+        tmx += " </layer>\n";
+    }
+
+    tmx += "</map>\n";
+
+    Ok(TiledMap { tmx, atlas })
+}
+
+/// Writes a Tiled map of a file, with its atlas written next to it
+/// under the same name followed by `_tiles.png`.
+///
+/// ### Parameters
+/// - `images`: The decoded images.
+/// - `output_filename`: The path of the `.tmx` file to create.
+pub fn export_tmx(images: &ImageList, output_filename: &str) -> Result<()> {
+    let output_path = Path::new(output_filename);
+    let stem = output_path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let atlas_name = format!("{}_tiles.png", stem);
+    let atlas_path = output_path.with_file_name(&atlas_name);
+
+    let map = build_tmx(images, &atlas_name)?;
+
+    map.atlas.save_with_format(&atlas_path, ImageFormat::Png)
+        .map_err(|err| BgstError::Write(format!("failed to write {}: {}", atlas_path.display(), err)))?;
+    fs::write(output_path, map.tmx)
+        .map_err(|err| BgstError::Write(format!("failed to write {}: {}", output_filename, err)))?;
+
+    Ok(())
+}
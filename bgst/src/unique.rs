@@ -0,0 +1,243 @@
+//! Extraction of every distinct image once, named by its image index,
+//! along with a `metadata.json` manifest that maps entries to the images they use.
+//! Entries often share images, so this writes far fewer files than a normal
+//! extraction while keeping enough to rebuild the file with `pack::pack_bgst`.
+
+use anyhow::Result;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::path::Path;
+use image::*;
+use crate::{Bgst, DanglingReference, ExtractOptions, Header, OutputFormat, SceneIndex, decode_block, select_entries, write_tile};
+use crate::manifest::{MANIFEST_VERSION, Manifest, ManifestEntry, ManifestImage, normalized, scene_bounds};
+
+/// The name of the manifest written next to the images.
+pub const METADATA_FILENAME: &str = "metadata.json";
+
+/// Returns the name an image is written under, without the extension.
+/// An image used as a mask is decoded differently, so it gets its own name.
+///
+/// ### Parameters
+/// - `image_index`: The image index.
+/// - `is_mask`: Whether the image is used as a mask.
+pub fn unique_image_name(image_index: i16, is_mask: bool) -> String {
+    if is_mask {
+        format!("img_{}_mask", image_index)
+    } else {
+        format!("img_{}", image_index)
+    }
+}
+
+/// Which image blocks of a file hold identical data.
+///
+/// ### Fields
+/// - `canonical`: For every image index, the first image index whose block holds the same bytes.
+pub struct BlockDedup {
+    canonical: Vec<usize>,
+}
+
+impl BlockDedup {
+    /// Returns the first image index whose block is identical to the given one's,
+    /// which is the index itself if no earlier block matches.
+    ///
+    /// ### Parameters
+    /// - `image_index`: The image index.
+    pub fn canonical_index(&self, image_index: usize) -> usize {
+        self.canonical.get(image_index).copied().unwrap_or(image_index)
+    }
+
+    /// Returns the number of distinct blocks.
+    pub fn unique_count(&self) -> usize {
+        self.canonical.iter().enumerate().filter(|(index, canonical)| index == *canonical).count()
+    }
+
+    /// Returns the number of blocks that were compared.
+    pub fn total_count(&self) -> usize {
+        self.canonical.len()
+    }
+}
+
+/// Finds the image blocks that hold identical data. Blocks are grouped by a hash
+/// of their contents and compared byte for byte, so a collision never merges
+/// two different blocks.
+///
+/// ### Parameters
+/// - `header`: The header of the file.
+/// - `bgst_contents`: The raw data of the file.
+///
+/// ### Returns
+/// - the canonical block of every image index
+pub fn dedup_blocks(header: &Header, bgst_contents: &[u8]) -> Result<BlockDedup> {
+    let mut by_hash: HashMap<u64, Vec<usize>> = HashMap::new();
+    let mut canonical = Vec::with_capacity(header.image_count as usize);
+
+    for image_index in 0..header.image_count as usize {
+        let block = header.image_block(bgst_contents, image_index)?;
+        let mut hasher = DefaultHasher::new();
+        block.hash(&mut hasher);
+
+        let candidates = by_hash.entry(hasher.finish()).or_default();
+        let mut matching = None;
+        for &candidate in candidates.iter() {
+            if header.image_block(bgst_contents, candidate)? == block {
+                matching = Some(candidate);
+                break;
+            }
+        }
+
+        if matching.is_none() {
+            candidates.push(image_index);
+        }
+        canonical.push(matching.unwrap_or(image_index));
+    }
+
+    Ok(BlockDedup { canonical })
+}
+
+/// What was written by `write_unique_images`.
+///
+/// ### Fields
+/// - `dedup`: Which image blocks of the file hold identical data.
+/// - `dangling_references`: The image indices that were skipped because they were out of range.
+pub struct UniqueImages {
+    pub dedup: BlockDedup,
+    pub dangling_references: Vec<DanglingReference>,
+}
+
+/// Decodes and writes every distinct image used by the selected entries once,
+/// as `img_<index>.png` or `img_<index>_mask.png`, followed by a `metadata.json` manifest.
+/// Image indices whose blocks are byte-identical to an earlier one, decoded
+/// in the same format, are written under the earlier index. Masks are not applied.
+/// The manifest lists every entry of the file, so it can be rebuilt even if some weren't extracted,
+/// but only the images that were extracted are restored. Entries on disabled layers are only
+/// extracted if the scene filter includes them, so select `SceneFilter::All` to repack every image.
+///
+/// ### Parameters
+/// - `bgst_contents`: The raw data of a bgst3 file.
+/// - `options`: Options that control which images are decoded and what is written.
+/// - `folder_name`: The existing folder to write the images into.
+///
+/// ### Returns
+/// - the identical blocks that were found, and the image indices that were skipped
+pub fn write_unique_images(
+    bgst_contents: &[u8],
+    options: &ExtractOptions,
+    folder_name: &str
+) -> Result<UniqueImages> {
+    let (header, selected, dangling_references) = select_entries(bgst_contents, &options.decode)?;
+
+    let dedup = dedup_blocks(&header, bgst_contents)?;
+
+    let writes = |image_index: i16, is_mask: bool| {
+        header.has_image(image_index) && (is_mask || !options.decode.masks_only)
+    };
+    // identical blocks only decode the same way in the same format
+    let canonical = |image_index: i16, is_mask: bool| {
+        let formats = &options.decode.overrides;
+        let first = dedup.canonical_index(image_index as usize);
+        if formats.format_for(first, is_mask) == formats.format_for(image_index as usize, is_mask) {
+            first as i16
+        } else {
+            image_index
+        }
+    };
+    let image_name = |image_index: i16, is_mask: bool| {
+        writes(image_index, is_mask).then(|| unique_image_name(canonical(image_index, is_mask), is_mask))
+    };
+
+    let extension = match options.format {
+        OutputFormat::Png => "png",
+        OutputFormat::Dds => "dds",
+    };
+
+    // the first entry to use an image decodes it, and decides what its block holds
+    let mut written = BTreeSet::new();
+    let mut images = BTreeMap::new();
+    for (entry_index, entry) in &selected {
+        for (image_index, is_mask) in [(entry.main_image_index, false), (entry.mask_image_index, true)] {
+            if !writes(image_index, is_mask) {
+                continue;
+            }
+
+            let format = options.decode.overrides.format_for(image_index as usize, is_mask);
+            let name = unique_image_name(canonical(image_index, is_mask), is_mask);
+            images.entry(image_index).or_insert_with(|| ManifestImage {
+                image_index,
+                format: format!("{:?}", format),
+                filename: format!("{}.{}", name, extension),
+            });
+
+            let image_index = canonical(image_index, is_mask);
+            if written.insert((image_index, is_mask)) {
+                let decoded = decode_block(&header, bgst_contents, *entry_index, image_index, format, is_mask)?;
+
+                if let Some(img) = RgbaImage::from_raw(header.image_width, header.image_height, decoded) {
+                    write_tile(folder_name, &name, &img, is_mask, options)?;
+                }
+            }
+        }
+    }
+
+    // entries are recorded as they are stored, before any blank index is removed
+    let names: HashMap<usize, (Option<String>, Option<String>)> = selected
+        .iter()
+        .map(|(entry_index, entry)| {
+            (*entry_index, (image_name(entry.main_image_index, false), image_name(entry.mask_image_index, true)))
+        })
+        .collect();
+    let all_entries = Bgst::parse_unchecked_with_stride(bgst_contents, header.entry_stride)?.grid_entries;
+    let entries = all_entries
+        .iter()
+        .enumerate()
+        .map(|(entry_index, entry)| {
+            let (main_image, mask_image) = names.get(&entry_index).cloned().unwrap_or_default();
+            ManifestEntry {
+                entry_index,
+                enabled: entry.enabled,
+                scene_index: entry.scene_index,
+                grid_x: entry.grid_x,
+                grid_y: entry.grid_y,
+                main_image_index: entry.main_image_index,
+                mask_image_index: entry.mask_image_index,
+                main_image,
+                mask_image,
+                unk_c: entry._unk_c,
+                unk_e: entry._unk_e,
+                normalized_x: normalized(entry.grid_x, header.grid_width),
+                normalized_y: normalized(entry.grid_y, header.grid_height),
+                pixel_x: entry.grid_x as i64 * header.image_width as i64,
+                pixel_y: entry.grid_y as i64 * header.image_height as i64,
+                scene_name: SceneIndex::name_of(entry.scene_index),
+            }
+        })
+        .collect();
+
+    // the scenes are measured from the extracted entries, with any dimension overrides applied
+    let bgst = Bgst {
+        header,
+        grid_entries: selected.into_iter().map(|(_, entry)| entry).collect(),
+    };
+    let scenes = scene_bounds(&bgst);
+    let header = bgst.header();
+
+    let manifest = Manifest {
+        version: MANIFEST_VERSION,
+        unk_4: header._unk_4,
+        image_width: header.image_width,
+        image_height: header.image_height,
+        grid_width: header.grid_width,
+        grid_height: header.grid_height,
+        image_count: header.image_count,
+        layer_enabled: header._layer_enabled,
+        reserved: header.header_tail(),
+        block_size: header.block_size(),
+        entry_stride: header.entry_stride(),
+        entries,
+        images: images.into_values().collect(),
+        scenes,
+    };
+
+    manifest.save(&Path::new(folder_name).join(METADATA_FILENAME))?;
+
+    Ok(UniqueImages { dedup, dangling_references })
+}
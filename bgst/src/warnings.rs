@@ -0,0 +1,224 @@
+//! Non-fatal problems found in a file, collected into a list
+//! so they can be shown together instead of being printed.
+
+use anyhow::Result;
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+use serde::Serialize;
+use crate::{
+    Bgst, DecodeOptions, ImageList, SceneIndex, dangling_references, decode_masked_entry, entry_count,
+    entry_table_padding, get_raw_images_with, select_entries
+};
+
+/// The kinds of problems a file can have that don't stop it from being decoded.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize)]
+pub enum WarningKind {
+    /// An entry references an image beyond the file's image count.
+    DanglingReference,
+    /// Two placed entries share the same cell of the same scene.
+    Overlap,
+    /// An entry is on a scene whose layer the header marks as disabled.
+    DisabledLayer,
+    /// An entry references an image block that is entirely zeroes.
+    EmptyBlock,
+    /// An entry's tile is fully transparent once its mask is applied.
+    FullyMasked,
+    /// The entry table doesn't end on a whole entry, so its last bytes are ignored.
+    PaddedEntryTable,
+}
+
+/// A non-fatal problem found in a file.
+///
+/// ### Fields
+/// - `kind`: The kind of problem.
+/// - `entry_index`: The entry the problem was found in.
+/// - `image_index`: The image the problem concerns, if any.
+/// - `message`: A description of the problem.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Warning {
+    pub kind: WarningKind,
+    pub entry_index: usize,
+    pub image_index: Option<i16>,
+    pub message: String,
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+/// Decoded images along with the problems found in their file.
+///
+/// ### Fields
+/// - `images`: The decoded images.
+/// - `warnings`: Every problem found in the file, including in entries that weren't decoded.
+pub struct ParseOutcome {
+    pub images: ImageList,
+    pub warnings: Vec<Warning>,
+}
+
+/// Finds every non-fatal problem in a file, in entry order.
+///
+/// ### Parameters
+/// - `bgst`: The parsed BGST file.
+/// - `bgst_contents`: The raw data of the file.
+///
+/// ### Returns
+/// - the problems that were found
+pub fn collect_warnings(bgst: &Bgst, bgst_contents: &[u8]) -> Vec<Warning> {
+    let header = bgst.header();
+    let mut warnings = Vec::new();
+
+    let mut dangling = dangling_references(bgst).into_iter().peekable();
+    let mut placed: HashMap<(i16, i16, i16), usize> = HashMap::new();
+    let mut checked_blocks = BTreeSet::new();
+
+    for (entry_index, entry) in bgst.grid_entries().iter().enumerate() {
+        while let Some(reference) = dangling.next_if(|reference| reference.entry_index == entry_index) {
+            warnings.push(Warning {
+                kind: WarningKind::DanglingReference,
+                entry_index,
+                image_index: Some(reference.image_index),
+                message: format!(
+                    "entry {} references {} image {}, which is out of range",
+                    entry_index,
+                    if reference.is_mask { "mask" } else { "main" },
+                    reference.image_index
+                ),
+            });
+        }
+
+        if entry.grid_x >= 0 && entry.grid_y >= 0 {
+            let cell = (entry.scene_index, entry.grid_x, entry.grid_y);
+            if let Some(first) = placed.get(&cell) {
+                warnings.push(Warning {
+                    kind: WarningKind::Overlap,
+                    entry_index,
+                    image_index: None,
+                    message: format!(
+                        "entry {} is placed on cell ({}, {}) of {}, the same as entry {}",
+                        entry_index,
+                        entry.grid_x,
+                        entry.grid_y,
+                        SceneIndex::name_of(entry.scene_index),
+                        first
+                    ),
+                });
+            } else {
+                placed.insert(cell, entry_index);
+            }
+        }
+
+        if SceneIndex::from_index(entry.scene_index).is_some_and(|scene| !header.layer_enabled(scene)) {
+            warnings.push(Warning {
+                kind: WarningKind::DisabledLayer,
+                entry_index,
+                image_index: None,
+                message: format!(
+                    "entry {} is on {}, whose layer is disabled",
+                    entry_index,
+                    SceneIndex::name_of(entry.scene_index)
+                ),
+            });
+        }
+
+        for image_index in [entry.main_image_index, entry.mask_image_index] {
+            if !header.has_image(image_index) || !checked_blocks.insert(image_index) {
+                continue;
+            }
+
+            // truncated files are reported as errors when they are decoded
+            let Ok(block) = header.image_block(bgst_contents, image_index as usize) else {
+                continue;
+            };
+
+            if block.iter().all(|byte| *byte == 0) {
+                warnings.push(Warning {
+                    kind: WarningKind::EmptyBlock,
+                    entry_index,
+                    image_index: Some(image_index),
+                    message: format!("entry {} references image {}, which is empty", entry_index, image_index),
+                });
+            }
+        }
+    }
+
+    let padding = entry_table_padding(header);
+    if padding != 0 {
+        warnings.push(Warning {
+            kind: WarningKind::PaddedEntryTable,
+            entry_index: entry_count(header),
+            image_index: None,
+            message: format!(
+                "the entry table is followed by {} bytes that don't make up a whole entry, which are ignored",
+                padding
+            ),
+        });
+    }
+
+    warnings
+}
+
+/// Decodes and masks every selected entry that has both a main and a mask image,
+/// and reports the tiles that end up fully transparent. This usually means the
+/// mask doesn't belong to the image, so it is checked separately from `collect_warnings`,
+/// which doesn't decode anything.
+///
+/// ### Parameters
+/// - `bgst_contents`: The raw data of a bgst3 file.
+/// - `options`: Options that control which images are decoded.
+///
+/// ### Returns
+/// - a warning for every fully transparent tile
+pub fn check_masked_tiles(bgst_contents: &[u8], options: &DecodeOptions) -> Result<Vec<Warning>> {
+    let (header, selected, _) = select_entries(bgst_contents, options)?;
+    let mut warnings = Vec::new();
+
+    for (entry_index, entry) in &selected {
+        if !header.has_image(entry.mask_image_index) {
+            continue;
+        }
+
+        let Some(tile) = decode_masked_entry(&header, bgst_contents, *entry_index, entry, &options.overrides)? else {
+            continue;
+        };
+
+        if tile.chunks_exact(4).all(|pixel| pixel[3] == 0) {
+            warnings.push(Warning {
+                kind: WarningKind::FullyMasked,
+                entry_index: *entry_index,
+                image_index: Some(entry.mask_image_index),
+                message: format!(
+                    "entry {} is fully transparent once mask image {} is applied",
+                    entry_index,
+                    entry.mask_image_index
+                ),
+            });
+        }
+    }
+
+    Ok(warnings)
+}
+
+/// Decodes the images selected by the given options and collects
+/// every problem found in the file.
+///
+/// ### Parameters
+/// - `bgst_contents`: The raw data of a bgst3 file.
+/// - `options`: Options that control which images are decoded.
+///
+/// ### Returns
+/// - the decoded images and the problems found
+pub fn get_raw_images_with_warnings(
+    bgst_contents: &[u8],
+    options: &DecodeOptions
+) -> Result<ParseOutcome> {
+    let images = get_raw_images_with(bgst_contents, options)?;
+    let bgst = Bgst::parse_unchecked(bgst_contents)?;
+
+    Ok(ParseOutcome {
+        images,
+        warnings: collect_warnings(&bgst, bgst_contents),
+    })
+}
@@ -0,0 +1,95 @@
+//! Decompression of Yaz0, the LZ77 variant Nintendo uses to compress files inside archives.
+//! Files are checked for the `Yaz0` magic, so uncompressed files pass through unchanged.
+
+use anyhow::{Result, bail};
+use byteorder::{ByteOrder, BigEndian};
+use crate::BgstError;
+
+/// The size of the Yaz0 header: the magic, the decompressed size and 8 reserved bytes.
+const YAZ0_HEADER_SIZE: usize = 0x10;
+
+/// How many times the size of the compressed data is reserved for the output at most.
+/// Files compress far better than this only rarely, and the output grows past it as needed.
+const MAX_RESERVED_RATIO: usize = 8;
+
+/// Returns if data starts with the Yaz0 magic.
+///
+/// ### Parameters
+/// - `data`: The data to check.
+pub fn is_compressed(data: &[u8]) -> bool {
+    data.starts_with(b"Yaz0")
+}
+
+/// Decompresses Yaz0 data.
+///
+/// ### Parameters
+/// - `data`: The compressed data, starting with its header.
+///
+/// ### Returns
+/// - the decompressed data
+pub fn decompress(data: &[u8]) -> Result<Vec<u8>> {
+    if !is_compressed(data) || data.len() < YAZ0_HEADER_SIZE {
+        bail!(BgstError::InvalidFile("data is not Yaz0 compressed".to_string()));
+    }
+
+    let truncated = || BgstError::InvalidFile("Yaz0 data ends before it is fully decompressed".to_string());
+
+    let size = BigEndian::read_u32(&data[4..8]) as usize;
+    // the size comes from the file, so only a plausible amount is reserved up front
+    let mut output = Vec::with_capacity(size.min(data.len().saturating_mul(MAX_RESERVED_RATIO)));
+    let mut input = data[YAZ0_HEADER_SIZE..].iter().copied();
+
+    while output.len() < size {
+        let code = input.next().ok_or_else(truncated)?;
+
+        for bit in (0..8).rev() {
+            if output.len() >= size {
+                break;
+            }
+
+            if code & (1 << bit) != 0 {
+                output.push(input.next().ok_or_else(truncated)?);
+                continue;
+            }
+
+            let first = input.next().ok_or_else(truncated)? as usize;
+            let second = input.next().ok_or_else(truncated)? as usize;
+            let distance = ((first & 0xF) << 8 | second) + 1;
+            let length = match first >> 4 {
+                0 => input.next().ok_or_else(truncated)? as usize + 0x12,
+                length => length + 2,
+            };
+
+            if distance > output.len() {
+                bail!(BgstError::InvalidFile(format!(
+                    "Yaz0 data refers {} bytes back, but only {} bytes have been decompressed",
+                    distance,
+                    output.len()
+                )));
+            }
+
+            // copied one byte at a time, since a copy may overlap what it writes
+            let start = output.len() - distance;
+            for i in 0..length.min(size - output.len()) {
+                output.push(output[start + i]);
+            }
+        }
+    }
+
+    Ok(output)
+}
+
+/// Decompresses data if it starts with the Yaz0 magic, and returns it unchanged otherwise.
+///
+/// ### Parameters
+/// - `data`: The data, which may be compressed.
+///
+/// ### Returns
+/// - the uncompressed data
+pub fn decompress_if_compressed(data: Vec<u8>) -> Result<Vec<u8>> {
+    if is_compressed(&data) {
+        decompress(&data)
+    } else {
+        Ok(data)
+    }
+}
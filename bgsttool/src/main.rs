@@ -1,23 +1,633 @@
+use std::collections::HashMap;
 use std::env;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
 use anyhow::{Result, bail};
-use bgst;
+use bgst::{
+    BgstError, DecodeOptions, DimensionLimits, ExtractOptions, FilmstripDirection, GridRect, OutputFormat,
+    SceneFilter, SceneIndex
+};
+use bgst::overrides::FormatConfig;
+use bgst::swizzle::TileLayout;
+use image::ImageFormat;
+use rayon::prelude::*;
 
-fn main() -> Result<()> {
-    let args: Vec<String> = env::args().collect();
+/// An error in the arguments given to the tool.
+#[derive(Debug)]
+struct UsageError(String);
+
+impl fmt::Display for UsageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for UsageError {}
+
+/// Maps an error to the exit code for its category.
+/// - 2: bad arguments
+/// - 3: file not found
+/// - 4: invalid BGST file
+/// - 5: decode failure
+/// - 6: write failure
+fn exit_code(error: &anyhow::Error) -> u8 {
+    for cause in error.chain() {
+        if cause.is::<UsageError>() {
+            return 2;
+        }
+
+        if let Some(error) = cause.downcast_ref::<BgstError>() {
+            return match error {
+                BgstError::FileNotFound(_) => 3,
+                BgstError::InvalidFile(_) => 4,
+                BgstError::Decode(_) => 5,
+                BgstError::Write(_) => 6,
+            };
+        }
+    }
+
+    1
+}
+
+/// Parses a grid range given as `x,y,w,h`.
+fn parse_region(value: &str) -> Result<GridRect> {
+    let parts = value
+        .split(',')
+        .map(|part| part.trim().parse::<i16>())
+        .collect::<Result<Vec<i16>, _>>()
+        .map_err(|_| UsageError(format!("invalid region {}", value)))?;
+
+    if parts.len() != 4 {
+        bail!(UsageError("region must be given as x,y,w,h".to_string()));
+    }
+
+    Ok(GridRect {
+        x: parts[0],
+        y: parts[1],
+        width: parts[2],
+        height: parts[3],
+    })
+}
+
+/// Sets the largest image dimension, used for both the width and the height.
+fn set_max_dimension(limits: &mut DimensionLimits, value: &str) -> Result<()> {
+    let max = value
+        .parse::<u32>()
+        .map_err(|_| UsageError(format!("invalid dimension {}", value)))?;
+
+    limits.max_image_width = max;
+    limits.max_image_height = max;
+    Ok(())
+}
+
+/// Parses how image blocks are laid out: `tiled`, `linear` or `detect`.
+fn parse_layout(value: &str) -> Result<TileLayout> {
+    bgst::swizzle::parse_layout(value).map_err(|_| UsageError(format!("unknown layout {}", value)).into())
+}
+
+/// Parses the largest number of pixels a composited image may have.
+fn parse_canvas_pixels(value: &str) -> Result<u64> {
+    value
+        .parse::<u64>()
+        .map_err(|_| UsageError(format!("invalid pixel count {}", value)).into())
+}
+
+/// Parses the name of an output format.
+fn parse_format(value: &str) -> Result<OutputFormat> {
+    match value {
+        "png" => Ok(OutputFormat::Png),
+        "dds" => Ok(OutputFormat::Dds),
+        _ => bail!(UsageError(format!("unknown output format {}", value))),
+    }
+}
+
+/// Sets the format a scene is written in, given as `scene=extension`, such as `Far01=webp`.
+fn add_scene_format(formats: &mut HashMap<SceneIndex, ImageFormat>, value: &str) -> Result<()> {
+    let Some((scene, extension)) = value.split_once('=') else {
+        bail!(UsageError("scene formats must be given as scene=format".to_string()));
+    };
+
+    let scene = SceneIndex::from_name(scene)
+        .ok_or_else(|| UsageError(format!("unknown scene {}", scene)))?;
+    let format = ImageFormat::from_extension(extension)
+        .filter(ImageFormat::can_write)
+        .ok_or_else(|| UsageError(format!("unknown image format {}", extension)))?;
+
+    formats.insert(scene, format);
+    Ok(())
+}
+
+/// Parses the direction of a filmstrip.
+fn parse_direction(value: &str) -> Result<FilmstripDirection> {
+    match value {
+        "vertical" => Ok(FilmstripDirection::Vertical),
+        "horizontal" => Ok(FilmstripDirection::Horizontal),
+        _ => bail!(UsageError(format!("unknown filmstrip direction {}", value))),
+    }
+}
+
+/// Adds a scene, given by name or index, to the scenes to decode.
+/// The first scene given replaces the default of every enabled layer.
+fn add_scene(scenes: &mut SceneFilter, value: &str) -> Result<()> {
+    let scene = SceneIndex::from_name(value)
+        .or_else(|| value.parse::<i16>().ok().and_then(SceneIndex::from_index))
+        .ok_or_else(|| UsageError(format!("unknown scene {}", value)))?;
+
+    match scenes {
+        SceneFilter::Only(list) => list.push(scene),
+        _ => *scenes = SceneFilter::Only(vec![scene]),
+    }
+
+    Ok(())
+}
+
+/// Parses a block size in bytes, given in decimal or as hexadecimal with a `0x` prefix.
+fn parse_block_size(value: &str) -> Result<usize> {
+    let parsed = match value.strip_prefix("0x") {
+        Some(hex) => usize::from_str_radix(hex, 16),
+        None => value.parse::<usize>(),
+    };
+
+    parsed
+        .ok()
+        .filter(|size| *size > 0)
+        .ok_or_else(|| UsageError(format!("invalid block size {}", value)).into())
+}
+
+/// Parses the distance between grid entries in bytes, given like a block size.
+fn parse_entry_stride(value: &str) -> Result<usize> {
+    parse_block_size(value)
+        .ok()
+        .filter(|stride| *stride >= bgst::GRID_ENTRY_SIZE)
+        .ok_or_else(|| UsageError(format!("invalid entry stride {}", value)).into())
+}
+
+/// Parses an image index, such as the placeholder given to `--blank-index`.
+fn parse_image_index(value: &str) -> Result<i16> {
+    value
+        .parse::<i16>()
+        .ok()
+        .filter(|index| *index >= 0)
+        .ok_or_else(|| UsageError(format!("invalid image index {}", value)).into())
+}
+
+/// Parses the largest number of images to decode.
+fn parse_max_images(value: &str) -> Result<usize> {
+    value
+        .parse::<usize>()
+        .map_err(|_| UsageError(format!("invalid image count {}", value)).into())
+}
+
+/// Includes every disabled entry: those that aren't enabled, and those on scenes whose
+/// layer the header disables. Scenes chosen with `--scene` are kept as they are.
+fn include_disabled(options: &mut DecodeOptions) {
+    options.skip_disabled = false;
+    if options.scenes == SceneFilter::EnabledLayers {
+        options.scenes = SceneFilter::All;
+    }
+}
+
+/// Parses the number of threads to extract with.
+fn parse_threads(value: &str) -> Result<usize> {
+    value
+        .parse::<usize>()
+        .ok()
+        .filter(|threads| *threads > 0)
+        .ok_or_else(|| UsageError(format!("invalid thread count {}", value)).into())
+}
+
+/// Returns the value following a flag.
+fn flag_value<'a>(rest: &mut impl Iterator<Item = &'a String>, flag: &str) -> Result<&'a String> {
+    let Some(value) = rest.next() else {
+        bail!(UsageError(format!("{} requires a value", flag)));
+    };
+
+    Ok(value)
+}
+
+/// Applies a flag that controls which images are decoded and how,
+/// returning `false` if the flag is something else.
+fn decode_flag<'a>(options: &mut DecodeOptions, arg: &str, rest: &mut impl Iterator<Item = &'a String>) -> Result<bool> {
+    match arg {
+        "--skip-disabled" => options.skip_disabled = true,
+        "--include-disabled" => include_disabled(options),
+        "--region" => options.region = Some(parse_region(flag_value(rest, arg)?)?),
+        "--max-dimension" => set_max_dimension(&mut options.limits, flag_value(rest, arg)?)?,
+        "--max-canvas-pixels" => options.limits.max_canvas_pixels = parse_canvas_pixels(flag_value(rest, arg)?)?,
+        "--all-scenes" => options.scenes = SceneFilter::All,
+        "--masks-only" => options.masks_only = true,
+        "--repair" => options.repair = true,
+        "--blank-index" => options.blank_index = Some(parse_image_index(flag_value(rest, arg)?)?),
+        "--max-images" => options.max_images = Some(parse_max_images(flag_value(rest, arg)?)?),
+        "--block-size" => options.overrides.block_size = Some(parse_block_size(flag_value(rest, arg)?)?),
+        "--entry-stride" => options.overrides.entry_stride = Some(parse_entry_stride(flag_value(rest, arg)?)?),
+        "--layout" => options.overrides.layout = Some(parse_layout(flag_value(rest, arg)?)?),
+        "--scene" => add_scene(&mut options.scenes, flag_value(rest, arg)?)?,
+        _ => return Ok(false),
+    }
+
+    Ok(true)
+}
+
+/// Reads an input file, reporting a missing file as such.
+fn read_input(filename: &str) -> Result<Vec<u8>> {
+    bgst::read_source(filename)
+}
+
+/// The kinds of output extraction can produce.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum ExtractOutput {
+    Folder,
+    Ora,
+    Tmx,
+    AtlasJson,
+    Tiff,
+    Filmstrip(FilmstripDirection),
+    Scenes,
+}
+
+/// Returns where a file is extracted to for the requested output.
+fn output_path(filename: &str, output: ExtractOutput) -> PathBuf {
+    let path = Path::new(filename);
+
+    match output {
+        ExtractOutput::Folder => PathBuf::from(bgst::output_folder_name(filename)),
+        ExtractOutput::Ora => path.with_extension("ora"),
+        ExtractOutput::Tmx => path.with_extension("tmx"),
+        ExtractOutput::AtlasJson => path.with_extension("atlas.json"),
+        ExtractOutput::Tiff => path.with_extension("tiff"),
+        ExtractOutput::Scenes => path.with_extension("scenes"),
+        ExtractOutput::Filmstrip(_) => path.with_extension("filmstrip.png"),
+    }
+}
+
+/// Fails if two of the files would be extracted to the same place,
+/// as the one extracted last would replace the other.
+fn check_output_paths(filenames: &[&String], output: ExtractOutput) -> Result<()> {
+    // paths that don't resolve are compared as they were given
+    let absolute = |path: &Path| std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+    let mut outputs: HashMap<PathBuf, &String> = HashMap::new();
+
+    for filename in filenames {
+        let output_path = absolute(&output_path(filename, output));
+        let Some(other) = outputs.insert(output_path.clone(), filename) else {
+            continue;
+        };
+
+        if absolute(Path::new(other)) == absolute(Path::new(filename)) {
+            bail!(UsageError(format!("{} is given more than once", filename)));
+        }
+
+        bail!(UsageError(format!(
+            "{} and {} would both be extracted to {}",
+            other, filename, output_path.display()
+        )));
+    }
+
+    Ok(())
+}
+
+/// Extracts one file to the requested output.
+fn extract_file(
+    filename: &str,
+    options: &ExtractOptions,
+    output: ExtractOutput,
+    scene_formats: &HashMap<SceneIndex, ImageFormat>
+) -> Result<()> {
+    if output == ExtractOutput::Folder {
+        return bgst::extract_bgst_with(filename, options);
+    }
+
+    let file_contents = read_input(filename)?;
+    let images = bgst::get_raw_images_with(&file_contents, &options.decode)?;
+    let output_filename = output_path(filename, output);
+    let output_name = output_filename.to_string_lossy();
+
+    match output {
+        ExtractOutput::Ora => bgst::ora::export_ora(&images, &output_name),
+        ExtractOutput::Tmx => bgst::tmx::export_tmx(&images, &output_name),
+        ExtractOutput::AtlasJson => bgst::atlas::export_atlas_json(&images, &output_name),
+        ExtractOutput::Tiff => bgst::tiff::export_tiff(&images, &output_name),
+        ExtractOutput::Scenes => bgst::export_scenes(&images, &output_name, scene_formats),
+        ExtractOutput::Filmstrip(direction) => {
+            let Some(strip) = bgst::filmstrip(&images, direction)? else {
+                bail!("no entries have a main image to put in the filmstrip");
+            };
 
+            strip.save(&output_filename)
+                .map_err(|err| BgstError::Write(format!("failed to write {}: {}", output_filename.display(), err)))?;
+            Ok(())
+        }
+        ExtractOutput::Folder => unreachable!("folders are extracted before the images are decoded"),
+    }
+}
+
+/// `bgsttool <file>... [mask] [options]`
+///
+/// Every file is extracted with the same options, one after another, or up to
+/// `--threads N` at once. Files that would be extracted to the same place are refused.
+/// When several are given, a failure doesn't stop the rest,
+/// and a summary is printed at the end.
+fn run_extract(args: &[String]) -> Result<()> {
+    let mut filenames = Vec::new();
+    let mut config = None;
+    let mut output = ExtractOutput::Folder;
+    let mut threads = None;
+    let mut scene_formats = HashMap::new();
+    let mut options = ExtractOptions::default();
+
+    let mut rest = args.iter();
+    while let Some(arg) = rest.next() {
+        match arg.as_str() {
+            "mask" => options.do_mask = true,
+            "--alpha" => options.write_alpha = true,
+            "--ora" => output = ExtractOutput::Ora,
+            "--tmx" => output = ExtractOutput::Tmx,
+            "--atlas-json" => output = ExtractOutput::AtlasJson,
+            // scene composites are written without any of the tiles they're made from
+            "--scenes" | "--composite-only" => output = ExtractOutput::Scenes,
+            "--scene-format" => add_scene_format(&mut scene_formats, flag_value(&mut rest, arg)?)?,
+            "--filmstrip" => output = ExtractOutput::Filmstrip(parse_direction(flag_value(&mut rest, arg)?)?),
+            "--unique" => options.unique_images = true,
+            "--indexed" => options.indexed = true,
+            "--flatten" => options.flatten = true,
+            "--entry-names" => options.entry_names = true,
+            "--check-masks" => options.check_masks = true,
+            "--annotate" => options.annotate = true,
+            "--strip-alpha" => options.strip_alpha = true,
+            "--flip-v" => options.decode.flip_v = true,
+            "--verbose" => options.verbose = true,
+            "--hex-dump" => options.hex_dump = true,
+            "--embed-meta" => options.embed_meta = true,
+            "--apng" => options.apng = true,
+            "--format" => match flag_value(&mut rest, arg)?.as_str() {
+                // a TIFF holds every tile, so it's written as one file instead of a folder
+                "tiff" => output = ExtractOutput::Tiff,
+                value => options.format = parse_format(value)?,
+            },
+            "--config" => config = Some(FormatConfig::load(Path::new(flag_value(&mut rest, arg)?))?),
+            "--threads" => threads = Some(parse_threads(flag_value(&mut rest, arg)?)?),
+            _ if decode_flag(&mut options.decode, arg, &mut rest)? => {}
+            _ if arg.starts_with("--") => bail!(UsageError(format!("unknown argument {}", arg))),
+            _ => filenames.push(arg),
+        }
+    }
+
+    if filenames.is_empty() {
+        bail!(UsageError("no input files given".to_string()));
+    }
+
+    // unique images are written without any metadata
+    if options.embed_meta && options.unique_images {
+        bail!(UsageError("--embed-meta can't be used with --unique".to_string()));
+    }
+
+    // APNGs are only ever written as they are
+    if options.apng {
+        let ignored = [
+            (options.format == OutputFormat::Dds, "--format dds"),
+            (options.write_alpha, "--alpha"),
+            (options.annotate, "--annotate"),
+            (options.embed_meta, "--embed-meta"),
+            (options.flatten, "--flatten"),
+        ];
+        if let Some((_, flag)) = ignored.iter().find(|(given, _)| *given) {
+            bail!(UsageError(format!("{} can't be used with --apng", flag)));
+        }
+    }
+
+    check_output_paths(&filenames, output)?;
+
+    let extract = |filename: &String| {
+        // overrides given as flags take precedence over the config
+        let mut file_options = options.clone();
+        if let Some(config) = &config {
+            file_options.decode.overrides = config.overrides_for(filename).merged_with(&options.decode.overrides);
+        }
+
+        extract_file(filename, &file_options, output, &scene_formats)
+    };
+
+    // the progress of files extracted at once interleaves, so they're only run in parallel when asked
+    let results: Vec<Result<()>> = match threads {
+        Some(threads) => {
+            if filenames.len() == 1 {
+                eprintln!("warning: --threads has no effect with a single file");
+            }
+
+            bgst::with_thread_limit(threads, || filenames.par_iter().map(|filename| extract(filename)).collect())?
+        }
+        None => filenames.iter().map(|filename| extract(filename)).collect(),
+    };
+
+    if filenames.len() == 1 {
+        return results.into_iter().next().unwrap_or(Ok(()));
+    }
+
+    let mut failures = Vec::new();
+    for (filename, result) in filenames.iter().zip(results) {
+        if let Err(error) = result {
+            eprintln!("Error: {}: {:#}", filename, error);
+            failures.push((filename, error));
+        }
+    }
+
+    println!("extracted {} of {} files", filenames.len() - failures.len(), filenames.len());
+    for (filename, _) in &failures {
+        println!("  failed: {}", filename);
+    }
+
+    let failed = failures.len();
+    match failures.into_iter().next() {
+        // the first failure decides the exit code
+        Some((_, error)) => Err(error.context(format!("{} of {} files failed", failed, filenames.len()))),
+        None => Ok(()),
+    }
+}
+
+/// `bgsttool preview <file> <output.gif> [--fps N] [options]`
+fn run_preview(args: &[String]) -> Result<()> {
     if args.len() < 2 {
-        bail!("incorrect argument count");
+        bail!(UsageError("preview requires an input file and an output file".to_string()));
+    }
+
+    let filename = &args[0];
+    let output_filename = &args[1];
+
+    let mut fps = 2;
+    let mut config = None;
+    let mut options = DecodeOptions::default();
+
+    let mut rest = args[2..].iter();
+    while let Some(arg) = rest.next() {
+        match arg.as_str() {
+            "--fps" => {
+                let value = flag_value(&mut rest, arg)?;
+                fps = value
+                    .parse::<u32>()
+                    .ok()
+                    .filter(|fps| *fps > 0)
+                    .ok_or_else(|| UsageError(format!("invalid frame rate {}", value)))?;
+            }
+            "--config" => config = Some(FormatConfig::load(Path::new(flag_value(&mut rest, arg)?))?),
+            _ if decode_flag(&mut options, arg, &mut rest)? => {}
+            _ => bail!(UsageError(format!("unknown argument {}", arg))),
+        }
+    }
+
+    // overrides given as flags take precedence over the config, wherever it's given
+    if let Some(config) = &config {
+        options.overrides = config.overrides_for(filename).merged_with(&options.overrides);
+    }
+
+    let file_contents = read_input(filename)?;
+    bgst::preview::export_gif_preview(&file_contents, &options, output_filename, fps)
+}
+
+/// `bgsttool csv <file>`
+fn run_csv(args: &[String]) -> Result<()> {
+    if args.len() != 1 {
+        bail!(UsageError("csv requires an input file".to_string()));
+    }
+
+    let file_contents = read_input(&args[0])?;
+    let bgst = bgst::Bgst::parse(&file_contents)?;
+    print!("{}", bgst::csv::entries_csv(&bgst));
+
+    Ok(())
+}
+
+/// `bgsttool jsonl <file>`
+fn run_jsonl(args: &[String]) -> Result<()> {
+    if args.len() != 1 {
+        bail!(UsageError("jsonl requires an input file".to_string()));
+    }
+
+    let file_contents = read_input(&args[0])?;
+    let bgst = bgst::Bgst::parse(&file_contents)?;
+    let result = bgst::jsonl::write_entries_jsonl(&bgst, std::io::stdout().lock());
+
+    // the reader stopping early, such as `head`, isn't an error
+    match result {
+        Err(error) if error
+            .downcast_ref::<std::io::Error>()
+            .is_some_and(|error| error.kind() == std::io::ErrorKind::BrokenPipe) => Ok(()),
+        result => result,
+    }
+}
+
+/// `bgsttool pack <folder> <output.bgst3>`
+fn run_pack(args: &[String]) -> Result<()> {
+    if args.len() != 2 {
+        bail!(UsageError("pack requires an input folder and an output file".to_string()));
+    }
+
+    let contents = bgst::pack::pack_bgst(Path::new(&args[0]))?;
+    std::fs::write(&args[1], contents)
+        .map_err(|err| BgstError::Write(format!("failed to write {}: {}", args[1], err)))?;
+
+    Ok(())
+}
+
+/// `bgsttool diff <original> <modified> <folder> [options]`
+///
+/// Both files are decoded with the same options, and a `--config` is matched by the original's name.
+fn run_diff(args: &[String]) -> Result<()> {
+    let mut paths = Vec::new();
+    let mut config = None;
+    let mut options = ExtractOptions::default();
+
+    let mut rest = args.iter();
+    while let Some(arg) = rest.next() {
+        match arg.as_str() {
+            "--config" => config = Some(FormatConfig::load(Path::new(flag_value(&mut rest, arg)?))?),
+            _ if decode_flag(&mut options.decode, arg, &mut rest)? => {}
+            _ if arg.starts_with("--") => bail!(UsageError(format!("unknown argument {}", arg))),
+            _ => paths.push(arg),
+        }
+    }
+
+    let [original, modified, folder_name] = paths[..] else {
+        bail!(UsageError("diff requires an original file, a modified file and an output folder".to_string()));
+    };
+
+    // overrides given as flags take precedence over the config, wherever it's given
+    if let Some(config) = &config {
+        options.decode.overrides = config.overrides_for(original).merged_with(&options.decode.overrides);
+    }
+
+    let original = read_input(original)?;
+    let modified = read_input(modified)?;
+
+    std::fs::create_dir_all(folder_name)
+        .map_err(|err| BgstError::Write(format!("failed to create folder {}: {}", folder_name, err)))?;
+
+    let changed = bgst::diff::write_changed_tiles(&original, &modified, &options, folder_name)?;
+    println!("{} tiles changed", changed.len());
+
+    Ok(())
+}
+
+/// `bgsttool inspect <file> [--json]`
+///
+/// Exits with an error if the file is truncated or has any warnings, so it can be used as a check.
+fn run_inspect(args: &[String]) -> Result<()> {
+    let mut filename = None;
+    let mut json = false;
+
+    for arg in args {
+        match arg.as_str() {
+            "--json" => json = true,
+            _ if arg.starts_with("--") => bail!(UsageError(format!("unknown argument {}", arg))),
+            _ if filename.is_none() => filename = Some(arg),
+            _ => bail!(UsageError("inspect takes one input file".to_string())),
+        }
     }
 
-    let filename = &args[1];
-    
-    let mut should_mask = false;
+    let Some(filename) = filename else {
+        bail!(UsageError("inspect requires an input file".to_string()));
+    };
 
-    if args.len() == 3 {
-        should_mask = &args[2] == "mask";
+    let file_contents = read_input(filename)?;
+    let report = bgst::report::inspect(&file_contents)?;
+    if json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    } else {
+        print!("{}", report);
     }
 
-    let result = bgst::extract_bgst(&filename, should_mask);
+    if !report.is_clean() {
+        bail!(BgstError::InvalidFile(format!("{} has problems", filename)));
+    }
+
+    Ok(())
+}
 
-    result
+fn run() -> Result<()> {
+    let args: Vec<String> = env::args().collect();
+
+    if args.len() < 2 {
+        bail!(UsageError("incorrect argument count".to_string()));
+    }
+
+    match args[1].as_str() {
+        "preview" => run_preview(&args[2..]),
+        "csv" => run_csv(&args[2..]),
+        "jsonl" => run_jsonl(&args[2..]),
+        "pack" => run_pack(&args[2..]),
+        "diff" => run_diff(&args[2..]),
+        "inspect" => run_inspect(&args[2..]),
+        _ => run_extract(&args[1..]),
+    }
+}
+
+fn main() -> ExitCode {
+    match run() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("Error: {:#}", error);
+            ExitCode::from(exit_code(&error))
+        }
+    }
 }